@@ -0,0 +1,455 @@
+//! Резервные копии данных приложения
+//!
+//! Архив zip: manifest.json (версия формата, причина, sha256 файлов) + JSON файлы
+//! каталога данных + согласованный снимок SQLite базы. Токены авторизации лежат
+//! отдельно (keychain / com.kripika.tli-companion) и в копию не попадают.
+//! Автоматические копии (перед миграцией базы, импортом, восстановлением)
+//! ротируются, ручные — нет.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::persistence;
+use crate::store::{Store, DB_FILE_NAME};
+use crate::types::{BackupInfo, BackupReason};
+
+const BACKUP_FORMAT_ID: &str = "tli-companion-backup";
+pub const BACKUP_VERSION: u32 = 1;
+const MANIFEST_NAME: &str = "manifest.json";
+const BACKUPS_DIR_NAME: &str = "backups";
+/// Сколько автоматических копий хранить
+const MAX_AUTO_BACKUPS: usize = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BackupManifest {
+    format: String,
+    version: u32,
+    app_version: String,
+    created_at: DateTime<Utc>,
+    reason: BackupReason,
+    #[serde(default)]
+    db_schema_version: Option<usize>,
+    files: Vec<BackupFile>,
+}
+
+/// Файлы архива: имя и содержимое
+type ArchiveFiles = Vec<(String, Vec<u8>)>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BackupFile {
+    name: String,
+    size: u64,
+    sha256: String,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Путь архива из IPC: только .zip, чтобы нельзя было читать и затирать произвольные файлы
+fn require_zip(path: &Path) -> io::Result<()> {
+    if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("zip")) {
+        Ok(())
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "Backup file must have a .zip extension"))
+    }
+}
+
+fn data_dir() -> io::Result<PathBuf> {
+    persistence::app_data_dir().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No local data directory"))
+}
+
+fn reason_slug(reason: BackupReason) -> &'static str {
+    match reason {
+        BackupReason::Manual => "manual",
+        BackupReason::BeforeMigration => "before_migration",
+        BackupReason::BeforeImport => "before_import",
+        BackupReason::BeforeRestore => "before_restore",
+    }
+}
+
+pub fn backups_dir() -> Option<PathBuf> {
+    persistence::app_data_dir().map(|d| d.join(BACKUPS_DIR_NAME))
+}
+
+/// JSON файл данных, который входит в копию (временные файлы и всё похожее на токены — нет)
+fn is_data_file(name: &str) -> bool {
+    name.ends_with(".json")
+        && !name.contains("token")
+        && !name.contains(['/', '\\'])
+        && !name.starts_with('.')
+}
+
+fn data_files(dir: &Path) -> io::Result<Vec<String>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if entry.file_type()?.is_file() && is_data_file(&name) {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Создать копию. `dest` — путь архива (None — каталог backups с ротацией автоматических копий).
+/// Без открытой базы файл базы копируется как есть.
+/// Существующий файл по пути `dest` заменяется, только если это тоже копия.
+pub(crate) fn create(reason: BackupReason, dest: Option<&Path>, store: Option<&Store>) -> io::Result<BackupInfo> {
+    create_in(&data_dir()?, reason, dest, store)
+}
+
+fn create_in(data_dir: &Path, reason: BackupReason, dest: Option<&Path>, store: Option<&Store>) -> io::Result<BackupInfo> {
+    let created_at = Utc::now();
+    let path = match dest {
+        Some(p) => {
+            require_zip(p)?;
+            if p.exists() {
+                open_manifest(p).map_err(|_| io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "Refusing to overwrite a file that is not a backup",
+                ))?;
+            }
+            p.to_path_buf()
+        }
+        None => {
+            let dir = data_dir.join(BACKUPS_DIR_NAME);
+            fs::create_dir_all(&dir)?;
+            dir.join(format!("backup-{}-{}.zip", created_at.format("%Y%m%d-%H%M%S%3f"), reason_slug(reason)))
+        }
+    };
+
+    let mut files: ArchiveFiles = Vec::new();
+    for name in data_files(data_dir)? {
+        files.push((name.clone(), fs::read(data_dir.join(&name))?));
+    }
+
+    let db_path = data_dir.join(DB_FILE_NAME);
+    let mut db_schema_version = None;
+    match store {
+        Some(store) => {
+            let snapshot = path.with_extension("db.tmp");
+            store.snapshot_to(&snapshot)?;
+            let data = fs::read(&snapshot);
+            let _ = fs::remove_file(&snapshot);
+            files.push((DB_FILE_NAME.to_string(), data?));
+            db_schema_version = Some(store.schema_version()?);
+        }
+        None if db_path.exists() => files.push((DB_FILE_NAME.to_string(), fs::read(&db_path)?)),
+        None => {}
+    }
+
+    let manifest = BackupManifest {
+        format: BACKUP_FORMAT_ID.to_string(),
+        version: BACKUP_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at,
+        reason,
+        db_schema_version,
+        files: files.iter()
+            .map(|(name, data)| BackupFile { name: name.clone(), size: data.len() as u64, sha256: sha256_hex(data) })
+            .collect(),
+    };
+
+    // Пишем во временный файл: оборванная запись не оставит битый архив под именем копии
+    let tmp = path.with_extension("zip.tmp");
+    {
+        let mut zip = ZipWriter::new(fs::File::create(&tmp)?);
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        zip.start_file(MANIFEST_NAME, options).map_err(io::Error::other)?;
+        zip.write_all(&serde_json::to_vec_pretty(&manifest).map_err(|e| invalid(e.to_string()))?)?;
+        for (name, data) in &files {
+            zip.start_file(name.as_str(), options).map_err(io::Error::other)?;
+            zip.write_all(data)?;
+        }
+        zip.finish().map_err(io::Error::other)?;
+    }
+    if path.exists() {
+        fs::remove_file(&path)?;
+    }
+    fs::rename(&tmp, &path)?;
+    info!("Backup created ({}): {}", reason_slug(reason), path.display());
+
+    if dest.is_none() && reason != BackupReason::Manual {
+        if let Err(e) = rotate(&data_dir.join(BACKUPS_DIR_NAME)) {
+            warn!("Failed to rotate automatic backups: {}", e);
+        }
+    }
+    info_for(&path, manifest)
+}
+
+/// Удалить старые автоматические копии сверх лимита
+fn rotate(dir: &Path) -> io::Result<()> {
+    let mut auto: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+            let name = p.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            name.starts_with("backup-") && name.ends_with(".zip") && !name.ends_with("-manual.zip")
+        })
+        .collect();
+    // Имя начинается с времени создания — сортировка по имени = по времени
+    auto.sort();
+    let excess = auto.len().saturating_sub(MAX_AUTO_BACKUPS);
+    for path in auto.into_iter().take(excess) {
+        fs::remove_file(&path)?;
+    }
+    Ok(())
+}
+
+fn info_for(path: &Path, manifest: BackupManifest) -> io::Result<BackupInfo> {
+    Ok(BackupInfo {
+        path: path.to_string_lossy().to_string(),
+        file_name: path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+        created_at: manifest.created_at,
+        reason: manifest.reason,
+        app_version: manifest.app_version,
+        format_version: manifest.version,
+        db_schema_version: manifest.db_schema_version,
+        files: manifest.files.into_iter().map(|f| f.name).collect(),
+        size_bytes: fs::metadata(path)?.len(),
+    })
+}
+
+fn read_manifest(zip: &mut ZipArchive<fs::File>) -> io::Result<BackupManifest> {
+    let mut data = String::new();
+    zip.by_name(MANIFEST_NAME)
+        .map_err(|_| invalid("Backup manifest is missing"))?
+        .read_to_string(&mut data)?;
+    let manifest: BackupManifest = serde_json::from_str(&data).map_err(|e| invalid(format!("Invalid backup manifest: {}", e)))?;
+    if manifest.format != BACKUP_FORMAT_ID {
+        return Err(invalid("Not a TLI Companion backup"));
+    }
+    if manifest.version == 0 || manifest.version > BACKUP_VERSION {
+        return Err(invalid(format!("Unsupported backup version: {}", manifest.version)));
+    }
+    Ok(manifest)
+}
+
+fn open_manifest(path: &Path) -> io::Result<BackupManifest> {
+    fs::File::open(path)
+        .and_then(|f| ZipArchive::new(f).map_err(|e| invalid(e.to_string())))
+        .and_then(|mut zip| read_manifest(&mut zip))
+}
+
+/// Прочитать и проверить архив: формат, имена файлов, контрольные суммы, JSON
+fn read_archive(path: &Path) -> io::Result<(BackupManifest, ArchiveFiles)> {
+    require_zip(path)?;
+    let mut zip = ZipArchive::new(fs::File::open(path)?).map_err(|e| invalid(e.to_string()))?;
+    let manifest = read_manifest(&mut zip)?;
+
+    let mut files = Vec::with_capacity(manifest.files.len());
+    for file in &manifest.files {
+        if file.name != DB_FILE_NAME && !is_data_file(&file.name) {
+            return Err(invalid(format!("Unexpected file in backup: {}", file.name)));
+        }
+        let mut data = Vec::new();
+        zip.by_name(&file.name)
+            .map_err(|_| invalid(format!("File missing from backup: {}", file.name)))?
+            .read_to_end(&mut data)?;
+        if data.len() as u64 != file.size || sha256_hex(&data) != file.sha256 {
+            return Err(invalid(format!("Checksum mismatch: {}", file.name)));
+        }
+        if file.name != DB_FILE_NAME {
+            serde_json::from_slice::<serde_json::Value>(&data)
+                .map_err(|e| invalid(format!("Invalid JSON in {}: {}", file.name, e)))?;
+        }
+        files.push((file.name.clone(), data));
+    }
+    Ok((manifest, files))
+}
+
+/// Проверить архив без восстановления
+pub(crate) fn inspect(path: &Path) -> io::Result<BackupInfo> {
+    let (manifest, _) = read_archive(path)?;
+    info_for(path, manifest)
+}
+
+/// Копии в каталоге backups, новые первыми (повреждённые архивы пропускаются)
+pub(crate) fn list() -> io::Result<Vec<BackupInfo>> {
+    let Some(dir) = backups_dir() else {
+        return Ok(Vec::new());
+    };
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|e| e != "zip") {
+            continue;
+        }
+        match open_manifest(&path) {
+            Ok(manifest) => backups.push(info_for(&path, manifest)?),
+            Err(e) => warn!("Skipping unreadable backup {}: {}", path.display(), e),
+        }
+    }
+    backups.sort_by_key(|b| std::cmp::Reverse(b.created_at));
+    Ok(backups)
+}
+
+fn remove_wal(data_dir: &Path) -> io::Result<()> {
+    for suffix in ["-wal", "-shm"] {
+        let path = data_dir.join(format!("{}{}", DB_FILE_NAME, suffix));
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Восстановить данные из архива. Перед заменой создаётся автоматическая копия текущих данных.
+/// JSON файлы, которых нет в архиве, удаляются. После восстановления запись на диск отключена
+/// до перезапуска приложения.
+pub(crate) fn restore(path: &Path, store: Option<&Store>) -> io::Result<BackupInfo> {
+    // Дальше данные на диске новее состояния в памяти: сохранения отключаются до перезапуска
+    restore_into(&data_dir()?, path, store, persistence::freeze_writes)
+}
+
+/// `before_write` вызывается после страховочной копии, перед заменой файлов
+fn restore_into(data_dir: &Path, path: &Path, store: Option<&Store>, before_write: impl FnOnce()) -> io::Result<BackupInfo> {
+    let (manifest, files) = read_archive(path)?;
+
+    create_in(data_dir, BackupReason::BeforeRestore, None, store)?;
+    before_write();
+
+    if let Some((_, data)) = files.iter().find(|(name, _)| name == DB_FILE_NAME) {
+        let tmp = data_dir.join(format!("{}.restore.tmp", DB_FILE_NAME));
+        fs::write(&tmp, data)?;
+        let result = match store {
+            Some(store) => store.restore_from(&tmp),
+            // База не открыта — подменяем файл, миграции применятся при следующем запуске.
+            // Старый WAL иначе применится поверх восстановленной базы.
+            None => remove_wal(data_dir).and_then(|_| fs::copy(&tmp, data_dir.join(DB_FILE_NAME)).map(|_| ())),
+        };
+        let _ = fs::remove_file(&tmp);
+        result?;
+    }
+
+    let restored: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+    for name in data_files(data_dir)? {
+        if !restored.contains(&name.as_str()) {
+            fs::remove_file(data_dir.join(&name))?;
+        }
+    }
+    for (name, data) in &files {
+        if name != DB_FILE_NAME {
+            let content = String::from_utf8(data.clone()).map_err(|e| invalid(e.to_string()))?;
+            persistence::write_replacing(&data_dir.join(name), &content)?;
+        }
+    }
+    info!("Backup restored: {}", path.display());
+    info_for(path, manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(tag: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tli-backup-{}-{}", tag, uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Переписать архив, подменив содержимое одного файла (манифест прежний)
+    fn tamper(path: &Path, name: &str, data: &[u8]) {
+        let mut zip = ZipArchive::new(fs::File::open(path).unwrap()).unwrap();
+        let mut entries = Vec::new();
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i).unwrap();
+            let mut content = Vec::new();
+            file.read_to_end(&mut content).unwrap();
+            entries.push((file.name().to_string(), content));
+        }
+        let mut out = ZipWriter::new(fs::File::create(path).unwrap());
+        for (entry, content) in entries {
+            out.start_file(entry.as_str(), SimpleFileOptions::default()).unwrap();
+            out.write_all(if entry == name { data } else { &content }).unwrap();
+        }
+        out.finish().unwrap();
+    }
+
+    #[test]
+    fn test_create_and_restore_round_trip() {
+        let data = temp_dir("data");
+        let out = temp_dir("out");
+        fs::write(data.join("settings.json"), r#"{"version":1,"data":{}}"#).unwrap();
+        fs::write(data.join("goals.json"), "[]").unwrap();
+        let archive = out.join("copy.zip");
+
+        let info = create_in(&data, BackupReason::Manual, Some(&archive), None).unwrap();
+        assert_eq!(info.files, vec!["goals.json".to_string(), "settings.json".to_string()]);
+
+        // Изменения после копии: правка, новый файл, удалённый файл
+        fs::write(data.join("settings.json"), r#"{"version":1,"data":{"x":1}}"#).unwrap();
+        fs::write(data.join("presets.json"), "[]").unwrap();
+        fs::remove_file(data.join("goals.json")).unwrap();
+
+        let mut frozen = false;
+        restore_into(&data, &archive, None, || frozen = true).unwrap();
+        assert!(frozen);
+        assert_eq!(fs::read_to_string(data.join("settings.json")).unwrap(), r#"{"version":1,"data":{}}"#);
+        assert_eq!(fs::read_to_string(data.join("goals.json")).unwrap(), "[]");
+        // JSON файла нет в архиве — он удаляется, но остаётся в страховочной копии
+        assert!(!data.join("presets.json").exists());
+        let safety: Vec<PathBuf> = fs::read_dir(data.join(BACKUPS_DIR_NAME)).unwrap().map(|e| e.unwrap().path()).collect();
+        assert_eq!(safety.len(), 1);
+        assert!(read_archive(&safety[0]).unwrap().1.iter().any(|(name, _)| name == "presets.json"));
+
+        // Существующую копию можно перезаписать
+        create_in(&data, BackupReason::Manual, Some(&archive), None).unwrap();
+
+        let _ = fs::remove_dir_all(&data);
+        let _ = fs::remove_dir_all(&out);
+    }
+
+    #[test]
+    fn test_rejects_foreign_paths_and_tampered_archives() {
+        let data = temp_dir("data");
+        let out = temp_dir("out");
+        fs::write(data.join("settings.json"), "{}").unwrap();
+
+        // Только .zip и не поверх чужих файлов
+        assert_eq!(
+            create_in(&data, BackupReason::Manual, Some(&out.join("notes.txt")), None).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput,
+        );
+        let foreign = out.join("photos.zip");
+        fs::write(&foreign, "not a backup").unwrap();
+        assert_eq!(
+            create_in(&data, BackupReason::Manual, Some(&foreign), None).unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists,
+        );
+        assert_eq!(fs::read_to_string(&foreign).unwrap(), "not a backup");
+        let text = out.join("notes.txt");
+        fs::write(&text, "{}").unwrap();
+        assert!(restore_into(&data, &text, None, || {}).is_err());
+
+        // Контрольная сумма не совпала — ничего не трогаем
+        let archive = out.join("copy.zip");
+        create_in(&data, BackupReason::Manual, Some(&archive), None).unwrap();
+        tamper(&archive, "settings.json", br#"{"tampered":true}"#);
+        let err = restore_into(&data, &archive, None, || panic!("must not write")).unwrap_err();
+        assert!(err.to_string().contains("Checksum mismatch"));
+        assert_eq!(fs::read_to_string(data.join("settings.json")).unwrap(), "{}");
+        assert!(!data.join(BACKUPS_DIR_NAME).exists());
+
+        let _ = fs::remove_dir_all(&data);
+        let _ = fs::remove_dir_all(&out);
+    }
+}
@@ -8,7 +8,13 @@ use log::info;
 use serde::Serialize;
 
 use crate::state::AppState;
-use crate::types::{SessionStats, AggregatedDrop, AppSettings, ItemInfo, UserProfile};
+use crate::types::{SessionStats, AggregatedDrop, AppSettings, ItemInfo, UserProfile, LogSourceInfo, MapRunStats, MapRunSummary, RecentDrop, ValuePoint,
+    StrategyPreset, PresetComparison, DropCorrection, ValuationRules, IncomeLedger,
+    ZoneDropStats, ExpectedDrop, ForecastTarget, SessionForecast, Goal, GoalProgress,
+    PriceObservation, PriceTrend, PriceVolatility, HistoricalSessionValue, PriceAlert,
+    QuarantinedPrice, Denomination, ExportFormat, ExportKind, ImportSource, ImportPreview, ImportConflictPolicy,
+    ImportResult, BackupInfo, BackupReason, IncomeSource, RecordedDropValue};
+use crate::export;
 use crate::file_watcher::find_log_path;
 use std::sync::atomic::AtomicBool;
 
@@ -39,29 +45,38 @@ pub async fn find_log_file() -> Result<Option<String>, String> {
     }
 }
 
-/// Установить путь к файлу логов вручную
-#[tauri::command]
-pub async fn set_log_path(
-    state: State<'_, Arc<AppState>>,
-    path: String,
-) -> Result<bool, String> {
+/// Проверить, что путь указывает на существующий UE_game.log
+fn validate_log_path(path: &str) -> Result<(), String> {
     // Проверяем, существует ли файл
-    if !std::path::Path::new(&path).exists() {
+    if !std::path::Path::new(path).exists() {
         return Err("File does not exist".to_string());
     }
 
     // Безопасность: по умолчанию разрешаем только UE_game.log, чтобы не дать приложению
     // читать произвольные файлы пользователя через IPC.
-    if let Some(file_name) = std::path::Path::new(&path).file_name().and_then(|n| n.to_str()) {
+    if let Some(file_name) = std::path::Path::new(path).file_name().and_then(|n| n.to_str()) {
         if !file_name.eq_ignore_ascii_case("UE_game.log") {
             return Err("Only UE_game.log is supported for security reasons".to_string());
         }
     } else {
         return Err("Invalid path".to_string());
     }
+    Ok(())
+}
+
+/// Установить путь к файлу логов вручную
+#[tauri::command]
+pub async fn set_log_path(
+    state: State<'_, Arc<AppState>>,
+    path: String,
+    source_id: Option<String>,
+) -> Result<bool, String> {
+    validate_log_path(&path)?;
     
-    state.set_log_path(Some(path.clone())).await;
-    info!("Log path set to: {}", path);
+    let source = state.source(source_id.as_deref()).await;
+    state.ensure_log_path_free(&path, Some(&source.id)).await?;
+    state.set_log_path(&source, Some(path.clone())).await;
+    info!("Log path set to: {} (source={})", path, source.id);
     Ok(true)
 }
 
@@ -70,18 +85,58 @@ pub async fn set_log_path(
 pub async fn start_session(
     state: State<'_, Arc<AppState>>,
     preset_id: Option<String>,
+    source_id: Option<String>,
 ) -> Result<(), String> {
-    state.start_session(preset_id).await;
+    let source = state.source(source_id.as_deref()).await;
+    state.start_session(&source, preset_id).await;
     Ok(())
 }
 
+/// Получить пресеты стратегий
+#[tauri::command]
+pub async fn get_presets(
+    state: State<'_, Arc<AppState>>,
+) -> Result<Vec<StrategyPreset>, String> {
+    Ok(state.get_presets().await)
+}
+
+/// Создать или обновить пресет стратегии
+#[tauri::command]
+pub async fn save_preset(
+    state: State<'_, Arc<AppState>>,
+    preset: StrategyPreset,
+) -> Result<StrategyPreset, String> {
+    state.save_preset(preset).await
+}
+
+/// Удалить пресет стратегии
+#[tauri::command]
+pub async fn delete_preset(
+    state: State<'_, Arc<AppState>>,
+    id: String,
+) -> Result<bool, String> {
+    state.delete_preset(&id).await
+}
+
+/// Сравнить текущую сессию с прошлыми сессиями того же пресета
+#[tauri::command]
+pub async fn get_preset_comparison(
+    state: State<'_, Arc<AppState>>,
+    source_id: Option<String>,
+) -> Result<Option<PresetComparison>, String> {
+    let source = state.source(source_id.as_deref()).await;
+    Ok(state.get_preset_comparison(&source).await)
+}
+
 /// Установить состояние паузы сессии
 #[tauri::command]
 pub async fn set_paused(
     state: State<'_, Arc<AppState>>,
     paused: bool,
+    source_id: Option<String>,
 ) -> Result<(), String> {
-    state.set_paused(paused).await;
+    let source = state.source(source_id.as_deref()).await;
+    state.set_paused(&source, paused).await;
     Ok(())
 }
 
@@ -90,13 +145,26 @@ pub async fn set_paused(
 pub async fn update_session_duration(
     state: State<'_, Arc<AppState>>,
     duration_sec: i32,
+    source_id: Option<String>,
 ) -> Result<(), String> {
-    state.update_session_duration(duration_sec).await;
+    let source = state.source(source_id.as_deref()).await;
+    state.update_session_duration(&source, duration_sec).await;
     Ok(())
 }
 
+/// Получить интервалы паузы текущей сессии
+#[tauri::command]
+pub async fn get_pause_intervals(
+    state: State<'_, Arc<AppState>>,
+    source_id: Option<String>,
+) -> Result<Vec<crate::types::PauseInterval>, String> {
+    let source = state.source(source_id.as_deref()).await;
+    Ok(state.get_pause_intervals(&source).await)
+}
+
 /// Добавить трату вручную
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn add_expense(
     state: State<'_, Arc<AppState>>,
     id: String,
@@ -105,8 +173,10 @@ pub async fn add_expense(
     name_ru: Option<String>,
     quantity: i32,
     price: f64,
+    source_id: Option<String>,
 ) -> Result<(), String> {
-    state.add_expense(id, game_id, name, name_ru, quantity, price).await;
+    let source = state.source(source_id.as_deref()).await;
+    state.add_expense(&source, id, game_id, name, name_ru, quantity, price).await;
     Ok(())
 }
 
@@ -115,8 +185,10 @@ pub async fn add_expense(
 pub async fn remove_expense(
     state: State<'_, Arc<AppState>>,
     id: String,
+    source_id: Option<String>,
 ) -> Result<(), String> {
-    state.remove_expense(&id).await;
+    let source = state.source(source_id.as_deref()).await;
+    state.remove_expense(&source, &id).await;
     Ok(())
 }
 
@@ -124,8 +196,10 @@ pub async fn remove_expense(
 #[tauri::command]
 pub async fn get_expenses(
     state: State<'_, Arc<AppState>>,
+    source_id: Option<String>,
 ) -> Result<Vec<crate::types::ExpenseEntry>, String> {
-    Ok(state.get_expenses().await)
+    let source = state.source(source_id.as_deref()).await;
+    Ok(state.get_expenses(&source).await)
 }
 
 /// Поиск предметов по названию
@@ -147,6 +221,7 @@ pub async fn get_cached_prices(
 
 /// Добавить ручной дроп (для уников/экипировки)
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn add_manual_drop(
     state: State<'_, Arc<AppState>>,
     id: String,
@@ -155,8 +230,10 @@ pub async fn add_manual_drop(
     name_ru: Option<String>,
     quantity: i32,
     price: f64,
+    source_id: Option<String>,
 ) -> Result<(), String> {
-    state.add_manual_drop(id, game_id, name, name_ru, quantity, price).await;
+    let source = state.source(source_id.as_deref()).await;
+    state.add_manual_drop(&source, id, game_id, name, name_ru, quantity, price).await;
     Ok(())
 }
 
@@ -165,8 +242,10 @@ pub async fn add_manual_drop(
 pub async fn remove_manual_drop(
     state: State<'_, Arc<AppState>>,
     id: String,
+    source_id: Option<String>,
 ) -> Result<(), String> {
-    state.remove_manual_drop(&id).await;
+    let source = state.source(source_id.as_deref()).await;
+    state.remove_manual_drop(&source, &id).await;
     Ok(())
 }
 
@@ -174,8 +253,154 @@ pub async fn remove_manual_drop(
 #[tauri::command]
 pub async fn get_manual_drops(
     state: State<'_, Arc<AppState>>,
+    source_id: Option<String>,
 ) -> Result<Vec<crate::types::ManualDropEntry>, String> {
-    Ok(state.get_manual_drops().await)
+    let source = state.source(source_id.as_deref()).await;
+    Ok(state.get_manual_drops(&source).await)
+}
+
+/// Исправить количество авто-дропа предмета
+#[tauri::command]
+pub async fn adjust_drop_quantity(
+    state: State<'_, Arc<AppState>>,
+    game_id: i64,
+    quantity: i32,
+    source_id: Option<String>,
+) -> Result<DropCorrection, String> {
+    let source = state.source(source_id.as_deref()).await;
+    state.adjust_drop_quantity(&source, game_id, quantity).await
+}
+
+/// Исключить предмет из подсчёта стоимости (excluded = false — вернуть)
+#[tauri::command]
+pub async fn set_drop_excluded(
+    state: State<'_, Arc<AppState>>,
+    game_id: i64,
+    excluded: bool,
+    source_id: Option<String>,
+) -> Result<Option<DropCorrection>, String> {
+    let source = state.source(source_id.as_deref()).await;
+    state.set_drop_excluded(&source, game_id, excluded).await
+}
+
+/// Отменить последние N авто-подборов
+#[tauri::command]
+pub async fn undo_last_drops(
+    state: State<'_, Arc<AppState>>,
+    count: usize,
+    source_id: Option<String>,
+) -> Result<Vec<DropCorrection>, String> {
+    let source = state.source(source_id.as_deref()).await;
+    state.undo_last_drops(&source, count).await
+}
+
+/// Журнал правок дропа текущей сессии
+#[tauri::command]
+pub async fn get_drop_corrections(
+    state: State<'_, Arc<AppState>>,
+    source_id: Option<String>,
+) -> Result<Vec<DropCorrection>, String> {
+    let source = state.source(source_id.as_deref()).await;
+    Ok(state.get_drop_corrections(&source).await)
+}
+
+/// Правила оценки предметов
+#[tauri::command]
+pub async fn get_valuation_rules(
+    state: State<'_, Arc<AppState>>,
+) -> Result<ValuationRules, String> {
+    Ok(state.get_valuation_rules().await)
+}
+
+/// Сохранить правила оценки предметов
+#[tauri::command]
+pub async fn save_valuation_rules(
+    state: State<'_, Arc<AppState>>,
+    rules: ValuationRules,
+) -> Result<ValuationRules, String> {
+    state.save_valuation_rules(rules).await
+}
+
+/// Журнал дохода сессии (авто-дроп, ручной дроп) с итогами по источникам
+#[tauri::command]
+pub async fn get_income_ledger(
+    state: State<'_, Arc<AppState>>,
+    source_id: Option<String>,
+) -> Result<IncomeLedger, String> {
+    let source = state.source(source_id.as_deref()).await;
+    Ok(state.get_income_ledger(&source).await)
+}
+
+/// Статистика дропа по зонам и пресетам (накоплена по завершённым сессиям)
+#[tauri::command]
+pub async fn get_drop_rate_stats(
+    state: State<'_, Arc<AppState>>,
+    zone: Option<String>,
+    preset_id: Option<String>,
+) -> Result<Vec<ZoneDropStats>, String> {
+    Ok(state.get_drop_rate_stats(zone.as_deref(), preset_id.as_deref()).await)
+}
+
+/// Ожидаемый дроп за N карт в зоне (по умолчанию 100)
+#[tauri::command]
+pub async fn get_expected_drops(
+    state: State<'_, Arc<AppState>>,
+    zone: String,
+    maps: Option<i32>,
+    preset_id: Option<String>,
+) -> Result<Vec<ExpectedDrop>, String> {
+    let maps = maps.unwrap_or(100);
+    if maps <= 0 {
+        return Err("Map count must be positive".to_string());
+    }
+    Ok(state.get_expected_drops(&zone, maps, preset_id.as_deref()).await)
+}
+
+/// Прогноз итогов сессии (к длительности, моменту времени или числу карт)
+#[tauri::command]
+pub async fn get_session_forecast(
+    state: State<'_, Arc<AppState>>,
+    target: ForecastTarget,
+    source_id: Option<String>,
+) -> Result<SessionForecast, String> {
+    let source = state.source(source_id.as_deref()).await;
+    state.get_session_forecast(&source, &target).await
+}
+
+/// Все цели сессии
+#[tauri::command]
+pub async fn get_goals(
+    state: State<'_, Arc<AppState>>,
+) -> Result<Vec<Goal>, String> {
+    Ok(state.get_goals().await)
+}
+
+/// Создать или обновить цель
+#[tauri::command]
+pub async fn save_goal(
+    state: State<'_, Arc<AppState>>,
+    goal: Goal,
+) -> Result<Goal, String> {
+    state.save_goal(goal).await
+}
+
+/// Удалить цель
+#[tauri::command]
+pub async fn delete_goal(
+    state: State<'_, Arc<AppState>>,
+    id: String,
+) -> Result<bool, String> {
+    state.delete_goal(&id).await
+}
+
+/// Прогресс и ETA целей в текущей сессии
+#[tauri::command]
+pub async fn get_goal_progress(
+    state: State<'_, Arc<AppState>>,
+    source_id: Option<String>,
+) -> Result<Vec<GoalProgress>, String> {
+    let source = state.source(source_id.as_deref()).await;
+    Ok(state.get_goal_progress(&source).await)
 }
 
 /// Завершить сессию фарма
@@ -183,13 +408,16 @@ pub async fn get_manual_drops(
 pub async fn end_session(
     state: State<'_, Arc<AppState>>,
     _app_handle: tauri::AppHandle,
+    source_id: Option<String>,
 ) -> Result<SessionStats, String> {
+    let source = state.source(source_id.as_deref()).await;
     // ВАЖНО: сначала берём финальные stats и данные сессии, потом сбрасываем состояние.
-    let stats = state.get_session_stats().await;
+    let stats = state.get_session_stats(&source).await;
+    let ledger = state.get_income_ledger(&source).await;
     
     // Get session data before reset (and update duration from wall-clock)
     let session = {
-        let mut s = state.session.write().await;
+        let mut s = source.session.write().await;
         // Update duration to wall-clock time (not just map time)
         s.total_duration_sec = stats.duration_sec;
         s.clone()
@@ -197,11 +425,26 @@ pub async fn end_session(
     
     // Calculate totals
     let total_expenses: f64 = session.expenses.iter().map(|e| e.price * e.quantity as f64).sum();
-    let total_income = stats.total_value;
+    // Доход из журнала дохода (авто + ручной дроп): total_income — до комиссии аукциона, как в старых записях
+    let total_income = ledger.gross_total;
     let total_profit = total_income - total_expenses;
     
-    // Save to local history (if logged in)
-    if let Some(user_id) = state.get_auth_user_id().await {
+    // Save to local history (guest profile when not logged in)
+    {
+        let user_id = state.history_user_id().await;
+        let drops: std::collections::HashMap<i64, i32> = session.drops.iter()
+            .filter(|(id, _)| !session.excluded_items.contains(id))
+            .map(|(id, qty)| (*id, *qty))
+            .collect();
+        // Цены и стоимость, по которым посчитан доход, — для экспорта без переоценки
+        let drop_values = ledger.entries.iter()
+            .filter(|e| e.source == IncomeSource::AutoDrop)
+            .filter_map(|e| e.game_id.filter(|id| drops.contains_key(id)).map(|id| (id, RecordedDropValue {
+                unit_price: e.unit_price,
+                total_value: e.gross_value,
+                net_value: e.net_value,
+            })))
+            .collect();
         let history_record = crate::persistence::SessionHistoryRecord {
             id: uuid::Uuid::new_v4().to_string(),
             started_at: session.started_at.unwrap_or_else(chrono::Utc::now),
@@ -211,10 +454,17 @@ pub async fn end_session(
             total_profit,
             total_expenses,
             total_income,
+            net_income: Some(ledger.net_total),
+            income_by_source: ledger.by_source,
             remote_id: None, // Not syncing to cloud anymore
+            preset_id: session.preset_id.clone(),
+            drops,
+            drop_values,
+            manual_drops: session.manual_drops.clone(),
+            expenses: session.expenses.clone(),
         };
         
-        if let Err(e) = crate::persistence::add_session_to_history(&user_id, history_record) {
+        if let Err(e) = state.add_history_record(&user_id, history_record, &session.map_runs).await {
             log::warn!("Failed to save session to local history: {}", e);
         } else {
             log::info!("Session saved to local history");
@@ -222,25 +472,28 @@ pub async fn end_session(
     }
     
     // Reset session state
-    let _session_data = state.end_session().await;
+    let _session_data = state.end_session(&source).await;
     Ok(stats)
 }
 
-/// Получить историю сессий пользователя (локально)
+/// Получить историю сессий пользователя (локально; без входа — гостевой профиль)
 #[tauri::command]
 pub async fn get_session_history(
     state: State<'_, Arc<AppState>>,
     limit: Option<i32>,
+    denomination: Option<i64>,
 ) -> Result<Vec<crate::persistence::SessionHistoryRecord>, String> {
-    let user_id = state.get_auth_user_id().await
-        .ok_or_else(|| "Not logged in".to_string())?;
-    
-    let mut sessions = crate::persistence::load_session_history(&user_id)
-        .map_err(|e| e.to_string())?;
+    let user_id = state.history_user_id().await;
     
-    // Apply limit
-    let limit = limit.unwrap_or(20) as usize;
-    sessions.truncate(limit);
+    let limit = limit.unwrap_or(20).max(0) as usize;
+    let mut sessions = state.load_history(&user_id, Some(limit)).await?;
+
+    // Пересчёт по текущему курсу из кэша цен
+    if let Some(d) = state.display_denomination(denomination).await? {
+        for record in &mut sessions {
+            crate::denomination::convert_history(record, &d);
+        }
+    }
     
     Ok(sessions)
 }
@@ -251,41 +504,174 @@ pub async fn delete_session_history(
     state: State<'_, Arc<AppState>>,
     session_id: String,
 ) -> Result<bool, String> {
-    let user_id = state.get_auth_user_id().await
-        .ok_or_else(|| "Not logged in".to_string())?;
+    let user_id = state.history_user_id().await;
     
-    let removed = crate::persistence::delete_session_from_history(&user_id, &session_id)
-        .map_err(|e| e.to_string())?;
+    let removed = state.delete_history_record(&user_id, &session_id).await?;
     
-    if removed.is_some() {
+    if removed {
         log::info!("Deleted session from history: {}", session_id);
     }
     
-    Ok(removed.is_some())
+    Ok(removed)
 }
 
 /// Получить текущую статистику сессии
 #[tauri::command]
 pub async fn get_session_stats(
     state: State<'_, Arc<AppState>>,
+    source_id: Option<String>,
+    denomination: Option<i64>,
 ) -> Result<SessionStats, String> {
-    Ok(state.get_session_stats().await)
+    let source = state.source(source_id.as_deref()).await;
+    let mut stats = state.get_session_stats(&source).await;
+    if let Some(d) = state.display_denomination(denomination).await? {
+        crate::denomination::convert_stats(&mut stats, &d);
+    }
+    Ok(stats)
 }
 
 /// Получить список дропов за сессию
 #[tauri::command]
 pub async fn get_drops(
     state: State<'_, Arc<AppState>>,
+    source_id: Option<String>,
+    denomination: Option<i64>,
 ) -> Result<Vec<AggregatedDrop>, String> {
-    Ok(state.get_aggregated_drops().await)
+    let source = state.source(source_id.as_deref()).await;
+    let mut drops = state.get_aggregated_drops(&source).await;
+    if let Some(d) = state.display_denomination(denomination).await? {
+        crate::denomination::convert_drops(&mut drops, &d);
+    }
+    Ok(drops)
+}
+
+/// Получить карты текущей сессии (время, дроп и стоимость по каждой)
+#[tauri::command]
+pub async fn get_map_runs(
+    state: State<'_, Arc<AppState>>,
+    source_id: Option<String>,
+) -> Result<Vec<MapRunStats>, String> {
+    let source = state.source(source_id.as_deref()).await;
+    Ok(state.get_map_runs(&source).await)
+}
+
+/// Последние N подборов (лента дропа)
+#[tauri::command]
+pub async fn get_recent_drops(
+    state: State<'_, Arc<AppState>>,
+    limit: Option<usize>,
+    source_id: Option<String>,
+) -> Result<Vec<RecentDrop>, String> {
+    let source = state.source(source_id.as_deref()).await;
+    Ok(state.get_recent_drops(&source, limit.unwrap_or(20)).await)
+}
+
+/// Накопленный доход во времени (для графика)
+#[tauri::command]
+pub async fn get_value_timeline(
+    state: State<'_, Arc<AppState>>,
+    bucket_sec: Option<i64>,
+    source_id: Option<String>,
+) -> Result<Vec<ValuePoint>, String> {
+    let source = state.source(source_id.as_deref()).await;
+    Ok(state.get_value_timeline(&source, bucket_sec).await)
+}
+
+/// Лучшая/худшая карта и распределение стоимости по картам
+#[tauri::command]
+pub async fn get_map_run_summary(
+    state: State<'_, Arc<AppState>>,
+    source_id: Option<String>,
+) -> Result<MapRunSummary, String> {
+    let source = state.source(source_id.as_deref()).await;
+    Ok(state.get_map_run_summary(&source).await)
 }
 
 /// Проверить, активна ли сессия
 #[tauri::command]
 pub async fn is_session_active(
     state: State<'_, Arc<AppState>>,
+    source_id: Option<String>,
 ) -> Result<bool, String> {
-    Ok(state.is_session_active().await)
+    let source = state.source(source_id.as_deref()).await;
+    Ok(state.is_session_active(&source).await)
+}
+
+/// Список отслеживаемых лог-файлов (клиентов игры)
+#[tauri::command]
+pub async fn list_log_sources(
+    state: State<'_, Arc<AppState>>,
+) -> Result<Vec<LogSourceInfo>, String> {
+    Ok(state.list_sources().await)
+}
+
+/// Добавить ещё один лог-файл (второй клиент / мультибокс) и сразу начать его отслеживать
+#[tauri::command]
+pub async fn add_log_source(
+    state: State<'_, Arc<AppState>>,
+    name: String,
+    path: String,
+) -> Result<LogSourceInfo, String> {
+    validate_log_path(&path)?;
+
+    let app_state = state.inner().clone();
+    let source = app_state.add_log_source(name, path.clone()).await?;
+    crate::log_pipeline::start_source(
+        app_state.clone(),
+        source.clone(),
+        std::path::PathBuf::from(path),
+    );
+
+    state
+        .list_sources()
+        .await
+        .into_iter()
+        .find(|s| s.id == source.id)
+        .ok_or_else(|| "Log source not found".to_string())
+}
+
+/// Удалить дополнительный лог-файл
+#[tauri::command]
+pub async fn remove_log_source(
+    state: State<'_, Arc<AppState>>,
+    source_id: String,
+) -> Result<(), String> {
+    state.remove_log_source(&source_id).await
+}
+
+/// Выбрать источник, с которым работает UI (команды без source_id)
+#[tauri::command]
+pub async fn select_log_source(
+    state: State<'_, Arc<AppState>>,
+    source_id: String,
+) -> Result<(), String> {
+    state.select_source(&source_id).await
+}
+
+/// Общая статистика по всем клиентам
+#[tauri::command]
+pub async fn get_aggregate_stats(
+    state: State<'_, Arc<AppState>>,
+    denomination: Option<i64>,
+) -> Result<SessionStats, String> {
+    let mut stats = state.get_aggregate_stats().await;
+    if let Some(d) = state.display_denomination(denomination).await? {
+        crate::denomination::convert_stats(&mut stats, &d);
+    }
+    Ok(stats)
+}
+
+/// Общий список дропов по всем клиентам
+#[tauri::command]
+pub async fn get_aggregate_drops(
+    state: State<'_, Arc<AppState>>,
+    denomination: Option<i64>,
+) -> Result<Vec<AggregatedDrop>, String> {
+    let mut drops = state.get_aggregate_drops().await;
+    if let Some(d) = state.display_denomination(denomination).await? {
+        crate::denomination::convert_drops(&mut drops, &d);
+    }
+    Ok(drops)
 }
 
 /// Получить настройки приложения
@@ -337,24 +723,287 @@ pub async fn update_item_price(
     game_id: i64,
     price: f64,
 ) -> Result<(), String> {
-    state.update_price(game_id, price).await;
+    state.update_price(game_id, price, None).await;
     Ok(())
 }
 
+/// История цены предмета (days = None — вся сохранённая история)
+#[tauri::command]
+pub async fn get_price_history(
+    state: State<'_, Arc<AppState>>,
+    game_id: i64,
+    days: Option<i64>,
+) -> Result<Vec<PriceObservation>, String> {
+    Ok(state.get_price_history(game_id, days).await)
+}
+
+/// Изменение цены предмета за 24ч и 7 дней
+#[tauri::command]
+pub async fn get_price_trend(
+    state: State<'_, Arc<AppState>>,
+    game_id: i64,
+) -> Result<PriceTrend, String> {
+    Ok(state.get_price_trend(game_id).await)
+}
+
+/// Волатильность цены предмета (по умолчанию за 7 дней)
+#[tauri::command]
+pub async fn get_price_volatility(
+    state: State<'_, Arc<AppState>>,
+    game_id: i64,
+    days: Option<i64>,
+) -> Result<Option<PriceVolatility>, String> {
+    let days = days.unwrap_or(7);
+    if days <= 0 {
+        return Err("Window must be at least one day".to_string());
+    }
+    Ok(state.get_price_volatility(game_id, days).await)
+}
+
+/// Стоимость сессии из истории по ценам на момент `at` (по умолчанию — на конец сессии)
+#[tauri::command]
+pub async fn get_session_value_at_prices(
+    state: State<'_, Arc<AppState>>,
+    session_id: String,
+    at: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<HistoricalSessionValue, String> {
+    let user_id = state.history_user_id().await;
+    let record = state.get_history_record(&user_id, &session_id).await?
+        .ok_or_else(|| "Session not found".to_string())?;
+    let at = at.unwrap_or(record.ended_at);
+    Ok(state.value_at_prices(&record, at).await)
+}
+
+/// Экспорт истории сессий (путь выбирается диалогом сохранения). Возвращает кол-во строк.
+#[tauri::command]
+pub async fn export_session_history(
+    state: State<'_, Arc<AppState>>,
+    path: String,
+    format: ExportFormat,
+) -> Result<usize, String> {
+    let user_id = state.history_user_id().await;
+    let sessions = state.load_history(&user_id, None).await?;
+    let content = match format {
+        ExportFormat::Csv => export::sessions_csv(&sessions),
+        ExportFormat::Json => export::to_json(ExportKind::Sessions, &sessions)?,
+    };
+    export::write(&path, format, &content)?;
+    info!("Exported {} sessions to {}", sessions.len(), path);
+    Ok(sessions.len())
+}
+
+/// Экспорт одной сессии из истории: дроп, ручной дроп и расходы
+#[tauri::command]
+pub async fn export_session(
+    state: State<'_, Arc<AppState>>,
+    session_id: String,
+    path: String,
+    format: ExportFormat,
+) -> Result<usize, String> {
+    let user_id = state.history_user_id().await;
+    let record = state.get_history_record(&user_id, &session_id).await?
+        .ok_or_else(|| "Session not found".to_string())?;
+    let items = state.export_session_items(&record).await;
+    let rows = items.len();
+    let content = match format {
+        ExportFormat::Csv => export::session_items_csv(&record.id, &items),
+        ExportFormat::Json => {
+            let map_runs = state.history_map_runs(&record.id).await;
+            export::to_json(ExportKind::Session, export::SessionExport { session: record, map_runs, items })?
+        }
+    };
+    export::write(&path, format, &content)?;
+    info!("Exported session {} ({} lines) to {}", session_id, rows, path);
+    Ok(rows)
+}
+
+/// Экспорт кэша цен
+#[tauri::command]
+pub async fn export_prices(
+    state: State<'_, Arc<AppState>>,
+    path: String,
+    format: ExportFormat,
+) -> Result<usize, String> {
+    let rows = state.export_price_rows().await;
+    let content = match format {
+        ExportFormat::Csv => export::prices_csv(&rows),
+        ExportFormat::Json => export::to_json(ExportKind::Prices, &rows)?,
+    };
+    export::write(&path, format, &content)?;
+    info!("Exported {} prices to {}", rows.len(), path);
+    Ok(rows.len())
+}
+
+/// Предпросмотр импорта истории сессий (путь выбирается диалогом открытия): ошибки и конфликты
+#[tauri::command]
+pub async fn preview_session_import(
+    state: State<'_, Arc<AppState>>,
+    path: String,
+    source: ImportSource,
+) -> Result<ImportPreview, String> {
+    state.preview_session_import(&path, &source).await
+}
+
+/// Импорт истории сессий (конфликты по умолчанию пропускаются)
+#[tauri::command]
+pub async fn import_sessions(
+    state: State<'_, Arc<AppState>>,
+    path: String,
+    source: ImportSource,
+    on_conflict: Option<ImportConflictPolicy>,
+) -> Result<ImportResult, String> {
+    state.import_sessions(&path, &source, on_conflict.unwrap_or_default()).await
+}
+
+/// Создать резервную копию данных (path = None — каталог backups, иначе путь из диалога сохранения)
+#[tauri::command]
+pub async fn create_backup(
+    state: State<'_, Arc<AppState>>,
+    path: Option<String>,
+) -> Result<BackupInfo, String> {
+    state.create_backup(BackupReason::Manual, path.as_deref()).await
+}
+
+/// Резервные копии в каталоге backups, новые первыми
+#[tauri::command]
+pub async fn list_backups() -> Result<Vec<BackupInfo>, String> {
+    crate::backup::list().map_err(|e| e.to_string())
+}
+
+/// Проверить архив копии без восстановления
+#[tauri::command]
+pub async fn inspect_backup(path: String) -> Result<BackupInfo, String> {
+    crate::backup::inspect(std::path::Path::new(&path)).map_err(|e| e.to_string())
+}
+
+/// Восстановить данные из копии и перезапустить приложение (состояние в памяти устарело)
+#[tauri::command]
+pub async fn restore_backup(
+    state: State<'_, Arc<AppState>>,
+    app_handle: tauri::AppHandle,
+    path: String,
+) -> Result<(), String> {
+    let restored = state.restore_backup(&path).await?;
+    info!("Restored backup from {}, restarting", restored.created_at);
+    app_handle.restart()
+}
+
+/// Все ценовые оповещения
+#[tauri::command]
+pub async fn get_price_alerts(
+    state: State<'_, Arc<AppState>>,
+) -> Result<Vec<PriceAlert>, String> {
+    Ok(state.get_price_alerts().await)
+}
+
+/// Создать или обновить ценовое оповещение
+#[tauri::command]
+pub async fn save_price_alert(
+    state: State<'_, Arc<AppState>>,
+    alert: PriceAlert,
+) -> Result<PriceAlert, String> {
+    state.save_price_alert(alert).await
+}
+
+/// Удалить ценовое оповещение
+#[tauri::command]
+pub async fn delete_price_alert(
+    state: State<'_, Arc<AppState>>,
+    id: String,
+) -> Result<bool, String> {
+    state.delete_price_alert(&id).await
+}
+
+/// Прайсчеки в карантине (подозрительные цены)
+#[tauri::command]
+pub async fn get_quarantined_prices(
+    state: State<'_, Arc<AppState>>,
+) -> Result<Vec<QuarantinedPrice>, String> {
+    Ok(state.get_quarantined_prices().await)
+}
+
+/// Подтвердить цену из карантина: применить и отправить в crowd данные
+#[tauri::command]
+pub async fn confirm_quarantined_price(
+    state: State<'_, Arc<AppState>>,
+    id: String,
+) -> Result<QuarantinedPrice, String> {
+    let entry = state.confirm_quarantined_price(&id).await?;
+    let sb_cfg = state.resolve_supabase_config().await;
+    crate::log_pipeline::upload_price_check(
+        &state,
+        &reqwest::Client::new(),
+        sb_cfg,
+        entry.game_id,
+        entry.prices.clone(),
+        entry.currency_id,
+    )
+    .await;
+    Ok(entry)
+}
+
+/// Подтвердить все прайсчеки предмета из карантина; в crowd данные — только последний
+#[tauri::command]
+pub async fn confirm_quarantined_item(
+    state: State<'_, Arc<AppState>>,
+    game_id: i64,
+) -> Result<Vec<QuarantinedPrice>, String> {
+    let confirmed = state.confirm_quarantined_item(game_id).await?;
+    if let Some(latest) = confirmed.last() {
+        let sb_cfg = state.resolve_supabase_config().await;
+        crate::log_pipeline::upload_price_check(
+            &state,
+            &reqwest::Client::new(),
+            sb_cfg,
+            latest.game_id,
+            latest.prices.clone(),
+            latest.currency_id,
+        )
+        .await;
+    }
+    Ok(confirmed)
+}
+
+/// Отклонить цену из карантина
+#[tauri::command]
+pub async fn reject_quarantined_price(
+    state: State<'_, Arc<AppState>>,
+    id: String,
+) -> Result<QuarantinedPrice, String> {
+    state.reject_quarantined_price(&id).await
+}
+
+/// Валюта отображения и её курс (по умолчанию — из настроек)
+#[tauri::command]
+pub async fn get_denomination(
+    state: State<'_, Arc<AppState>>,
+    denomination: Option<i64>,
+) -> Result<Denomination, String> {
+    let game_id = match denomination {
+        Some(id) => Some(id),
+        None => state.settings.read().await.display_denomination,
+    };
+    state.resolve_denomination(game_id).await
+}
+
 /// Получить путь к логам
 #[tauri::command]
 pub async fn get_log_path(
     state: State<'_, Arc<AppState>>,
+    source_id: Option<String>,
 ) -> Result<Option<String>, String> {
-    Ok(state.get_log_path().await)
+    let source = state.source(source_id.as_deref()).await;
+    Ok(state.get_log_path(&source).await)
 }
 
 /// Проверить статус лог-файла: существует ли, когда последний раз изменялся
 #[tauri::command]
 pub async fn check_log_status(
     state: State<'_, Arc<AppState>>,
+    source_id: Option<String>,
 ) -> Result<LogFileStatus, String> {
-    let log_path = state.get_log_path().await;
+    let source = state.source(source_id.as_deref()).await;
+    let log_path = state.get_log_path(&source).await;
     
     match log_path {
         None => Ok(LogFileStatus {
@@ -419,6 +1068,8 @@ pub async fn open_url(url: String) -> Result<(), String> {
 pub struct AuthStatus {
     pub is_logged_in: bool,
     pub email: Option<String>,
+    /// Гостевые сессии, которые можно перенести в аккаунт (0 — не предлагать)
+    pub guest_sessions_to_merge: usize,
 }
 
 /// Получить статус авторизации
@@ -426,12 +1077,26 @@ pub struct AuthStatus {
 pub async fn auth_status(state: State<'_, Arc<AppState>>) -> Result<AuthStatus, String> {
     let is_logged_in = state.is_logged_in().await;
     let email = state.get_auth_email().await;
+    let guest_sessions_to_merge = state.guest_history_pending().await;
     Ok(AuthStatus {
         is_logged_in,
         email,
+        guest_sessions_to_merge,
     })
 }
 
+/// Перенести гостевую историю сессий в аккаунт
+#[tauri::command]
+pub async fn merge_guest_history(state: State<'_, Arc<AppState>>) -> Result<usize, String> {
+    state.merge_guest_history().await
+}
+
+/// Не переносить гостевую историю в текущий аккаунт (больше не предлагать)
+#[tauri::command]
+pub async fn decline_guest_history_merge(state: State<'_, Arc<AppState>>) -> Result<(), String> {
+    state.decline_guest_history_merge().await
+}
+
 /// Войти через kripika.com (единая точка входа)
 #[tauri::command]
 pub async fn auth_sign_in_kripika(state: State<'_, Arc<AppState>>) -> Result<AuthStatus, String> {
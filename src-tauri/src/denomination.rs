@@ -0,0 +1,51 @@
+//! Пересчёт значений в другую валюту отображения
+//!
+//! Все значения считаются в базовой валюте (Flame Elementium). Для отображения
+//! их можно перевести в любой предмет с известной ценой (например, Divinity)
+//! по курсу из кэша цен. Там же — перевод цен прайсчеков в других валютах.
+
+use crate::persistence::SessionHistoryRecord;
+use crate::types::{AggregatedDrop, Denomination, SessionStats};
+
+/// currency_id базовой валюты в прайсчеках (Flame Elementium)
+pub const BASE_CURRENCY_ID: i64 = 100300;
+
+fn convert(value: f64, d: &Denomination) -> f64 {
+    value / d.rate
+}
+
+/// Перевести статистику сессии
+pub fn convert_stats(stats: &mut SessionStats, d: &Denomination) {
+    stats.total_value = convert(stats.total_value, d);
+    stats.net_value = convert(stats.net_value, d);
+    stats.auction_fee = convert(stats.auction_fee, d);
+    stats.hourly_profit = convert(stats.hourly_profit, d);
+    stats.net_hourly_profit = convert(stats.net_hourly_profit, d);
+    stats.denomination = Some(d.clone());
+}
+
+/// Перевести строки дропа
+pub fn convert_drops(drops: &mut [AggregatedDrop], d: &Denomination) {
+    for drop in drops {
+        drop.unit_price = convert(drop.unit_price, d);
+        drop.total_value = convert(drop.total_value, d);
+        drop.net_value = convert(drop.net_value, d);
+    }
+}
+
+/// Перевести запись истории сессий
+pub fn convert_history(record: &mut SessionHistoryRecord, d: &Denomination) {
+    record.total_profit = convert(record.total_profit, d);
+    record.total_expenses = convert(record.total_expenses, d);
+    record.total_income = convert(record.total_income, d);
+    record.net_income = record.net_income.map(|v| convert(v, d));
+    for v in record.drop_values.values_mut() {
+        v.unit_price = convert(v.unit_price, d);
+        v.total_value = convert(v.total_value, d);
+        v.net_value = convert(v.net_value, d);
+    }
+    for t in &mut record.income_by_source {
+        t.gross_value = convert(t.gross_value, d);
+        t.net_value = convert(t.net_value, d);
+    }
+}
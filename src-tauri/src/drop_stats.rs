@@ -0,0 +1,247 @@
+//! Статистика дропа по истории: частота предметов по зонам и пресетам
+//!
+//! При завершении сессии её законченные карты складываются в накопители
+//! (зона, пресет). По накопителям считаются ставки «за карту» и «в час»
+//! с 95% доверительным интервалом по выборочной дисперсии дропа на карту.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::types::{
+    DropCorrectionKind, DropRateBucket, ExpectedDrop, FarmSessionState, ItemDropRate, ItemDropTally, ItemInfo, RateEstimate,
+    ZoneDropStats,
+};
+
+/// z для двустороннего 95% интервала
+const Z_95: f64 = 1.96;
+
+/// Предметы, количество которых в картах не совпадает с реальным: исключённые
+/// из подсчёта и исправленные вручную (правка не привязана к карте)
+fn corrected_items(session: &FarmSessionState) -> HashSet<i64> {
+    session.drop_corrections
+        .iter()
+        .filter(|c| c.kind == DropCorrectionKind::AdjustQuantity)
+        .map(|c| c.game_id)
+        .chain(session.excluded_items.iter().copied())
+        .collect()
+}
+
+/// Сложить законченные карты сессии в накопители (зона = сцена карты)
+pub fn record_session(buckets: &mut Vec<DropRateBucket>, session: &FarmSessionState) {
+    let skipped = corrected_items(session);
+    let mut touched: Vec<usize> = Vec::new();
+    for run in &session.map_runs {
+        let Some(exited_at) = run.exited_at else {
+            continue;
+        };
+        if run.scene_name.is_empty() {
+            continue;
+        }
+        let idx = match buckets
+            .iter()
+            .position(|b| b.zone == run.scene_name && b.preset_id == session.preset_id)
+        {
+            Some(i) => i,
+            None => {
+                buckets.push(DropRateBucket {
+                    zone: run.scene_name.clone(),
+                    preset_id: session.preset_id.clone(),
+                    ..Default::default()
+                });
+                buckets.len() - 1
+            }
+        };
+        let bucket = &mut buckets[idx];
+        bucket.maps += 1;
+        bucket.duration_sec += (exited_at - run.entered_at).num_seconds().max(0);
+        for (game_id, qty) in &run.drops {
+            if *qty <= 0 || skipped.contains(game_id) {
+                continue;
+            }
+            let tally = bucket.items.entry(*game_id).or_default();
+            tally.total += *qty as i64;
+            tally.sum_sq += (*qty as f64) * (*qty as f64);
+            tally.maps_with_drop += 1;
+        }
+        if !touched.contains(&idx) {
+            touched.push(idx);
+        }
+    }
+    for idx in touched {
+        buckets[idx].sessions += 1;
+    }
+}
+
+/// Объединить накопители (например, все пресеты одной зоны)
+pub fn merge<'a>(buckets: impl IntoIterator<Item = &'a DropRateBucket>) -> DropRateBucket {
+    let mut merged = DropRateBucket::default();
+    for b in buckets {
+        if merged.zone.is_empty() {
+            merged.zone = b.zone.clone();
+            merged.preset_id = b.preset_id.clone();
+        } else if merged.preset_id != b.preset_id {
+            merged.preset_id = None;
+        }
+        merged.sessions += b.sessions;
+        merged.maps += b.maps;
+        merged.duration_sec += b.duration_sec;
+        for (game_id, t) in &b.items {
+            let m = merged.items.entry(*game_id).or_default();
+            m.total += t.total;
+            m.sum_sq += t.sum_sq;
+            m.maps_with_drop += t.maps_with_drop;
+        }
+    }
+    merged
+}
+
+/// Среднее количество за карту с 95% интервалом (нужно минимум 2 карты)
+fn per_map_rate(tally: &ItemDropTally, maps: i64) -> RateEstimate {
+    if maps <= 0 {
+        return RateEstimate::default();
+    }
+    let n = maps as f64;
+    let mean = tally.total as f64 / n;
+    if maps < 2 {
+        return RateEstimate { value: mean, ci_low: None, ci_high: None };
+    }
+    // Несмещённая дисперсия по картам (карты без дропа — нули)
+    let variance = ((tally.sum_sq - n * mean * mean) / (n - 1.0)).max(0.0);
+    let half = Z_95 * (variance / n).sqrt();
+    RateEstimate {
+        value: mean,
+        ci_low: Some((mean - half).max(0.0)),
+        ci_high: Some(mean + half),
+    }
+}
+
+fn scale(rate: &RateEstimate, k: f64) -> RateEstimate {
+    RateEstimate {
+        value: rate.value * k,
+        ci_low: rate.ci_low.map(|v| v * k),
+        ci_high: rate.ci_high.map(|v| v * k),
+    }
+}
+
+/// Ставки по предметам накопителя, по убыванию среднего за карту
+pub fn zone_stats(bucket: &DropRateBucket, items_cache: &HashMap<i64, ItemInfo>) -> ZoneDropStats {
+    let hours = bucket.duration_sec as f64 / 3600.0;
+    let maps_per_hour = if hours > 0.0 { bucket.maps as f64 / hours } else { 0.0 };
+
+    let mut items: Vec<ItemDropRate> = bucket
+        .items
+        .iter()
+        .map(|(game_id, tally)| {
+            let per_map = per_map_rate(tally, bucket.maps);
+            ItemDropRate {
+                game_id: *game_id,
+                item_info: items_cache.get(game_id).cloned(),
+                total_quantity: tally.total,
+                maps_with_drop: tally.maps_with_drop,
+                drop_chance: if bucket.maps > 0 { tally.maps_with_drop as f64 / bucket.maps as f64 } else { 0.0 },
+                per_hour: scale(&per_map, maps_per_hour),
+                per_map,
+            }
+        })
+        .collect();
+    items.sort_by(|a, b| b.per_map.value.partial_cmp(&a.per_map.value).unwrap_or(std::cmp::Ordering::Equal));
+
+    ZoneDropStats {
+        zone: bucket.zone.clone(),
+        preset_id: bucket.preset_id.clone(),
+        sessions: bucket.sessions,
+        maps: bucket.maps,
+        duration_sec: bucket.duration_sec,
+        items,
+    }
+}
+
+/// Ожидаемый дроп за `maps` карт (интервал — для среднего, а не для одного прогона)
+pub fn expected_drops(bucket: &DropRateBucket, maps: i32, items_cache: &HashMap<i64, ItemInfo>) -> Vec<ExpectedDrop> {
+    zone_stats(bucket, items_cache)
+        .items
+        .into_iter()
+        .map(|r| ExpectedDrop {
+            game_id: r.game_id,
+            item_info: r.item_info,
+            expected: scale(&r.per_map, maps as f64),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+    use crate::types::{DropCorrection, MapRun};
+
+    fn run(zone: &str, minutes: i64, drops: &[(i64, i32)]) -> MapRun {
+        let entered_at = Utc::now();
+        MapRun {
+            scene_name: zone.to_string(),
+            entered_at,
+            exited_at: Some(entered_at + Duration::minutes(minutes)),
+            drops: drops.iter().cloned().collect(),
+        }
+    }
+
+    #[test]
+    fn test_record_session_groups_by_zone_and_skips_open_map() {
+        let mut session = FarmSessionState { preset_id: Some("p1".into()), ..Default::default() };
+        session.map_runs = vec![
+            run("ZoneA", 3, &[(1, 2)]),
+            run("ZoneA", 3, &[]),
+            run("ZoneB", 6, &[(1, 1), (2, 5)]),
+        ];
+        let mut open = run("ZoneA", 1, &[(1, 10)]);
+        open.exited_at = None;
+        session.map_runs.push(open);
+
+        let mut buckets = Vec::new();
+        record_session(&mut buckets, &session);
+
+        assert_eq!(buckets.len(), 2);
+        let a = buckets.iter().find(|b| b.zone == "ZoneA").unwrap();
+        assert_eq!(a.maps, 2);
+        assert_eq!(a.sessions, 1);
+        assert_eq!(a.duration_sec, 360);
+        assert_eq!(a.items[&1].total, 2);
+        assert_eq!(a.items[&1].maps_with_drop, 1);
+    }
+
+    #[test]
+    fn test_record_session_skips_corrected_items() {
+        let session = FarmSessionState {
+            map_runs: vec![run("ZoneA", 3, &[(1, 2), (2, 7), (3, 1)])],
+            excluded_items: [2].into_iter().collect(),
+            drop_corrections: vec![DropCorrection {
+                at: Utc::now(),
+                kind: DropCorrectionKind::AdjustQuantity,
+                game_id: 3,
+                quantity_before: 1,
+                quantity_after: 0,
+                undone: None,
+            }],
+            ..Default::default()
+        };
+
+        let mut buckets = Vec::new();
+        record_session(&mut buckets, &session);
+
+        assert_eq!(buckets[0].maps, 1);
+        assert_eq!(buckets[0].items.keys().copied().collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn test_per_map_rate_confidence_interval() {
+        // 4 карты: 2, 0, 2, 0 → среднее 1, дисперсия 4/3
+        let tally = ItemDropTally { total: 4, sum_sq: 8.0, maps_with_drop: 2 };
+        let rate = per_map_rate(&tally, 4);
+        assert!((rate.value - 1.0).abs() < 1e-9);
+        let half = Z_95 * (4.0f64 / 3.0 / 4.0).sqrt();
+        assert!((rate.ci_high.unwrap() - (1.0 + half)).abs() < 1e-9);
+        assert_eq!(rate.ci_low, Some(0.0));
+
+        // Одна карта — интервал не считается
+        assert!(per_map_rate(&tally, 1).ci_low.is_none());
+    }
+}
@@ -0,0 +1,271 @@
+//! Шина доменных событий (tokio broadcast)
+//!
+//! AppState публикует события после изменения состояния, а подписчики
+//! (отправка во frontend, сохранение на диск, будущие интеграции) работают
+//! независимо друг от друга и от цикла обработки логов.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::broadcast;
+use log::{debug, error, warn};
+
+use crate::state::AppState;
+use crate::types::{
+    AutoPauseEvent, GoalProgress, ItemDropEvent, LogEvent, MapChangeEvent, PauseKind, PriceAlertFired, QuarantinedPrice,
+    SessionStats,
+};
+
+const EVENT_BUS_CAPACITY: usize = 1024;
+/// Окно склейки пачки событий: один пересчёт статистики / одна запись на диск на пачку
+const COALESCE_WINDOW_MS: u64 = 250;
+
+/// Доменное событие приложения
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DomainEvent {
+    /// Событие разобрано из лога — публикуется всегда, даже без сессии и на паузе
+    LogParsed { source_id: String, event: LogEvent },
+    /// Дроп записан в сессию
+    DropAdded { source_id: String, drop: ItemDropEvent },
+    /// Вход на карту
+    MapEntered { source_id: String, event: MapChangeEvent },
+    /// Выход в убежище — карта завершена (map_index как в MapRunStats.index)
+    MapCompleted { source_id: String, event: MapChangeEvent, map_index: i32 },
+    /// Цена обновлена прайсчеком из лога
+    PriceUpdated { game_id: i64, price: f64 },
+    /// Цены слиты из remote источника
+    PricesMerged { updated: usize, game_ids: Vec<i64> },
+    SessionStarted { source_id: String },
+    SessionEnded { source_id: String },
+    /// Пауза поставлена/снята (kind = Idle — автопауза по бездействию)
+    Paused { source_id: String, paused: bool, kind: PauseKind, at: DateTime<Utc> },
+    /// Прочие изменения сессии (траты, ручной дроп, правки дропа)
+    SessionUpdated { source_id: String },
+    /// Цель сессии достигнута
+    GoalReached { source_id: String, progress: GoalProgress },
+    /// Сработало ценовое оповещение
+    PriceAlertFired { fired: PriceAlertFired },
+    /// Прайсчек отправлен в карантин и ждёт решения пользователя
+    PriceQuarantined { entry: QuarantinedPrice },
+}
+
+impl DomainEvent {
+    /// Источник логов, к сессии которого относится событие
+    pub fn source_id(&self) -> Option<&str> {
+        match self {
+            DomainEvent::LogParsed { source_id, .. }
+            | DomainEvent::DropAdded { source_id, .. }
+            | DomainEvent::MapEntered { source_id, .. }
+            | DomainEvent::MapCompleted { source_id, .. }
+            | DomainEvent::SessionStarted { source_id }
+            | DomainEvent::SessionEnded { source_id }
+            | DomainEvent::Paused { source_id, .. }
+            | DomainEvent::SessionUpdated { source_id }
+            | DomainEvent::GoalReached { source_id, .. } => Some(source_id),
+            DomainEvent::PriceUpdated { .. }
+            | DomainEvent::PricesMerged { .. }
+            | DomainEvent::PriceAlertFired { .. }
+            | DomainEvent::PriceQuarantined { .. } => None,
+        }
+    }
+}
+
+/// Шина событий: публикация не блокирует и не падает, если подписчиков нет
+pub struct EventBus {
+    tx: broadcast::Sender<DomainEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        Self { tx }
+    }
+
+    pub fn publish(&self, event: DomainEvent) {
+        // Err = нет подписчиков, это нормально
+        let _ = self.tx.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DomainEvent> {
+        self.tx.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Пачка событий, пришедших за окно склейки
+struct Batch {
+    events: Vec<DomainEvent>,
+    /// Подписчик отстал и часть событий потеряна — нужно обновить всё
+    lagged: bool,
+}
+
+/// Дождаться события и добрать всё, что придёт за окно склейки. None — шина закрыта.
+async fn recv_batch(rx: &mut broadcast::Receiver<DomainEvent>) -> Option<Batch> {
+    let mut batch = Batch { events: Vec::new(), lagged: false };
+    match rx.recv().await {
+        Ok(ev) => batch.events.push(ev),
+        Err(broadcast::error::RecvError::Lagged(n)) => {
+            warn!("Event subscriber lagged, {} events skipped", n);
+            batch.lagged = true;
+        }
+        Err(broadcast::error::RecvError::Closed) => return None,
+    }
+    tokio::time::sleep(Duration::from_millis(COALESCE_WINDOW_MS)).await;
+    loop {
+        match rx.try_recv() {
+            Ok(ev) => batch.events.push(ev),
+            Err(broadcast::error::TryRecvError::Lagged(_)) => batch.lagged = true,
+            Err(_) => break,
+        }
+    }
+    Some(batch)
+}
+
+/// Статистика конкретного источника (для UI с несколькими клиентами)
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceStatsPayload {
+    pub source_id: String,
+    pub stats: SessionStats,
+}
+
+/// Подписчик: события во frontend + пересчёт статистики один раз на пачку
+pub fn spawn_tauri_bridge(app_handle: AppHandle, state: Arc<AppState>) {
+    let mut rx = state.events.subscribe();
+    tauri::async_runtime::spawn(async move {
+        while let Some(batch) = recv_batch(&mut rx).await {
+            let mut dirty: HashSet<String> = HashSet::new();
+            // Цены или пропуск событий меняют статистику всех источников
+            let mut all_dirty = batch.lagged;
+
+            for ev in &batch.events {
+                let result = match ev {
+                    // item-drop / map-change / price-update — на каждое событие лога, как раньше
+                    DomainEvent::LogParsed { event, .. } => match event {
+                        LogEvent::ItemDrop(drop) => app_handle.emit("item-drop", drop),
+                        LogEvent::MapChange(map) => app_handle.emit("map-change", map),
+                        LogEvent::PriceSearch(price) => app_handle.emit("price-update", price),
+                    },
+                    DomainEvent::PriceUpdated { .. } | DomainEvent::PricesMerged { .. } => {
+                        all_dirty = true;
+                        Ok(())
+                    }
+                    DomainEvent::Paused { source_id, paused, kind: PauseKind::Idle, at } => {
+                        let payload = AutoPauseEvent { source_id: source_id.clone(), paused: *paused, at: *at };
+                        app_handle.emit("auto-pause", &payload)
+                    }
+                    DomainEvent::GoalReached { progress, .. } => app_handle.emit("goal-reached", progress),
+                    DomainEvent::PriceAlertFired { fired } => app_handle.emit("price-alert", fired),
+                    DomainEvent::PriceQuarantined { entry } => app_handle.emit("price-quarantined", entry),
+                    _ => Ok(()),
+                };
+                if let Err(e) = result {
+                    error!("Failed to emit event to frontend: {}", e);
+                }
+                if let Some(id) = ev.source_id() {
+                    dirty.insert(id.to_string());
+                }
+            }
+
+            let selected = state.selected_source.read().await.clone();
+            // Статистика во frontend — в валюте отображения из настроек
+            let denomination = state.display_denomination(None).await.ok().flatten();
+            for source in state.all_sources().await {
+                if !all_dirty && !dirty.contains(&source.id) {
+                    continue;
+                }
+                let mut stats = state.get_session_stats(&source).await;
+                if let Some(d) = &denomination {
+                    crate::denomination::convert_stats(&mut stats, d);
+                }
+                debug!("Emitting stats-update: source={}, items={}, maps={}", source.id, stats.total_items, stats.maps_completed);
+                // stats-update — для выбранного в UI источника (как раньше)
+                if source.id == selected {
+                    if let Err(e) = app_handle.emit("stats-update", &stats) {
+                        error!("Failed to emit stats-update event: {}", e);
+                    }
+                }
+                let payload = SourceStatsPayload { source_id: source.id.clone(), stats };
+                if let Err(e) = app_handle.emit("source-stats-update", &payload) {
+                    error!("Failed to emit source-stats-update event: {}", e);
+                }
+            }
+        }
+    });
+}
+
+/// Подписчик: сохранение сессий и кэша цен на диск (одна запись на пачку)
+pub fn spawn_persistence(state: Arc<AppState>) {
+    let mut rx = state.events.subscribe();
+    tauri::async_runtime::spawn(async move {
+        while let Some(batch) = recv_batch(&mut rx).await {
+            let mut sessions: HashSet<String> = HashSet::new();
+            let mut prices: HashSet<i64> = HashSet::new();
+            for ev in &batch.events {
+                match ev {
+                    // Дроп сохраняется сразу в add_drop
+                    DomainEvent::MapEntered { source_id, .. }
+                    | DomainEvent::MapCompleted { source_id, .. }
+                    | DomainEvent::SessionUpdated { source_id } => {
+                        sessions.insert(source_id.clone());
+                    }
+                    DomainEvent::PriceUpdated { game_id, .. } => {
+                        prices.insert(*game_id);
+                    }
+                    DomainEvent::PricesMerged { game_ids, .. } => prices.extend(game_ids),
+                    _ => {}
+                }
+            }
+
+            for source in state.all_sources().await {
+                if batch.lagged || sessions.contains(&source.id) {
+                    state.persist_session(&source).await;
+                }
+            }
+            // Пропуск событий — неизвестно, что изменилось, пишем все цены
+            if batch.lagged {
+                state.persist_prices(None).await;
+            } else if !prices.is_empty() {
+                state.persist_prices(Some(&prices)).await;
+            }
+        }
+    });
+}
+
+/// Подписчик: проверка целей сессии после изменений дропа, карт и цен
+pub fn spawn_goal_tracker(state: Arc<AppState>) {
+    let mut rx = state.events.subscribe();
+    tauri::async_runtime::spawn(async move {
+        while let Some(batch) = recv_batch(&mut rx).await {
+            let mut sources: HashSet<String> = HashSet::new();
+            let mut all = batch.lagged;
+            for ev in &batch.events {
+                match ev {
+                    DomainEvent::DropAdded { source_id, .. }
+                    | DomainEvent::MapCompleted { source_id, .. }
+                    | DomainEvent::SessionUpdated { source_id } => {
+                        sources.insert(source_id.clone());
+                    }
+                    DomainEvent::PriceUpdated { .. } | DomainEvent::PricesMerged { .. } => all = true,
+                    _ => {}
+                }
+            }
+            if sources.is_empty() && !all {
+                continue;
+            }
+            for source in state.all_sources().await {
+                if all || sources.contains(&source.id) {
+                    state.check_goals(&source).await;
+                }
+            }
+        }
+    });
+}
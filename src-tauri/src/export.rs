@@ -0,0 +1,320 @@
+//! Экспорт истории сессий, детализации сессии и кэша цен в CSV / JSON
+//!
+//! Схема колонок описана в docs/EXPORT_FORMAT.md и не меняется без смены
+//! EXPORT_VERSION: новые колонки добавляются только в конец. CSV — RFC 4180
+//! (запятая, CRLF, UTF-8 с BOM для Excel), JSON — конверт с версией формата.
+
+use std::collections::HashMap;
+use std::path::Path;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::freshness;
+use crate::persistence::{PersistedPriceEntry, SessionHistoryRecord};
+use crate::price_history;
+use crate::types::{
+    ExportFormat, ExportKind, FreshnessRules, ItemInfo, MapRun, PriceExportRow, PriceObservation, PriceOrigin, SessionItemKind, SessionItemLine,
+};
+
+/// Идентификатор формата в JSON конверте
+pub const EXPORT_FORMAT_ID: &str = "tli-companion-export";
+pub const EXPORT_VERSION: u32 = 1;
+
+pub const SESSIONS_COLUMNS: &[&str] = &[
+    "session_id",
+    "started_at",
+    "ended_at",
+    "duration_sec",
+    "maps_completed",
+    "total_income",
+    "net_income",
+    "total_expenses",
+    "total_profit",
+    "profit_per_hour",
+    "preset_id",
+];
+
+pub const SESSION_ITEMS_COLUMNS: &[&str] = &[
+    "session_id",
+    "kind",
+    "game_id",
+    "name",
+    "category",
+    "quantity",
+    "unit_price",
+    "total_value",
+];
+
+pub const PRICES_COLUMNS: &[&str] = &[
+    "game_id",
+    "name",
+    "category",
+    "price",
+    "updated_at",
+    "origin",
+    "is_current_league",
+    "league_name",
+    "is_stale",
+    "confidence",
+];
+
+/// JSON конверт экспорта
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportEnvelope<T> {
+    pub format: String,
+    pub version: u32,
+    pub kind: ExportKind,
+    pub exported_at: DateTime<Utc>,
+    pub data: T,
+}
+
+/// Данные JSON экспорта одной сессии
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionExport {
+    pub session: SessionHistoryRecord,
+    #[serde(default)]
+    pub map_runs: Vec<MapRun>,
+    pub items: Vec<SessionItemLine>,
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_table(columns: &[&str], rows: impl IntoIterator<Item = Vec<String>>) -> String {
+    let mut out = String::from("\u{feff}");
+    out.push_str(&columns.join(","));
+    out.push_str("\r\n");
+    for row in rows {
+        debug_assert_eq!(row.len(), columns.len());
+        let fields: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&fields.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+fn time(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn opt<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+fn kind_name(kind: SessionItemKind) -> &'static str {
+    match kind {
+        SessionItemKind::Drop => "drop",
+        SessionItemKind::ManualDrop => "manual_drop",
+        SessionItemKind::Expense => "expense",
+    }
+}
+
+fn origin_name(origin: PriceOrigin) -> &'static str {
+    match origin {
+        PriceOrigin::PriceCheck => "price_check",
+        PriceOrigin::Manual => "manual",
+        PriceOrigin::Crowd => "crowd",
+        PriceOrigin::PreviousLeague => "previous_league",
+    }
+}
+
+pub fn sessions_csv(records: &[SessionHistoryRecord]) -> String {
+    csv_table(SESSIONS_COLUMNS, records.iter().map(|r| {
+        let profit_per_hour = if r.total_duration_sec > 0 {
+            r.total_profit / r.total_duration_sec as f64 * 3600.0
+        } else {
+            0.0
+        };
+        vec![
+            r.id.clone(),
+            time(r.started_at),
+            time(r.ended_at),
+            r.total_duration_sec.to_string(),
+            r.maps_completed.to_string(),
+            r.total_income.to_string(),
+            opt(r.net_income),
+            r.total_expenses.to_string(),
+            r.total_profit.to_string(),
+            profit_per_hour.to_string(),
+            r.preset_id.clone().unwrap_or_default(),
+        ]
+    }))
+}
+
+pub fn session_items_csv(session_id: &str, items: &[SessionItemLine]) -> String {
+    csv_table(SESSION_ITEMS_COLUMNS, items.iter().map(|i| {
+        vec![
+            session_id.to_string(),
+            kind_name(i.kind).to_string(),
+            opt(i.game_id),
+            i.name.clone(),
+            i.category.clone().unwrap_or_default(),
+            i.quantity.to_string(),
+            opt(i.unit_price),
+            opt(i.total_value),
+        ]
+    }))
+}
+
+pub fn prices_csv(rows: &[PriceExportRow]) -> String {
+    csv_table(PRICES_COLUMNS, rows.iter().map(|p| {
+        vec![
+            p.game_id.to_string(),
+            p.name.clone().unwrap_or_default(),
+            p.category.clone().unwrap_or_default(),
+            p.price.to_string(),
+            time(p.updated_at),
+            origin_name(p.origin).to_string(),
+            p.is_current_league.to_string(),
+            p.league_name.clone().unwrap_or_default(),
+            p.is_stale.to_string(),
+            p.confidence.to_string(),
+        ]
+    }))
+}
+
+pub fn to_json<T: Serialize>(kind: ExportKind, data: T) -> Result<String, String> {
+    let envelope = ExportEnvelope {
+        format: EXPORT_FORMAT_ID.to_string(),
+        version: EXPORT_VERSION,
+        kind,
+        exported_at: Utc::now(),
+        data,
+    };
+    serde_json::to_string_pretty(&envelope).map_err(|e| e.to_string())
+}
+
+/// Расширение файла экспорта
+fn extension(format: ExportFormat) -> &'static str {
+    match format {
+        ExportFormat::Csv => "csv",
+        ExportFormat::Json => "json",
+    }
+}
+
+/// Путь экспорта из IPC: только .csv / .json по формату, чтобы не затереть произвольный файл
+fn validate_path(path: &Path, format: ExportFormat) -> Result<(), String> {
+    if path.as_os_str().is_empty() {
+        return Err("Export path is empty".to_string());
+    }
+    let expected = extension(format);
+    if !path.extension().is_some_and(|e| e.eq_ignore_ascii_case(expected)) {
+        return Err(format!("Export file must have a .{} extension", expected));
+    }
+    Ok(())
+}
+
+/// Записать файл экспорта (путь выбирается диалогом сохранения на фронте)
+pub fn write(path: &str, format: ExportFormat, content: &str) -> Result<(), String> {
+    let path = Path::new(path);
+    validate_path(path, format)?;
+    std::fs::write(path, content).map_err(|e| e.to_string())
+}
+
+/// Детализация сессии: авто-дроп по оценке при завершении, ручной дроп, расходы
+pub(crate) fn session_items(
+    record: &SessionHistoryRecord,
+    items: &HashMap<i64, ItemInfo>,
+    history: &HashMap<i64, Vec<PriceObservation>>,
+) -> Vec<SessionItemLine> {
+    let mut drops: Vec<(&i64, &i32)> = record.drops.iter().collect();
+    drops.sort_unstable_by_key(|(id, _)| **id);
+
+    let mut lines = Vec::with_capacity(drops.len() + record.manual_drops.len() + record.expenses.len());
+    for (game_id, qty) in drops {
+        let item = items.get(game_id);
+        // Оценка из записи (как вошла в доход); старые записи — по истории цен на момент завершения
+        let (unit_price, total_value) = match record.drop_values.get(game_id) {
+            Some(v) => (Some(v.unit_price), Some(v.total_value)),
+            None => {
+                let unit_price = if item.is_some_and(|i| i.is_base_currency) {
+                    Some(1.0)
+                } else {
+                    history.get(game_id).and_then(|s| price_history::price_at(s, record.ended_at)).map(|o| o.price)
+                };
+                (unit_price, unit_price.map(|p| p * *qty as f64))
+            }
+        };
+        lines.push(SessionItemLine {
+            kind: SessionItemKind::Drop,
+            game_id: Some(*game_id),
+            name: item.map(|i| i.name.clone()).unwrap_or_else(|| game_id.to_string()),
+            category: item.map(|i| i.category.clone()),
+            quantity: *qty,
+            unit_price,
+            total_value,
+        });
+    }
+    let manual = record.manual_drops.iter()
+        .map(|d| (SessionItemKind::ManualDrop, d.game_id, &d.name, d.quantity, d.price));
+    let expenses = record.expenses.iter()
+        .map(|e| (SessionItemKind::Expense, e.game_id, &e.name, e.quantity, e.price));
+    for (kind, game_id, name, quantity, price) in manual.chain(expenses) {
+        lines.push(SessionItemLine {
+            kind,
+            game_id,
+            name: name.clone(),
+            category: game_id.and_then(|id| items.get(&id)).map(|i| i.category.clone()),
+            quantity,
+            unit_price: Some(price),
+            total_value: Some(price * quantity as f64),
+        });
+    }
+    lines
+}
+
+/// Строки экспорта кэша цен (по game_id)
+pub(crate) fn price_rows(
+    prices: &HashMap<i64, PersistedPriceEntry>,
+    items: &HashMap<i64, ItemInfo>,
+    rules: &FreshnessRules,
+    now: DateTime<Utc>,
+) -> Vec<PriceExportRow> {
+    let mut rows: Vec<PriceExportRow> = prices.iter().map(|(game_id, entry)| {
+        let item = items.get(game_id);
+        let fresh = freshness::assess(entry, *game_id, item, rules, now);
+        PriceExportRow {
+            game_id: *game_id,
+            name: item.map(|i| i.name.clone()),
+            category: item.map(|i| i.category.clone()),
+            price: entry.price,
+            updated_at: entry.updated_at,
+            origin: fresh.origin,
+            is_current_league: entry.is_current_league,
+            league_name: entry.league_name.clone(),
+            is_stale: fresh.is_stale,
+            confidence: fresh.confidence,
+        }
+    }).collect();
+    rows.sort_unstable_by_key(|r| r.game_id);
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_quoting_and_header() {
+        let csv = csv_table(&["a", "b"], vec![
+            vec!["plain".to_string(), "with, comma".to_string()],
+            vec!["say \"hi\"".to_string(), String::new()],
+        ]);
+        assert_eq!(csv, "\u{feff}a,b\r\nplain,\"with, comma\"\r\n\"say \"\"hi\"\"\",\r\n");
+        assert!(sessions_csv(&[]).ends_with(&format!("{}\r\n", SESSIONS_COLUMNS.join(","))));
+    }
+
+    #[test]
+    fn test_export_path_extension() {
+        assert!(validate_path(Path::new("/tmp/sessions.csv"), ExportFormat::Csv).is_ok());
+        assert!(validate_path(Path::new("/tmp/sessions.JSON"), ExportFormat::Json).is_ok());
+        assert!(validate_path(Path::new("/tmp/sessions.json"), ExportFormat::Csv).is_err());
+        assert!(validate_path(Path::new("/home/user/.bashrc"), ExportFormat::Csv).is_err());
+        assert!(validate_path(Path::new(""), ExportFormat::Json).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+//! Versioned envelope and migration chains for the JSON files in the data directory
+//!
+//! Every file is written as `{ "version": N, "data": ... }`. Older layouts (the
+//! per-file wrappers like `{ "version": 1, "prices": ... }` and the unversioned
+//! files, treated as version 0) are upgraded step by step through the file's
+//! migration chain before deserializing. Files that cannot be read are moved aside
+//! to `<name>.<timestamp>.corrupt` so the next save starts clean and the data stays
+//! inspectable. Files written by a newer build are left in place and never overwritten.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Upgrades a whole file from version `from` to `from + 1`
+pub(crate) type Migration = fn(Value) -> Result<Value, String>;
+
+/// Layout of one persisted file type
+pub(crate) struct FileFormat {
+    /// Used in log messages
+    pub name: &'static str,
+    /// Version written by the current build
+    pub version: u32,
+    /// Ordered chain: (from version, upgrade to from + 1)
+    pub migrations: &'static [(u32, Migration)],
+}
+
+/// Files written by a newer build, found while loading: saves must not downgrade them
+static NEWER_FILES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+#[derive(Serialize)]
+struct EnvelopeRef<'a, T: ?Sized> {
+    version: u32,
+    data: &'a T,
+}
+
+#[derive(Deserialize)]
+struct Envelope<T> {
+    data: T,
+}
+
+/// File version: the `version` field of an object, 0 for unversioned files
+fn detect_version(value: &Value) -> u32 {
+    value.get("version").and_then(Value::as_u64).map(|v| v as u32).unwrap_or(0)
+}
+
+/// Shared last step for the old per-file wrappers: `{ version, <field> }` -> `{ version, data }`
+pub(crate) fn rename_payload(mut value: Value, field: &str, to_version: u32) -> Result<Value, String> {
+    let data = value.get_mut(field)
+        .map(Value::take)
+        .ok_or_else(|| format!("Missing `{}`", field))?;
+    Ok(json!({ "version": to_version, "data": data }))
+}
+
+/// Parse and upgrade file content. Returns the payload and the version it was stored with.
+pub(crate) fn decode<T: DeserializeOwned>(content: &str, format: &FileFormat) -> Result<(T, u32), String> {
+    let mut value: Value = serde_json::from_str(content).map_err(|e| e.to_string())?;
+    let stored = detect_version(&value);
+    if stored > format.version {
+        return Err(format!("{} v{} is newer than supported v{}", format.name, stored, format.version));
+    }
+
+    let mut version = stored;
+    while version < format.version {
+        let (_, migrate) = format.migrations.iter()
+            .find(|(from, _)| *from == version)
+            .ok_or_else(|| format!("No migration for {} v{}", format.name, version))?;
+        value = migrate(value)?;
+        version += 1;
+    }
+
+    let envelope: Envelope<T> = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    Ok((envelope.data, stored))
+}
+
+pub(crate) fn encode<T: Serialize + ?Sized>(data: &T, format: &FileFormat) -> io::Result<String> {
+    serde_json::to_string(&EnvelopeRef { version: format.version, data })
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// `<name>.<timestamp>.corrupt` next to the file, so earlier quarantined copies are kept
+pub(crate) fn corrupt_path(path: &Path, at: DateTime<Utc>) -> PathBuf {
+    let mut name = path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+    name.push(format!(".{}.corrupt", at.format("%Y%m%d-%H%M%S%3f")));
+    path.with_file_name(name)
+}
+
+/// Whether the file was found to be written by a newer build (saves must skip it)
+pub(crate) fn written_by_newer_build(path: &Path) -> bool {
+    NEWER_FILES.lock().unwrap().iter().any(|p| p == path)
+}
+
+fn is_newer(content: &str, format: &FileFormat) -> bool {
+    serde_json::from_str::<Value>(content)
+        .map(|value| detect_version(&value) > format.version)
+        .unwrap_or(false)
+}
+
+/// Load a file. None — no file. Unreadable content is moved to `.corrupt` and reported as InvalidData.
+/// A file from a newer build stays in place, is reported as Unsupported and is protected from saves.
+pub(crate) fn load<T: DeserializeOwned>(path: &Path, format: &FileFormat) -> io::Result<Option<T>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path)?;
+    match decode(&content, format) {
+        Ok((data, _)) => Ok(Some(data)),
+        Err(e) if is_newer(&content, format) => {
+            warn!("Not loading {} ({}), the file is kept and will not be overwritten", format.name, e);
+            let mut newer = NEWER_FILES.lock().unwrap();
+            if !newer.iter().any(|p| p == path) {
+                newer.push(path.to_path_buf());
+            }
+            Err(io::Error::new(io::ErrorKind::Unsupported, format!("{}: {}", format.name, e)))
+        }
+        Err(e) => {
+            let target = corrupt_path(path, Utc::now());
+            warn!("Failed to load {} ({}), moving it to {}", format.name, e, target.display());
+            fs::rename(path, &target)?;
+            Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", format.name, e)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrap(value: Value) -> Result<Value, String> {
+        Ok(json!({ "version": 1, "items": value }))
+    }
+
+    fn rename(value: Value) -> Result<Value, String> {
+        rename_payload(value, "items", 2)
+    }
+
+    const FORMAT: FileFormat = FileFormat {
+        name: "test",
+        version: 2,
+        migrations: &[(0, wrap), (1, rename)],
+    };
+
+    #[test]
+    fn test_migration_chain_and_quarantine() {
+        assert_eq!(decode::<Vec<i32>>("[1, 2]", &FORMAT).unwrap(), (vec![1, 2], 0));
+        assert_eq!(decode::<Vec<i32>>(r#"{"version":1,"items":[3]}"#, &FORMAT).unwrap(), (vec![3], 1));
+        assert_eq!(decode::<Vec<i32>>(&encode(&vec![4], &FORMAT).unwrap(), &FORMAT).unwrap(), (vec![4], 2));
+        assert!(decode::<Vec<i32>>(r#"{"version":3,"data":[]}"#, &FORMAT).is_err());
+
+        let dir = std::env::temp_dir().join(format!("tli-schema-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.json");
+        fs::write(&path, "{ not json").unwrap();
+        assert!(load::<Vec<i32>>(&path, &FORMAT).is_err());
+        assert!(!path.exists());
+        assert!(load::<Vec<i32>>(&path, &FORMAT).unwrap().is_none());
+        // A second corrupt file does not replace the first quarantined copy
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        fs::write(&path, "{ still not json").unwrap();
+        assert!(load::<Vec<i32>>(&path, &FORMAT).is_err());
+        let corrupt: Vec<String> = fs::read_dir(&dir).unwrap()
+            .map(|e| fs::read_to_string(e.unwrap().path()).unwrap())
+            .collect();
+        assert_eq!(corrupt.len(), 2);
+        assert!(corrupt.contains(&"{ not json".to_string()));
+
+        // A file from a newer build stays in place and is protected from saves
+        let newer = dir.join("newer.json");
+        fs::write(&newer, r#"{"version":3,"data":[]}"#).unwrap();
+        assert_eq!(load::<Vec<i32>>(&newer, &FORMAT).unwrap_err().kind(), io::ErrorKind::Unsupported);
+        assert!(newer.exists());
+        assert!(written_by_newer_build(&newer));
+        assert!(!written_by_newer_build(&path));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -73,7 +73,8 @@ impl LogWatcher {
     }
     
     /// Запустить отслеживание файла
-    /// Возвращает канал для получения событий
+    /// Возвращает канал для получения событий (файл не открылся — канал сразу закрыт,
+    /// is_running() = false)
     pub fn start(&self) -> mpsc::Receiver<LogEvent> {
         let (tx, rx) = mpsc::channel(1000);
         
@@ -82,6 +83,16 @@ impl LogWatcher {
         let running = self.running.clone();
         let parser = self.parser.clone();
         
+        // Открываем файл до запуска потока, чтобы is_running() сразу отражал реальное состояние
+        let file = match File::open(&log_path) {
+            Ok(f) => f,
+            Err(e) => {
+                error!("Failed to open log file: {}", e);
+                *running.lock().unwrap() = false;
+                return rx;
+            }
+        };
+
         // Устанавливаем флаг работы
         *running.lock().unwrap() = true;
         
@@ -89,15 +100,6 @@ impl LogWatcher {
         thread::spawn(move || {
             info!("Starting log watcher for: {}", log_path.display());
             
-            // Открываем файл и переходим в конец (не читаем историю)
-            let file = match File::open(&log_path) {
-                Ok(f) => f,
-                Err(e) => {
-                    error!("Failed to open log file: {}", e);
-                    return;
-                }
-            };
-            
             let mut reader = BufReader::new(file);
 
             // Warm-up: читаем небольшой хвост файла, чтобы собрать baseline по слотам,
@@ -250,7 +252,6 @@ impl LogWatcher {
     }
     
     /// Проверить, работает ли watcher
-    #[allow(dead_code)]
     pub fn is_running(&self) -> bool {
         *self.running.lock().unwrap()
     }
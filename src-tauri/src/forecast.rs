@@ -0,0 +1,184 @@
+//! Прогноз итогов сессии и прогресс целей
+//!
+//! Считается по темпу текущей сессии из SessionStats: доход и карты за активное
+//! время (без пауз). ETA — в активном времени, от текущего момента.
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::types::{ForecastTarget, Goal, GoalKind, GoalProgress, SessionForecast, SessionStats};
+
+fn per_hour(value: f64, duration_sec: i32) -> f64 {
+    if duration_sec > 0 {
+        value / duration_sec as f64 * 3600.0
+    } else {
+        0.0
+    }
+}
+
+/// Прогноз дохода сессии к моменту/длительности или к числу карт
+pub fn project(stats: &SessionStats, target: &ForecastTarget, now: DateTime<Utc>) -> Result<SessionForecast, String> {
+    let duration = stats.duration_sec.max(0);
+    let maps_per_hour = per_hour(stats.maps_completed as f64, duration);
+
+    let remaining_sec: i64 = match target {
+        ForecastTarget::Duration { duration_sec } => (*duration_sec - duration as i64).max(0),
+        // Время на стене: считаем, что пауз до этого момента не будет
+        ForecastTarget::At { at } => (*at - now).num_seconds().max(0),
+        ForecastTarget::Maps { maps } => {
+            let remaining_maps = (*maps - stats.maps_completed).max(0);
+            if remaining_maps == 0 {
+                0
+            } else if maps_per_hour > 0.0 {
+                (remaining_maps as f64 / maps_per_hour * 3600.0).round() as i64
+            } else {
+                return Err("No completed maps yet, map pace is unknown".to_string());
+            }
+        }
+    };
+
+    let remaining_h = remaining_sec as f64 / 3600.0;
+    let hourly_value = per_hour(stats.total_value, duration);
+    let net_hourly_value = per_hour(stats.net_value, duration);
+    Ok(SessionForecast {
+        remaining_sec,
+        projected_duration_sec: duration as i64 + remaining_sec,
+        projected_maps: stats.maps_completed as f64 + maps_per_hour * remaining_h,
+        projected_value: stats.total_value + hourly_value * remaining_h,
+        projected_net_value: stats.net_value + net_hourly_value * remaining_h,
+        hourly_value,
+        net_hourly_value,
+        maps_per_hour,
+        finish_at: now + Duration::seconds(remaining_sec),
+    })
+}
+
+/// Текущее значение цели: доход после комиссии, карты или количество предмета
+pub fn goal_current(goal: &Goal, stats: &SessionStats, item_quantity: i64) -> f64 {
+    match goal.kind {
+        GoalKind::Income => stats.net_value,
+        GoalKind::Maps => stats.maps_completed as f64,
+        GoalKind::Item => item_quantity as f64,
+    }
+}
+
+/// Прогресс и ETA цели по темпу сессии
+pub fn goal_progress(
+    goal: &Goal,
+    source_id: &str,
+    stats: &SessionStats,
+    item_quantity: i64,
+    now: DateTime<Utc>,
+) -> GoalProgress {
+    let current = goal_current(goal, stats, item_quantity);
+    let remaining = (goal.target - current).max(0.0);
+    let rate_per_hour = per_hour(current, stats.duration_sec);
+    let eta_sec = if remaining <= 0.0 {
+        Some(0)
+    } else if rate_per_hour > 0.0 {
+        Some((remaining / rate_per_hour * 3600.0).round() as i64)
+    } else {
+        None
+    };
+    GoalProgress {
+        goal: goal.clone(),
+        source_id: source_id.to_string(),
+        current,
+        remaining,
+        progress_pct: if goal.target > 0.0 { (current / goal.target * 100.0).clamp(0.0, 100.0) } else { 100.0 },
+        rate_per_hour,
+        eta_sec,
+        eta_at: eta_sec.map(|s| now + Duration::seconds(s)),
+        reached: remaining <= 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Час активного времени: 4 карты, 1000 до комиссии, 900 после
+    fn stats() -> SessionStats {
+        SessionStats { duration_sec: 3600, maps_completed: 4, total_value: 1000.0, net_value: 900.0, ..Default::default() }
+    }
+
+    fn goal(kind: GoalKind, target: f64) -> Goal {
+        Goal {
+            id: "g".into(),
+            name: String::new(),
+            kind,
+            target,
+            game_id: None,
+            source_id: None,
+            created_at: Utc::now(),
+            reached: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_project_duration() {
+        let now = Utc::now();
+        let f = project(&stats(), &ForecastTarget::Duration { duration_sec: 3 * 3600 }, now).unwrap();
+        assert_eq!(f.remaining_sec, 2 * 3600);
+        assert_eq!(f.projected_duration_sec, 3 * 3600);
+        assert_eq!(f.projected_maps, 12.0);
+        assert_eq!(f.projected_value, 3000.0);
+        assert_eq!(f.projected_net_value, 2700.0);
+        assert_eq!(f.finish_at, now + Duration::hours(2));
+
+        // Цель уже пройдена — прогноз равен текущему
+        let f = project(&stats(), &ForecastTarget::Duration { duration_sec: 600 }, now).unwrap();
+        assert_eq!(f.remaining_sec, 0);
+        assert_eq!(f.projected_value, 1000.0);
+    }
+
+    #[test]
+    fn test_project_at() {
+        let now = Utc::now();
+        let f = project(&stats(), &ForecastTarget::At { at: now + Duration::minutes(30) }, now).unwrap();
+        assert_eq!(f.remaining_sec, 1800);
+        assert_eq!(f.projected_maps, 6.0);
+        assert_eq!(f.projected_value, 1500.0);
+
+        let f = project(&stats(), &ForecastTarget::At { at: now - Duration::minutes(30) }, now).unwrap();
+        assert_eq!(f.remaining_sec, 0);
+        assert_eq!(f.finish_at, now);
+    }
+
+    #[test]
+    fn test_project_maps() {
+        let now = Utc::now();
+        let f = project(&stats(), &ForecastTarget::Maps { maps: 10 }, now).unwrap();
+        assert_eq!(f.remaining_sec, 90 * 60);
+        assert_eq!(f.projected_maps, 10.0);
+        assert_eq!(f.projected_value, 2500.0);
+        assert_eq!(project(&stats(), &ForecastTarget::Maps { maps: 2 }, now).unwrap().remaining_sec, 0);
+
+        // Карт ещё нет — темп неизвестен
+        let empty = SessionStats { maps_completed: 0, ..stats() };
+        assert!(project(&empty, &ForecastTarget::Maps { maps: 10 }, now).is_err());
+        assert_eq!(project(&empty, &ForecastTarget::Maps { maps: 0 }, now).unwrap().remaining_sec, 0);
+    }
+
+    #[test]
+    fn test_goal_eta() {
+        let now = Utc::now();
+        let p = goal_progress(&goal(GoalKind::Income, 1800.0), "main", &stats(), 0, now);
+        assert_eq!(p.remaining, 900.0);
+        assert_eq!(p.progress_pct, 50.0);
+        assert_eq!(p.eta_sec, Some(3600));
+        assert_eq!(p.eta_at, Some(now + Duration::hours(1)));
+        assert!(!p.reached);
+
+        let p = goal_progress(&goal(GoalKind::Maps, 4.0), "main", &stats(), 0, now);
+        assert!(p.reached);
+        assert_eq!(p.eta_sec, Some(0));
+
+        // Нулевой темп — ETA неизвестна
+        let p = goal_progress(&goal(GoalKind::Item, 5.0), "main", &stats(), 0, now);
+        assert_eq!(p.rate_per_hour, 0.0);
+        assert_eq!(p.eta_sec, None);
+        assert_eq!(p.eta_at, None);
+        let idle = SessionStats { duration_sec: 0, ..stats() };
+        assert_eq!(goal_progress(&goal(GoalKind::Income, 1800.0), "main", &idle, 0, now).eta_sec, None);
+    }
+}
@@ -0,0 +1,166 @@
+//! Политика свежести цен
+//!
+//! TTL цены задаётся для предмета, категории или глобально; источник цены
+//! (свой прайсчек, crowd, предыдущая лига) масштабирует TTL. Доверие к цене
+//! падает вдвое за каждый эффективный TTL: на границе устаревания оно 0.5.
+
+use chrono::{DateTime, Utc};
+
+use crate::persistence::PersistedPriceEntry;
+use crate::types::{FreshnessRules, ItemInfo, PriceObservationSource, PriceOrigin};
+
+/// Свежесть цены из кэша
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Freshness {
+    pub origin: PriceOrigin,
+    pub is_stale: bool,
+    /// 0..1
+    pub confidence: f64,
+}
+
+/// Источник цены в кэше (для записей без source — по стратегии выбора)
+pub(crate) fn origin(entry: &PersistedPriceEntry) -> PriceOrigin {
+    if !entry.is_current_league {
+        return PriceOrigin::PreviousLeague;
+    }
+    match entry.source {
+        Some(PriceObservationSource::PriceCheck) => PriceOrigin::PriceCheck,
+        Some(PriceObservationSource::Manual) => PriceOrigin::Manual,
+        Some(PriceObservationSource::Remote) => PriceOrigin::Crowd,
+        None if entry.strategy.is_some() => PriceOrigin::PriceCheck,
+        None => PriceOrigin::Crowd,
+    }
+}
+
+/// TTL предмета: предмет > категория > общий
+pub(crate) fn ttl_sec(rules: &FreshnessRules, game_id: i64, item: Option<&ItemInfo>) -> i64 {
+    if let Some(ttl) = rules.items.get(&game_id) {
+        return *ttl;
+    }
+    item.and_then(|i| rules.categories.get(&i.category))
+        .copied()
+        .unwrap_or(rules.default_ttl_sec)
+}
+
+pub(crate) fn assess(
+    entry: &PersistedPriceEntry,
+    game_id: i64,
+    item: Option<&ItemInfo>,
+    rules: &FreshnessRules,
+    now: DateTime<Utc>,
+) -> Freshness {
+    let origin = origin(entry);
+    let weight = match origin {
+        PriceOrigin::PriceCheck => rules.source_weights.price_check,
+        PriceOrigin::Manual => rules.source_weights.manual,
+        PriceOrigin::Crowd => rules.source_weights.crowd,
+        PriceOrigin::PreviousLeague => rules.source_weights.previous_league,
+    };
+    let effective_ttl = ttl_sec(rules, game_id, item) as f64 * weight;
+    if effective_ttl <= 0.0 {
+        return Freshness { origin, is_stale: true, confidence: 0.0 };
+    }
+    let age = (now - entry.updated_at).num_seconds().max(0) as f64;
+    Freshness {
+        origin,
+        is_stale: age > effective_ttl,
+        confidence: 0.5f64.powf(age / effective_ttl) * weight.min(1.0),
+    }
+}
+
+/// Проверка политики свежести
+pub(crate) fn validate(rules: &FreshnessRules) -> Result<(), String> {
+    let ttls = std::iter::once(&rules.default_ttl_sec)
+        .chain(rules.categories.values())
+        .chain(rules.items.values());
+    for ttl in ttls {
+        if *ttl <= 0 {
+            return Err("Price TTL must be positive".to_string());
+        }
+    }
+    let w = &rules.source_weights;
+    if [w.price_check, w.manual, w.crowd, w.previous_league].iter().any(|w| !w.is_finite() || *w < 0.0) {
+        return Err("Source weight must be a non-negative number".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn item(game_id: i64, category: &str) -> ItemInfo {
+        ItemInfo {
+            game_id,
+            name: format!("item {}", game_id),
+            name_en: None,
+            name_ru: None,
+            name_cn: None,
+            category: category.into(),
+            icon_url: None,
+            is_base_currency: false,
+        }
+    }
+
+    fn entry(age_sec: i64, source: PriceObservationSource, now: DateTime<Utc>) -> PersistedPriceEntry {
+        PersistedPriceEntry {
+            price: 10.0,
+            updated_at: now - Duration::seconds(age_sec),
+            is_current_league: true,
+            league_name: None,
+            strategy: None,
+            source: Some(source),
+        }
+    }
+
+    fn rules() -> FreshnessRules {
+        FreshnessRules {
+            default_ttl_sec: 3600,
+            categories: [("mat".to_string(), 600)].into_iter().collect(),
+            items: [(7, 60)].into_iter().collect(),
+            source_weights: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_ttl_precedence() {
+        let rules = rules();
+        assert_eq!(ttl_sec(&rules, 7, Some(&item(7, "mat"))), 60);
+        assert_eq!(ttl_sec(&rules, 7, None), 60);
+        assert_eq!(ttl_sec(&rules, 8, Some(&item(8, "mat"))), 600);
+        assert_eq!(ttl_sec(&rules, 9, Some(&item(9, "other"))), 3600);
+        assert_eq!(ttl_sec(&rules, 9, None), 3600);
+    }
+
+    #[test]
+    fn test_assess_weights_and_boundary() {
+        let now = Utc::now();
+        let mut rules = rules();
+        let mat = item(8, "mat");
+
+        // На границе TTL доверие 0.5 и цена ещё не устарела
+        let f = assess(&entry(600, PriceObservationSource::PriceCheck, now), 8, Some(&mat), &rules, now);
+        assert_eq!(f.origin, PriceOrigin::PriceCheck);
+        assert!(!f.is_stale);
+        assert!((f.confidence - 0.5).abs() < 1e-9);
+        assert!(assess(&entry(601, PriceObservationSource::PriceCheck, now), 8, Some(&mat), &rules, now).is_stale);
+
+        // Вес crowd 0.5: TTL вдвое короче, доверие ниже вдвое
+        let f = assess(&entry(300, PriceObservationSource::Remote, now), 8, Some(&mat), &rules, now);
+        assert_eq!(f.origin, PriceOrigin::Crowd);
+        assert!(!f.is_stale);
+        assert!((f.confidence - 0.25).abs() < 1e-9);
+        assert!(assess(&entry(301, PriceObservationSource::Remote, now), 8, Some(&mat), &rules, now).is_stale);
+
+        let mut previous = entry(0, PriceObservationSource::PriceCheck, now);
+        previous.is_current_league = false;
+        assert_eq!(assess(&previous, 8, Some(&mat), &rules, now).origin, PriceOrigin::PreviousLeague);
+
+        // Вес 0 — цена источника всегда устаревшая
+        rules.source_weights.manual = 0.0;
+        let f = assess(&entry(0, PriceObservationSource::Manual, now), 8, Some(&mat), &rules, now);
+        assert!(f.is_stale);
+        assert_eq!(f.confidence, 0.0);
+    }
+}
@@ -8,11 +8,27 @@ pub mod file_watcher;
 pub mod state;
 pub mod commands;
 pub mod persistence;
+pub mod file_schema;
 pub mod supabase_sync;
 pub mod auth;
 pub mod supabase_defaults;
+pub mod log_pipeline;
+pub mod events;
+pub mod drop_stats;
+pub mod forecast;
+pub mod price_selection;
+pub mod price_history;
+pub mod price_alerts;
+pub mod price_guard;
+pub mod freshness;
+pub mod denomination;
+pub mod store;
+pub mod export;
+pub mod session_import;
+pub mod backup;
+pub mod valuation;
 
 pub use types::*;
 pub use log_parser::LogParser;
 pub use file_watcher::{LogWatcher, find_log_path};
-pub use state::AppState;
+pub use state::{AppState, LogSource};
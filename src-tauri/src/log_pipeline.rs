@@ -0,0 +1,165 @@
+//! Обработка событий лога: watcher → AppState
+//!
+//! Для каждого источника логов (клиента игры) запускается свой watcher и своя
+//! задача-обработчик. Цены общие, сессии — у каждого источника свои.
+//! Frontend и диск узнают об изменениях через шину событий (см. events).
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use log::{info, debug};
+
+use crate::state::{AppState, LogSource};
+use crate::file_watcher::LogWatcher;
+use crate::events::DomainEvent;
+use crate::types::{LogEvent, MapEventType, PriceSearchEvent};
+use crate::supabase_sync::{self, SupabaseConfig};
+
+// Rate limiting для crowd price upload
+// Максимум 10 запросов в 60 секунд на пользователя
+const PRICE_UPLOAD_RATE_LIMIT: u64 = 10;
+const PRICE_UPLOAD_WINDOW_SEC: u64 = 60;
+
+struct RateLimiter {
+    window_start: AtomicU64,
+    count: AtomicU64,
+}
+
+impl RateLimiter {
+    fn new() -> Self {
+        Self {
+            window_start: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    fn check_and_increment(&self) -> bool {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let window = self.window_start.load(Ordering::Relaxed);
+
+        // Новое окно если прошло больше WINDOW секунд
+        if now - window >= PRICE_UPLOAD_WINDOW_SEC {
+            self.window_start.store(now, Ordering::Relaxed);
+            self.count.store(1, Ordering::Relaxed);
+            return true;
+        }
+
+        // Проверяем лимит
+        let current = self.count.fetch_add(1, Ordering::Relaxed);
+        current < PRICE_UPLOAD_RATE_LIMIT
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref PRICE_RATE_LIMITER: RateLimiter = RateLimiter::new();
+}
+
+/// Crowd price upload (optional): если пользователь залогинен.
+/// Rate limited: максимум 10 запросов в минуту
+pub(crate) async fn upload_price_check(
+    state: &AppState,
+    http: &reqwest::Client,
+    sb_cfg: Option<SupabaseConfig>,
+    game_id: i64,
+    prices: Vec<f64>,
+    currency_id: i64,
+) {
+    if !PRICE_RATE_LIMITER.check_and_increment() {
+        debug!("Price upload rate limited, skipping");
+        return;
+    }
+    let Some(cfg) = sb_cfg else {
+        return;
+    };
+    let Some(jwt) = state.get_valid_access_token(http, &cfg).await else {
+        return;
+    };
+    let http = http.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = supabase_sync::upsert_market_price(
+            &http,
+            &cfg,
+            &jwt,
+            game_id,
+            &prices,
+            currency_id,
+        )
+        .await
+        {
+            debug!("Supabase upsert_market_price error: {}", e);
+        }
+    });
+}
+
+/// Прайсчек из лога: цена в кэш (или в карантин) и crowd upload
+async fn handle_price_search(
+    state: &AppState,
+    http: &reqwest::Client,
+    sb_cfg: &Option<SupabaseConfig>,
+    price: &PriceSearchEvent,
+) {
+    // Цены в другой валюте переводим в базовую по курсу из кэша
+    let Some(lot_prices) = state.lot_prices_in_base(price).await else {
+        debug!("Unknown rate for currency {}, skipping price check of {}", price.currency_id, price.game_id);
+        return;
+    };
+    if let Some((selected, strategy)) = state.select_market_price(price.game_id, &lot_prices).await {
+        // Подозрительная цена ждёт подтверждения: ни в кэш, ни в crowd upload
+        if state.quarantine_if_suspicious(price, selected, strategy).await {
+            return;
+        }
+        state.update_price(price.game_id, selected, Some(strategy)).await;
+        upload_price_check(state, http, sb_cfg.clone(), price.game_id, price.prices.clone(), price.currency_id).await;
+    }
+}
+
+/// Запустить watcher и обработку событий для источника логов.
+/// Watcher назначается источнику до возврата, чтобы is_watching сразу был актуален.
+pub fn start_source(
+    state: Arc<AppState>,
+    source: Arc<LogSource>,
+    log_path: PathBuf,
+) {
+    info!("Starting log watcher for source {}: {}", source.id, log_path.display());
+
+    // Перезапуск: останавливаем предыдущий watcher этого источника
+    source.stop_watcher();
+    let watcher = LogWatcher::new(log_path, source.log_parser.clone());
+    let mut rx = watcher.start();
+    *source.watcher.lock().unwrap() = Some(watcher);
+
+    tauri::async_runtime::spawn(async move {
+        let http = reqwest::Client::new();
+        let sb_cfg = state.resolve_supabase_config().await;
+
+        // Обрабатываем события из логов
+        while let Some(event) = rx.recv().await {
+            // Любое событие лога — активность игрока (прайсчек в городе тоже)
+            AppState::touch_activity(&source).await;
+            match &event {
+                LogEvent::ItemDrop(drop) => {
+                    state.add_drop(&source, drop).await;
+                }
+                LogEvent::PriceSearch(price) => {
+                    handle_price_search(&state, &http, &sb_cfg, price).await;
+                }
+                LogEvent::MapChange(map) => {
+                    // Вход на карту снимает автопаузу по бездействию
+                    if map.event_type == MapEventType::EnterMap {
+                        state.auto_resume(&source).await;
+                    }
+                    state.handle_map_change(&source, map).await;
+                }
+            }
+
+            // Frontend получает каждое событие лога, даже без сессии и на паузе
+            state.events.publish(DomainEvent::LogParsed { source_id: source.id.clone(), event });
+        }
+
+        info!("Log source {} stopped", source.id);
+    });
+}
@@ -15,85 +15,36 @@ mod file_watcher;
 mod state;
 mod commands;
 mod persistence;
+mod file_schema;
 mod supabase_sync;
 mod auth;
 mod supabase_defaults;
+mod log_pipeline;
+mod events;
+mod drop_stats;
+mod forecast;
+mod price_selection;
+mod price_history;
+mod price_alerts;
+mod price_guard;
+mod freshness;
+mod denomination;
+mod store;
+mod export;
+mod session_import;
+mod backup;
+mod valuation;
 
 use std::sync::Arc;
-use std::sync::Mutex;
-use std::sync::atomic::{AtomicU64, Ordering};
 use tauri::{Manager, Emitter};
 use tauri::menu::{Menu, MenuItem};
 use tauri::tray::{TrayIconBuilder, TrayIconEvent, MouseButton, MouseButtonState};
-use log::{info, warn, error, debug, LevelFilter};
+use log::{info, warn, debug, LevelFilter};
 use env_logger::Builder;
 
 use state::AppState;
-use file_watcher::{find_log_path, LogWatcher};
-use types::LogEvent;
-use log_parser::LogParser;
-
-// Rate limiting для crowd price upload
-// Максимум 10 запросов в 60 секунд на пользователя
-const PRICE_UPLOAD_RATE_LIMIT: u64 = 10;
-const PRICE_UPLOAD_WINDOW_SEC: u64 = 60;
-
-struct RateLimiter {
-    window_start: AtomicU64,
-    count: AtomicU64,
-}
-
-impl RateLimiter {
-    fn new() -> Self {
-        Self {
-            window_start: AtomicU64::new(0),
-            count: AtomicU64::new(0),
-        }
-    }
-    
-    fn check_and_increment(&self) -> bool {
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        
-        let window = self.window_start.load(Ordering::Relaxed);
-        
-        // Новое окно если прошло больше WINDOW секунд
-        if now - window >= PRICE_UPLOAD_WINDOW_SEC {
-            self.window_start.store(now, Ordering::Relaxed);
-            self.count.store(1, Ordering::Relaxed);
-            return true;
-        }
-        
-        // Проверяем лимит
-        let current = self.count.fetch_add(1, Ordering::Relaxed);
-        current < PRICE_UPLOAD_RATE_LIMIT
-    }
-}
-
-lazy_static::lazy_static! {
-    static ref PRICE_RATE_LIMITER: RateLimiter = RateLimiter::new();
-}
-
-fn select_market_price(prices: &[f64]) -> Option<f64> {
-    // В логах есть список unitPrices (обычно по одному значению на лот).
-    // Явного объёма/кол-ва на каждой цене в сообщении мы не видим, поэтому
-    // лучше брать низкий перцентиль вместо min, чтобы не ловить единичные манипуляции.
-    let mut v: Vec<f64> = prices
-        .iter()
-        .copied()
-        .filter(|p| p.is_finite() && *p > 0.0)
-        .collect();
-    if v.is_empty() {
-        return None;
-    }
-    v.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
-
-    // p20: для маленьких выборок это фактически min, для больших — устойчивее.
-    let idx = (((v.len() - 1) as f64) * 0.2).round() as usize;
-    v.get(idx).copied()
-}
+use file_watcher::find_log_path;
+use persistence::MAIN_SOURCE_ID;
 
 fn main() {
     // Загружаем .env (dev convenience). В релизе переменные обычно прокидываются на этапе сборки/CI.
@@ -119,11 +70,8 @@ fn main() {
         .setup(|app| {
             info!("Setting up application...");
             
-            // Общий парсер: нужен и watcher'у, и командам (сброс кэша при старте сессии).
-            let shared_parser = Arc::new(Mutex::new(LogParser::new()));
-
-            // Создаём глобальное состояние
-            let app_state = Arc::new(AppState::new(shared_parser.clone()));
+            // Создаём глобальное состояние (у каждого источника логов свой парсер и сессия)
+            let app_state = Arc::new(AppState::new());
             app.manage(app_state.clone());
             
             // Создаём меню для tray иконки
@@ -164,6 +112,11 @@ fn main() {
             
             // Получаем handle для отправки событий в frontend
             let app_handle = app.handle().clone();
+
+            // Подписчики шины событий: отправка во frontend и сохранение на диск
+            events::spawn_tauri_bridge(app_handle.clone(), app_state.clone());
+            events::spawn_persistence(app_state.clone());
+            events::spawn_goal_tracker(app_state.clone());
             
             // СИНХРОННАЯ инициализация: загрузка настроек, цен, и восстановление логина.
             // Это должно завершиться ДО того как UI начнёт делать запросы.
@@ -172,12 +125,29 @@ fn main() {
                 // Загружаем настройки (в т.ч. Supabase overrides) перед тем как запускать синк.
                 state_clone.load_settings_from_disk().await;
 
+                // Дополнительные клиенты (мультибокс) из настроек
+                state_clone.register_sources_from_settings().await;
+
+                // Локальная база SQLite (первый запуск — импорт JSON файлов)
+                state_clone.open_store().await;
+
+                // Пресеты стратегий
+                state_clone.load_presets_from_disk().await;
+                state_clone.load_valuation_rules_from_disk().await;
+                state_clone.load_drop_stats_from_disk().await;
+                state_clone.load_goals_from_disk().await;
+                state_clone.load_price_history_from_disk().await;
+                state_clone.load_price_alerts_from_disk().await;
+                state_clone.load_price_quarantine_from_disk().await;
+
                 // Восстанавливаем кэш цен (чтобы цены сохранялись между сессиями и перезапусками).
                 state_clone.load_prices_cache_from_disk().await;
                 
-                // Восстанавливаем активную сессию (если было аварийное закрытие)
-                if state_clone.load_session_from_disk().await {
-                    info!("Restored active session from previous run");
+                // Восстанавливаем активные сессии (если было аварийное закрытие)
+                for source in state_clone.all_sources().await {
+                    if state_clone.load_session_from_disk(&source).await {
+                        info!("Restored active session from previous run: source={}", source.id);
+                    }
                 }
 
                 let http = reqwest::Client::new();
@@ -255,6 +225,19 @@ fn main() {
                     });
                 }
 
+                // Heartbeat сессий: часы сессии идут на бэкенде, даже если webview свёрнут/завис
+                // + автопауза по бездействию в убежище
+                {
+                    let state_for_task = state_clone.clone();
+                    tauri::async_runtime::spawn(async move {
+                        loop {
+                            tokio::time::sleep(std::time::Duration::from_secs(state::SESSION_HEARTBEAT_SEC)).await;
+                            state_for_task.check_idle().await;
+                            state_for_task.tick_sessions().await;
+                        }
+                    });
+                }
+
                 // Периодический фоновый рефреш списка предметов (каждые 5 минут)
                 if let Some(_cfg) = sb_cfg.clone() {
                     let state_for_task = state_clone.clone();
@@ -292,90 +275,32 @@ fn main() {
                     find_log_path()
                 };
                 
+                let main_source = state_clone.source(Some(MAIN_SOURCE_ID)).await;
                 if let Some(log_path) = log_path_option {
                     let path_str = log_path.to_string_lossy().to_string();
-                    state_clone.set_log_path(Some(path_str.clone())).await;
-                    
-                    info!("Starting log watcher for: {}", path_str);
-                    
-                    // Создаём watcher
-                    let watcher = LogWatcher::new(log_path, shared_parser.clone());
-                    let mut rx = watcher.start();
-                    
-                    // Обрабатываем события из логов
-                    while let Some(event) = rx.recv().await {
-                        match &event {
-                            LogEvent::ItemDrop(drop) => {
-                                state_clone.add_drop(drop).await;
-                                
-                                // Отправляем событие в frontend
-                                info!("Emitting item-drop event: game_id={}", drop.game_id);
-                                if let Err(e) = app_handle.emit("item-drop", drop) {
-                                    error!("Failed to emit item-drop event: {}", e);
-                                }
-                            }
-                            LogEvent::PriceSearch(price) => {
-                                if let Some(selected) = select_market_price(&price.prices) {
-                                    state_clone.update_price(price.game_id, selected).await;
-
-                                    // Crowd price upload (optional): если пользователь залогинен.
-                                    // Rate limited: максимум 10 запросов в минуту
-                                    if PRICE_RATE_LIMITER.check_and_increment() {
-                                        if let Some(cfg) = sb_cfg.clone() {
-                                            let jwt = state_clone.get_valid_access_token(&http, &cfg).await;
-                                            if let Some(jwt) = jwt {
-                                                let prices = price.prices.clone();
-                                                let game_id = price.game_id;
-                                                let currency_id = price.currency_id;
-                                                let http2 = http.clone();
-                                                tauri::async_runtime::spawn(async move {
-                                                    if let Err(e) = supabase_sync::upsert_market_price(
-                                                        &http2,
-                                                        &cfg,
-                                                        &jwt,
-                                                        game_id,
-                                                        &prices,
-                                                        currency_id,
-                                                    )
-                                                    .await
-                                                    {
-                                                        debug!("Supabase upsert_market_price error: {}", e);
-                                                    }
-                                                });
-                                            } 
-                                        }
-                                    } else {
-                                        debug!("Price upload rate limited, skipping");
-                                    }
-                                }
-                                
-                                // Отправляем событие в frontend
-                                if let Err(e) = app_handle.emit("price-update", price) {
-                                    error!("Failed to emit price-update event: {}", e);
-                                }
-                            }
-                            LogEvent::MapChange(map) => {
-                                state_clone.handle_map_change(map).await;
-                                
-                                // Отправляем событие в frontend
-                                if let Err(e) = app_handle.emit("map-change", map) {
-                                    error!("Failed to emit map-change event: {}", e);
-                                }
-                            }
-                        }
-                        
-                        // Отправляем обновлённую статистику
-                        let stats = state_clone.get_session_stats().await;
-                        info!("Emitting stats-update: items={}, maps={}", stats.total_items, stats.maps_completed);
-                        if let Err(e) = app_handle.emit("stats-update", &stats) {
-                            error!("Failed to emit stats-update event: {}", e);
-                        }
-                    }
+                    state_clone.set_log_path(&main_source, Some(path_str)).await;
+                    log_pipeline::start_source(state_clone.clone(), main_source, log_path);
                 } else {
                     info!("Log file not found, waiting for manual configuration");
                     // Отправляем событие что нужно настроить путь
                     let _ = app_handle.emit("log-path-needed", ());
                 }
+
+                // Дополнительные источники логов
+                for source in state_clone.all_sources().await {
+                    if source.id == MAIN_SOURCE_ID {
+                        continue;
+                    }
+                    let Some(path) = state_clone.get_log_path(&source).await else {
+                        continue;
+                    };
+                    let path = std::path::PathBuf::from(path);
+                    if path.exists() {
+                        log_pipeline::start_source(state_clone.clone(), source, path);
+                    } else {
+                        warn!("Log file of source {} does not exist: {}", source.id, path.display());
+                    }
+                }
             });
             
             Ok(())
@@ -384,8 +309,13 @@ fn main() {
             commands::find_log_file,
             commands::set_log_path,
             commands::start_session,
+            commands::get_presets,
+            commands::save_preset,
+            commands::delete_preset,
+            commands::get_preset_comparison,
             commands::set_paused,
             commands::update_session_duration,
+            commands::get_pause_intervals,
             commands::add_expense,
             commands::remove_expense,
             commands::get_expenses,
@@ -394,11 +324,50 @@ fn main() {
             commands::add_manual_drop,
             commands::remove_manual_drop,
             commands::get_manual_drops,
+            commands::adjust_drop_quantity,
+            commands::set_drop_excluded,
+            commands::undo_last_drops,
+            commands::get_drop_corrections,
+            commands::get_valuation_rules,
+            commands::save_valuation_rules,
+            commands::get_income_ledger,
+            commands::get_drop_rate_stats,
+            commands::get_expected_drops,
+            commands::get_session_forecast,
+            commands::get_goals,
+            commands::save_goal,
+            commands::delete_goal,
+            commands::get_goal_progress,
+            commands::get_price_history,
+            commands::get_price_trend,
+            commands::get_price_volatility,
+            commands::get_session_value_at_prices,
+            commands::export_session_history,
+            commands::export_session,
+            commands::export_prices,
+            commands::preview_session_import,
+            commands::import_sessions,
+            commands::create_backup,
+            commands::list_backups,
+            commands::inspect_backup,
+            commands::restore_backup,
+            commands::get_price_alerts,
+            commands::save_price_alert,
+            commands::delete_price_alert,
+            commands::get_quarantined_prices,
+            commands::confirm_quarantined_price,
+            commands::confirm_quarantined_item,
+            commands::reject_quarantined_price,
+            commands::get_denomination,
             commands::end_session,
             commands::get_session_history,
             commands::delete_session_history,
             commands::get_session_stats,
             commands::get_drops,
+            commands::get_map_runs,
+            commands::get_map_run_summary,
+            commands::get_recent_drops,
+            commands::get_value_timeline,
             commands::is_session_active,
             commands::get_settings,
             commands::save_settings,
@@ -413,7 +382,15 @@ fn main() {
             commands::auth_sign_in_kripika,
             commands::auth_cancel_login,
             commands::auth_sign_out,
+            commands::merge_guest_history,
+            commands::decline_guest_history_merge,
             commands::get_my_profile,
+            commands::list_log_sources,
+            commands::add_log_source,
+            commands::remove_log_source,
+            commands::select_log_source,
+            commands::get_aggregate_stats,
+            commands::get_aggregate_drops,
         ])
         .on_window_event(|window, event| {
             // При закрытии окна - полностью выходим из приложения
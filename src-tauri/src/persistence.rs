@@ -5,18 +5,20 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use std::collections::HashMap;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use log::debug;
 
-use crate::types::{AppSettings, FarmSessionState};
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct PricesCacheFile {
-    pub version: u32,
-    pub prices: HashMap<i64, PersistedPriceEntry>,
-}
+use crate::file_schema::{self, rename_payload, FileFormat};
+use crate::types::{
+    AppSettings, DropRateBucket, DropRecord, ExpenseEntry, FarmSessionState, Goal, IncomeSourceTotal, ManualDropEntry,
+    PriceAlert, PriceObservation, PriceObservationSource, PriceStrategy, QuarantinedPrice, RecordedDropValue, StrategyPreset,
+    ValuationRules,
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PersistedPriceEntry {
@@ -28,17 +30,132 @@ pub struct PersistedPriceEntry {
     /// Название лиги (SS10, SS11, etc)
     #[serde(default)]
     pub league_name: Option<String>,
+    /// Strategy used to pick the price from the price-check lots (None for remote prices)
+    #[serde(default)]
+    pub strategy: Option<PriceStrategy>,
+    /// Where the price came from (None for entries saved before it was tracked)
+    #[serde(default)]
+    pub source: Option<PriceObservationSource>,
 }
 
 fn default_true() -> bool { true }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SettingsFile {
-    pub version: u32,
-    pub settings: AppSettings,
+// ─────────────────────────────────────────────────────────────────────────────
+// File formats (see file_schema): current version and migration chain per file
+// ─────────────────────────────────────────────────────────────────────────────
+
+/// v0: bare `game_id -> price` map, v1: `{ version: 1, prices: {} }` written on serialization failure,
+/// v2: `{ version: 2, prices: game_id -> entry }`, v3: envelope
+const PRICES_CACHE_FORMAT: FileFormat = FileFormat {
+    name: "prices cache",
+    version: 3,
+    migrations: &[
+        (0, prices_cache_v0_to_v1),
+        (1, |v| Ok(json!({ "version": 2, "prices": v.get("prices").cloned().unwrap_or_else(|| json!({})) }))),
+        (2, |v| rename_payload(v, "prices", 3)),
+    ],
+};
+
+/// v0: bare AppSettings, v1: `{ version: 1, settings }`, v2: envelope
+const SETTINGS_FORMAT: FileFormat = FileFormat {
+    name: "settings",
+    version: 2,
+    migrations: &[
+        (0, |v| Ok(json!({ "version": 1, "settings": v }))),
+        (1, |v| rename_payload(v, "settings", 2)),
+    ],
+};
+
+/// v0: bare FarmSessionState (drop_log may be missing), v1: envelope
+const ACTIVE_SESSION_FORMAT: FileFormat = FileFormat {
+    name: "active session",
+    version: 1,
+    migrations: &[(0, active_session_v0_to_v1)],
+};
+
+const SESSION_HISTORY_FORMAT: FileFormat = FileFormat {
+    name: "session history",
+    version: 2,
+    migrations: &[(1, |v| rename_payload(v, "sessions", 2))],
+};
+
+const PRESETS_FORMAT: FileFormat = FileFormat {
+    name: "presets",
+    version: 2,
+    migrations: &[(1, |v| rename_payload(v, "presets", 2))],
+};
+
+const VALUATION_RULES_FORMAT: FileFormat = FileFormat {
+    name: "valuation rules",
+    version: 2,
+    migrations: &[(1, |v| rename_payload(v, "rules", 2))],
+};
+
+const DROP_STATS_FORMAT: FileFormat = FileFormat {
+    name: "drop stats",
+    version: 2,
+    migrations: &[(1, |v| rename_payload(v, "buckets", 2))],
+};
+
+const PRICE_HISTORY_FORMAT: FileFormat = FileFormat {
+    name: "price history",
+    version: 2,
+    migrations: &[(1, |v| rename_payload(v, "items", 2))],
+};
+
+const PRICE_ALERTS_FORMAT: FileFormat = FileFormat {
+    name: "price alerts",
+    version: 2,
+    migrations: &[(1, |v| rename_payload(v, "alerts", 2))],
+};
+
+const PRICE_QUARANTINE_FORMAT: FileFormat = FileFormat {
+    name: "price quarantine",
+    version: 2,
+    migrations: &[(1, |v| rename_payload(v, "entries", 2))],
+};
+
+const GOALS_FORMAT: FileFormat = FileFormat {
+    name: "goals",
+    version: 2,
+    migrations: &[(1, |v| rename_payload(v, "goals", 2))],
+};
+
+/// Bare price map -> entries (no timestamp in the old format: treat as fresh)
+fn prices_cache_v0_to_v1(value: Value) -> Result<Value, String> {
+    let legacy: HashMap<i64, f64> = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    let now = Utc::now();
+    let prices: HashMap<i64, PersistedPriceEntry> = legacy
+        .into_iter()
+        .filter(|(_, p)| p.is_finite() && *p > 0.0)
+        .map(|(k, p)| (k, PersistedPriceEntry {
+            price: p,
+            updated_at: now,
+            is_current_league: true,
+            league_name: None,
+            strategy: None,
+            source: None,
+        }))
+        .collect();
+    Ok(json!({ "version": 1, "prices": prices }))
+}
+
+/// Old session files had no drop timeline: rebuild it from `drops`, stamped with the session start
+fn active_session_v0_to_v1(value: Value) -> Result<Value, String> {
+    let mut session: FarmSessionState = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    if session.drop_log.is_empty() && !session.drops.is_empty() {
+        let ts = session.started_at.unwrap_or_else(Utc::now);
+        let mut legacy: Vec<(i64, i32)> = session.drops.iter().map(|(k, v)| (*k, *v)).collect();
+        legacy.sort();
+        session.drop_log = legacy
+            .into_iter()
+            .map(|(game_id, quantity)| DropRecord { timestamp: ts, game_id, quantity, map_index: None, correction: false })
+            .collect();
+    }
+    Ok(json!({ "version": 1, "data": session }))
 }
 
-fn app_data_dir() -> Option<PathBuf> {
+pub(crate) fn app_data_dir() -> Option<PathBuf> {
     dirs::data_local_dir().map(|d| d.join("tli-companion"))
 }
 
@@ -50,40 +167,62 @@ fn settings_path() -> Option<PathBuf> {
     app_data_dir().map(|d| d.join("settings.json"))
 }
 
-fn session_path() -> Option<PathBuf> {
-    app_data_dir().map(|d| d.join("active_session.json"))
+/// ID основного источника логов (его сессия хранится в active_session.json, как раньше)
+pub const MAIN_SOURCE_ID: &str = "main";
+
+/// Оставляем только безопасные для имени файла символы
+fn sanitize_file_id(id: &str) -> String {
+    id.chars()
+        .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
+        .collect()
+}
+
+fn session_path(source_id: &str) -> Option<PathBuf> {
+    // Основной источник — старое имя файла (обратная совместимость),
+    // остальные — отдельный файл на источник, чтобы сессии не перетирали друг друга.
+    let file_name = if source_id == MAIN_SOURCE_ID {
+        "active_session.json".to_string()
+    } else {
+        format!("active_session_{}.json", sanitize_file_id(source_id))
+    };
+    app_data_dir().map(|d| d.join(file_name))
 }
 
 pub fn load_prices_cache() -> io::Result<HashMap<i64, PersistedPriceEntry>> {
     let Some(path) = prices_cache_path() else {
         return Ok(HashMap::new());
     };
-    if !path.exists() {
-        return Ok(HashMap::new());
-    }
+    Ok(file_schema::load(&path, &PRICES_CACHE_FORMAT)?.unwrap_or_default())
+}
 
-    let data = fs::read_to_string(&path)?;
-    // v2 format
-    if let Ok(parsed) = serde_json::from_str::<PricesCacheFile>(&data) {
-        return Ok(parsed.prices);
-    }
+/// Set after a backup restore: the files on disk are newer than the in-memory state,
+/// so every later save is dropped until the app restarts
+static WRITES_FROZEN: AtomicBool = AtomicBool::new(false);
 
-    // v1 legacy: game_id -> price (without timestamp)
-    let legacy: HashMap<i64, f64> = serde_json::from_str(&data).unwrap_or_default();
-    let now = Utc::now();
-    Ok(legacy
-        .into_iter()
-        .filter(|(_, p)| p.is_finite() && *p > 0.0)
-        .map(|(k, p)| (k, PersistedPriceEntry { 
-            price: p, 
-            updated_at: now,
-            is_current_league: true,
-            league_name: None,
-        }))
-        .collect())
+pub(crate) fn freeze_writes() {
+    WRITES_FROZEN.store(true, Ordering::SeqCst);
+}
+
+fn writes_frozen() -> bool {
+    WRITES_FROZEN.load(Ordering::SeqCst)
+}
+
+pub(crate) fn atomic_write(path: &Path, content: &str) -> io::Result<()> {
+    if writes_frozen() {
+        debug!("Writes are frozen until restart, skipping {}", path.display());
+        return Ok(());
+    }
+    if file_schema::written_by_newer_build(path) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} was written by a newer version, not overwriting it", path.display()),
+        ));
+    }
+    write_replacing(path, content)
 }
 
-fn atomic_write(path: &Path, content: &str) -> io::Result<()> {
+/// atomic_write without the freeze check (used by the restore itself)
+pub(crate) fn write_replacing(path: &Path, content: &str) -> io::Result<()> {
     let dir = path.parent().unwrap_or(Path::new("."));
     fs::create_dir_all(dir)?;
 
@@ -110,31 +249,14 @@ pub fn save_prices_cache(prices: &HashMap<i64, PersistedPriceEntry>) -> io::Resu
         }
     }
 
-    let file = PricesCacheFile {
-        version: 2,
-        prices: sanitized,
-    };
-    let json = serde_json::to_string(&file).unwrap_or_else(|_| "{\"version\":1,\"prices\":{}}".to_string());
-    atomic_write(&path, &json)
+    atomic_write(&path, &file_schema::encode(&sanitized, &PRICES_CACHE_FORMAT)?)
 }
 
 pub fn load_settings() -> io::Result<Option<AppSettings>> {
     let Some(path) = settings_path() else {
         return Ok(None);
     };
-    if !path.exists() {
-        return Ok(None);
-    }
-
-    let data = fs::read_to_string(&path)?;
-    if let Ok(parsed) = serde_json::from_str::<SettingsFile>(&data) {
-        return Ok(Some(parsed.settings));
-    }
-
-    // legacy: raw AppSettings without wrapper
-    let legacy: AppSettings =
-        serde_json::from_str(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-    Ok(Some(legacy))
+    file_schema::load(&path, &SETTINGS_FORMAT)
 }
 
 pub fn save_settings(settings: &AppSettings) -> io::Result<()> {
@@ -142,53 +264,34 @@ pub fn save_settings(settings: &AppSettings) -> io::Result<()> {
         return Ok(());
     };
 
-    let file = SettingsFile {
-        version: 1,
-        settings: settings.clone(),
-    };
-    let json =
-        serde_json::to_string(&file).unwrap_or_else(|_| "{\"version\":1,\"settings\":{}}".to_string());
-    atomic_write(&path, &json)
+    atomic_write(&path, &file_schema::encode(settings, &SETTINGS_FORMAT)?)
 }
 
-/// Load active session from disk (for recovery after crash/close)
-pub fn load_session() -> io::Result<Option<FarmSessionState>> {
-    let Some(path) = session_path() else {
+/// Load active session of a log source from disk (for recovery after crash/close)
+pub fn load_session(source_id: &str) -> io::Result<Option<FarmSessionState>> {
+    let Some(path) = session_path(source_id) else {
         return Ok(None);
     };
-    if !path.exists() {
-        return Ok(None);
-    }
-
-    let data = fs::read_to_string(&path)?;
-    let session: FarmSessionState = serde_json::from_str(&data)
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-    
+    let session: Option<FarmSessionState> = file_schema::load(&path, &ACTIVE_SESSION_FORMAT)?;
     // Only return session if it was actually started
-    if session.started_at.is_some() {
-        Ok(Some(session))
-    } else {
-        Ok(None)
-    }
+    Ok(session.filter(|s| s.started_at.is_some()))
 }
 
-/// Save active session to disk (for recovery)
-pub fn save_session(session: &FarmSessionState) -> io::Result<()> {
-    let Some(path) = session_path() else {
+/// Save active session of a log source to disk (for recovery)
+pub fn save_session(source_id: &str, session: &FarmSessionState) -> io::Result<()> {
+    let Some(path) = session_path(source_id) else {
         return Ok(());
     };
 
-    let json = serde_json::to_string(session)
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-    atomic_write(&path, &json)
+    atomic_write(&path, &file_schema::encode(session, &ACTIVE_SESSION_FORMAT)?)
 }
 
-/// Delete session file (when session ends normally)
-pub fn delete_session() -> io::Result<()> {
-    let Some(path) = session_path() else {
+/// Delete session file of a log source (when session ends normally)
+pub fn delete_session(source_id: &str) -> io::Result<()> {
+    let Some(path) = session_path(source_id) else {
         return Ok(());
     };
-    if path.exists() {
+    if path.exists() && !writes_frozen() {
         fs::remove_file(path)?;
     }
     Ok(())
@@ -206,40 +309,70 @@ pub struct SessionHistoryRecord {
     pub ended_at: DateTime<Utc>,
     pub maps_completed: i32,
     pub total_duration_sec: i32,
+    /// Profit before the auction fee: total_income - total_expenses
     pub total_profit: f64,
     pub total_expenses: f64,
+    /// Income before the auction fee
     pub total_income: f64,
+    /// Income after the auction fee (None for records saved before fee support)
+    #[serde(default)]
+    pub net_income: Option<f64>,
+    /// Income split by source (auto drops, manual drops)
+    #[serde(default)]
+    pub income_by_source: Vec<IncomeSourceTotal>,
     /// Remote ID in Supabase (if synced)
     pub remote_id: Option<String>,
+    /// Strategy preset the session was started with
+    #[serde(default)]
+    pub preset_id: Option<String>,
+    /// Auto drops by game_id, without excluded items (empty for older records)
+    #[serde(default)]
+    pub drops: HashMap<i64, i32>,
+    /// Valuation of `drops` used for the income totals (empty for older records)
+    #[serde(default)]
+    pub drop_values: HashMap<i64, RecordedDropValue>,
+    /// Manual drop entries (empty for older records)
+    #[serde(default)]
+    pub manual_drops: Vec<ManualDropEntry>,
+    /// Expense entries (empty for older records)
+    #[serde(default)]
+    pub expenses: Vec<ExpenseEntry>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct SessionHistoryFile {
-    version: u32,
-    sessions: Vec<SessionHistoryRecord>,
-}
+/// History owner id used when nobody is logged in
+pub const GUEST_USER_ID: &str = "guest";
 
 fn session_history_path(user_id: &str) -> Option<PathBuf> {
     // Sanitize user_id for filename (remove special chars)
-    let safe_id: String = user_id.chars()
-        .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_')
-        .collect();
+    let safe_id = sanitize_file_id(user_id);
     app_data_dir().map(|d| d.join(format!("sessions_{}.json", safe_id)))
 }
 
-/// Load session history for user
-pub fn load_session_history(user_id: &str) -> io::Result<Vec<SessionHistoryRecord>> {
-    let Some(path) = session_history_path(user_id) else {
+/// User ids that have a JSON session history file (for the one-time SQLite import)
+pub fn list_history_user_ids() -> io::Result<Vec<String>> {
+    let Some(dir) = app_data_dir() else {
         return Ok(Vec::new());
     };
-    if !path.exists() {
+    if !dir.exists() {
         return Ok(Vec::new());
     }
 
-    let data = fs::read_to_string(&path)?;
-    let file: SessionHistoryFile = serde_json::from_str(&data)
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-    Ok(file.sessions)
+    let mut ids = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if let Some(id) = name.strip_prefix("sessions_").and_then(|n| n.strip_suffix(".json")) {
+            ids.push(id.to_string());
+        }
+    }
+    Ok(ids)
+}
+
+/// Load session history for user
+pub fn load_session_history(user_id: &str) -> io::Result<Vec<SessionHistoryRecord>> {
+    let Some(path) = session_history_path(user_id) else {
+        return Ok(Vec::new());
+    };
+    Ok(file_schema::load(&path, &SESSION_HISTORY_FORMAT)?.unwrap_or_default())
 }
 
 /// Save session history for user
@@ -248,13 +381,7 @@ pub fn save_session_history(user_id: &str, sessions: &[SessionHistoryRecord]) ->
         return Ok(());
     };
 
-    let file = SessionHistoryFile {
-        version: 1,
-        sessions: sessions.to_vec(),
-    };
-    let json = serde_json::to_string(&file)
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-    atomic_write(&path, &json)
+    atomic_write(&path, &file_schema::encode(sessions, &SESSION_HISTORY_FORMAT)?)
 }
 
 /// Add a session to history
@@ -270,6 +397,26 @@ pub fn add_session_to_history(user_id: &str, session: SessionHistoryRecord) -> i
     save_session_history(user_id, &sessions)
 }
 
+/// Move all sessions of `from_user` into `to_user` history, skipping ids already there.
+/// Returns the number of moved sessions.
+pub fn merge_session_history(from_user: &str, to_user: &str) -> io::Result<usize> {
+    let from = load_session_history(from_user)?;
+    if from.is_empty() {
+        return Ok(0);
+    }
+    let mut sessions = load_session_history(to_user)?;
+    let known: std::collections::HashSet<String> = sessions.iter().map(|s| s.id.clone()).collect();
+    let moved: Vec<SessionHistoryRecord> = from.into_iter().filter(|s| !known.contains(&s.id)).collect();
+    let count = moved.len();
+
+    sessions.extend(moved);
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.started_at));
+    sessions.truncate(100);
+    save_session_history(to_user, &sessions)?;
+    save_session_history(from_user, &[])?;
+    Ok(count)
+}
+
 /// Delete a session from history
 pub fn delete_session_from_history(user_id: &str, session_id: &str) -> io::Result<Option<SessionHistoryRecord>> {
     let mut sessions = load_session_history(user_id)?;
@@ -282,3 +429,275 @@ pub fn delete_session_from_history(user_id: &str, session_id: &str) -> io::Resul
     
     Ok(removed)
 }
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Strategy presets
+// ─────────────────────────────────────────────────────────────────────────────
+
+fn presets_path() -> Option<PathBuf> {
+    app_data_dir().map(|d| d.join("presets.json"))
+}
+
+/// Load strategy presets
+pub fn load_presets() -> io::Result<Vec<StrategyPreset>> {
+    let Some(path) = presets_path() else {
+        return Ok(Vec::new());
+    };
+    Ok(file_schema::load(&path, &PRESETS_FORMAT)?.unwrap_or_default())
+}
+
+/// Save strategy presets
+pub fn save_presets(presets: &[StrategyPreset]) -> io::Result<()> {
+    let Some(path) = presets_path() else {
+        return Ok(());
+    };
+
+    atomic_write(&path, &file_schema::encode(presets, &PRESETS_FORMAT)?)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Valuation rules
+// ─────────────────────────────────────────────────────────────────────────────
+
+fn valuation_rules_path() -> Option<PathBuf> {
+    app_data_dir().map(|d| d.join("valuation_rules.json"))
+}
+
+/// Load item valuation rules
+pub fn load_valuation_rules() -> io::Result<ValuationRules> {
+    let Some(path) = valuation_rules_path() else {
+        return Ok(ValuationRules::default());
+    };
+    Ok(file_schema::load(&path, &VALUATION_RULES_FORMAT)?.unwrap_or_default())
+}
+
+/// Save item valuation rules
+pub fn save_valuation_rules(rules: &ValuationRules) -> io::Result<()> {
+    let Some(path) = valuation_rules_path() else {
+        return Ok(());
+    };
+
+    atomic_write(&path, &file_schema::encode(rules, &VALUATION_RULES_FORMAT)?)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Drop-rate statistics
+// ─────────────────────────────────────────────────────────────────────────────
+
+fn drop_stats_path() -> Option<PathBuf> {
+    app_data_dir().map(|d| d.join("drop_stats.json"))
+}
+
+/// Load accumulated drop-rate statistics
+pub fn load_drop_stats() -> io::Result<Vec<DropRateBucket>> {
+    let Some(path) = drop_stats_path() else {
+        return Ok(Vec::new());
+    };
+    Ok(file_schema::load(&path, &DROP_STATS_FORMAT)?.unwrap_or_default())
+}
+
+/// Save accumulated drop-rate statistics
+pub fn save_drop_stats(buckets: &[DropRateBucket]) -> io::Result<()> {
+    let Some(path) = drop_stats_path() else {
+        return Ok(());
+    };
+
+    atomic_write(&path, &file_schema::encode(buckets, &DROP_STATS_FORMAT)?)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Price history
+// ─────────────────────────────────────────────────────────────────────────────
+
+fn price_history_path() -> Option<PathBuf> {
+    app_data_dir().map(|d| d.join("price_history.json"))
+}
+
+/// Load price observations by game_id
+pub fn load_price_history() -> io::Result<HashMap<i64, Vec<PriceObservation>>> {
+    let Some(path) = price_history_path() else {
+        return Ok(HashMap::new());
+    };
+    Ok(file_schema::load(&path, &PRICE_HISTORY_FORMAT)?.unwrap_or_default())
+}
+
+/// Save price observations by game_id
+pub fn save_price_history(items: &HashMap<i64, Vec<PriceObservation>>) -> io::Result<()> {
+    let Some(path) = price_history_path() else {
+        return Ok(());
+    };
+
+    atomic_write(&path, &file_schema::encode(items, &PRICE_HISTORY_FORMAT)?)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Price alerts
+// ─────────────────────────────────────────────────────────────────────────────
+
+fn price_alerts_path() -> Option<PathBuf> {
+    app_data_dir().map(|d| d.join("price_alerts.json"))
+}
+
+/// Load price alert definitions
+pub fn load_price_alerts() -> io::Result<Vec<PriceAlert>> {
+    let Some(path) = price_alerts_path() else {
+        return Ok(Vec::new());
+    };
+    Ok(file_schema::load(&path, &PRICE_ALERTS_FORMAT)?.unwrap_or_default())
+}
+
+/// Save price alert definitions (with their last fire time, for cooldowns across restarts)
+pub fn save_price_alerts(alerts: &[PriceAlert]) -> io::Result<()> {
+    let Some(path) = price_alerts_path() else {
+        return Ok(());
+    };
+
+    atomic_write(&path, &file_schema::encode(alerts, &PRICE_ALERTS_FORMAT)?)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Price quarantine
+// ─────────────────────────────────────────────────────────────────────────────
+
+fn price_quarantine_path() -> Option<PathBuf> {
+    app_data_dir().map(|d| d.join("price_quarantine.json"))
+}
+
+/// Load price checks awaiting user confirmation
+pub fn load_price_quarantine() -> io::Result<Vec<QuarantinedPrice>> {
+    let Some(path) = price_quarantine_path() else {
+        return Ok(Vec::new());
+    };
+    Ok(file_schema::load(&path, &PRICE_QUARANTINE_FORMAT)?.unwrap_or_default())
+}
+
+/// Save price checks awaiting user confirmation
+pub fn save_price_quarantine(entries: &[QuarantinedPrice]) -> io::Result<()> {
+    let Some(path) = price_quarantine_path() else {
+        return Ok(());
+    };
+
+    atomic_write(&path, &file_schema::encode(entries, &PRICE_QUARANTINE_FORMAT)?)
+}
+
+// ─────────────────────────────────────────────────────────────────────────────
+// Session goals
+// ─────────────────────────────────────────────────────────────────────────────
+
+fn goals_path() -> Option<PathBuf> {
+    app_data_dir().map(|d| d.join("goals.json"))
+}
+
+/// Load session goals
+pub fn load_goals() -> io::Result<Vec<Goal>> {
+    let Some(path) = goals_path() else {
+        return Ok(Vec::new());
+    };
+    Ok(file_schema::load(&path, &GOALS_FORMAT)?.unwrap_or_default())
+}
+
+/// Save session goals
+pub fn save_goals(goals: &[Goal]) -> io::Result<()> {
+    let Some(path) = goals_path() else {
+        return Ok(());
+    };
+
+    atomic_write(&path, &file_schema::encode(goals, &GOALS_FORMAT)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::de::DeserializeOwned;
+
+    /// Decode a fixture and check that the current encoding reads back to the same payload
+    fn decode_fixture<T: DeserializeOwned + Serialize>(content: &str, format: &FileFormat, stored: u32) -> T {
+        let (data, version): (T, u32) = file_schema::decode(content, format).unwrap();
+        assert_eq!(version, stored, "{}", format.name);
+        let encoded = file_schema::encode(&data, format).unwrap();
+        let (again, version): (T, u32) = file_schema::decode(&encoded, format).unwrap();
+        assert_eq!(version, format.version);
+        assert_eq!(serde_json::to_value(&again).unwrap(), serde_json::to_value(&data).unwrap());
+        data
+    }
+
+    #[test]
+    fn test_prices_cache_fixtures() {
+        let v0: HashMap<i64, PersistedPriceEntry> =
+            decode_fixture(include_str!("../tests/fixtures/persistence/prices_cache.v0.json"), &PRICES_CACHE_FORMAT, 0);
+        assert_eq!(v0.len(), 2);
+        assert_eq!(v0[&200].price, 3.5);
+        assert!(v0[&200].is_current_league && v0[&200].strategy.is_none());
+
+        let v1: HashMap<i64, PersistedPriceEntry> =
+            decode_fixture(include_str!("../tests/fixtures/persistence/prices_cache.v1.json"), &PRICES_CACHE_FORMAT, 1);
+        assert!(v1.is_empty());
+
+        let v2: HashMap<i64, PersistedPriceEntry> =
+            decode_fixture(include_str!("../tests/fixtures/persistence/prices_cache.v2.json"), &PRICES_CACHE_FORMAT, 2);
+        assert!(v2[&200].is_current_league && v2[&200].source.is_none());
+        assert!(!v2[&201].is_current_league);
+        assert_eq!(v2[&201].league_name.as_deref(), Some("SS9"));
+        assert_eq!(v2[&201].strategy, Some(PriceStrategy::Median));
+        assert_eq!(v2[&201].source, Some(PriceObservationSource::PriceCheck));
+    }
+
+    #[test]
+    fn test_settings_and_active_session_fixtures() {
+        let v0: AppSettings =
+            decode_fixture(include_str!("../tests/fixtures/persistence/settings.v0.json"), &SETTINGS_FORMAT, 0);
+        assert_eq!(v0.custom_log_path.as_deref(), Some("C:\\Games\\TLI\\UE_game.log"));
+        assert!(v0.auto_start);
+        assert_eq!(v0.language, "en");
+
+        let v1: AppSettings =
+            decode_fixture(include_str!("../tests/fixtures/persistence/settings.v1.json"), &SETTINGS_FORMAT, 1);
+        assert!(!v1.minimize_to_tray);
+        assert_eq!(v1.language, "ru");
+
+        let session: FarmSessionState =
+            decode_fixture(include_str!("../tests/fixtures/persistence/active_session.v0.json"), &ACTIVE_SESSION_FORMAT, 0);
+        assert_eq!(session.maps_completed, 3);
+        assert_eq!(session.drop_log.len(), 2);
+        assert_eq!(session.drop_log[0].game_id, 200);
+        assert_eq!(session.drop_log[1].quantity, 120);
+        assert_eq!(Some(session.drop_log[0].timestamp), session.started_at);
+    }
+
+    #[test]
+    fn test_v1_wrapper_fixtures() {
+        let sessions: Vec<SessionHistoryRecord> =
+            decode_fixture(include_str!("../tests/fixtures/persistence/sessions.v1.json"), &SESSION_HISTORY_FORMAT, 1);
+        assert_eq!(sessions.len(), 1);
+        assert!(sessions[0].net_income.is_none() && sessions[0].drops.is_empty());
+
+        let presets: Vec<StrategyPreset> =
+            decode_fixture(include_str!("../tests/fixtures/persistence/presets.v1.json"), &PRESETS_FORMAT, 1);
+        assert_eq!(presets[0].consumables[0].game_id, 5028);
+
+        let rules: ValuationRules =
+            decode_fixture(include_str!("../tests/fixtures/persistence/valuation_rules.v1.json"), &VALUATION_RULES_FORMAT, 1);
+        assert!(rules.ignored_items.contains(&200));
+        assert_eq!(rules.fixed_prices[&201], 2.5);
+
+        let buckets: Vec<DropRateBucket> =
+            decode_fixture(include_str!("../tests/fixtures/persistence/drop_stats.v1.json"), &DROP_STATS_FORMAT, 1);
+        assert_eq!(buckets[0].maps, 12);
+
+        let history: HashMap<i64, Vec<PriceObservation>> =
+            decode_fixture(include_str!("../tests/fixtures/persistence/price_history.v1.json"), &PRICE_HISTORY_FORMAT, 1);
+        assert_eq!(history[&200][0].price, 3.5);
+
+        let alerts: Vec<PriceAlert> =
+            decode_fixture(include_str!("../tests/fixtures/persistence/price_alerts.v1.json"), &PRICE_ALERTS_FORMAT, 1);
+        assert!(alerts[0].enabled);
+
+        let quarantine: Vec<QuarantinedPrice> =
+            decode_fixture(include_str!("../tests/fixtures/persistence/price_quarantine.v1.json"), &PRICE_QUARANTINE_FORMAT, 1);
+        assert_eq!(quarantine[0].currency_id, 100300);
+
+        let goals: Vec<Goal> =
+            decode_fixture(include_str!("../tests/fixtures/persistence/goals.v1.json"), &GOALS_FORMAT, 1);
+        assert_eq!(goals[0].target, 10000.0);
+    }
+}
@@ -0,0 +1,164 @@
+//! Ценовые оповещения
+//!
+//! Проверяются после изменения цены предмета (прайсчек, remote merge, ручной ввод).
+//! Порог по цене срабатывает, пока условие выполняется, но не чаще cooldown;
+//! изменение в процентах считается от цены в начале окна по истории цен.
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::price_history;
+use crate::types::{ItemInfo, PriceAlert, PriceAlertCondition, PriceAlertFired, PriceObservation};
+
+/// Верхняя граница cooldown и окна изменения — год
+const MAX_ALERT_MINUTES: i64 = 365 * 24 * 60;
+
+/// Проверка параметров оповещения
+pub fn validate(alert: &PriceAlert) -> Result<(), String> {
+    if alert.cooldown_min < 0 {
+        return Err("Cooldown must not be negative".to_string());
+    }
+    if alert.cooldown_min > MAX_ALERT_MINUTES {
+        return Err("Cooldown must not exceed one year".to_string());
+    }
+    match alert.condition {
+        PriceAlertCondition::Above { price } | PriceAlertCondition::Below { price } => {
+            if !price.is_finite() || price <= 0.0 {
+                return Err("Alert price must be positive".to_string());
+            }
+        }
+        PriceAlertCondition::ChangePct { pct, window_min } => {
+            if !pct.is_finite() || pct <= 0.0 {
+                return Err("Alert percentage must be positive".to_string());
+            }
+            if window_min <= 0 {
+                return Err("Alert window must be positive".to_string());
+            }
+            if window_min > MAX_ALERT_MINUTES {
+                return Err("Alert window must not exceed one year".to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Проверить оповещение для новой цены. `series` — история цен предмета (с новой ценой).
+pub fn evaluate(
+    alert: &PriceAlert,
+    price: f64,
+    series: &[PriceObservation],
+    item_info: Option<&ItemInfo>,
+    now: DateTime<Utc>,
+) -> Option<PriceAlertFired> {
+    if !alert.enabled {
+        return None;
+    }
+    // Значения вне диапазона Duration (файл правили руками) — cooldown не истекает
+    if let Some(last) = alert.last_fired_at {
+        match Duration::try_minutes(alert.cooldown_min) {
+            Some(cooldown) if now - last >= cooldown => {}
+            _ => return None,
+        }
+    }
+
+    let (triggered, reference_price, change_pct) = match alert.condition {
+        PriceAlertCondition::Above { price: threshold } => (price > threshold, None, None),
+        PriceAlertCondition::Below { price: threshold } => (price < threshold, None, None),
+        PriceAlertCondition::ChangePct { pct, window_min } => {
+            let start = now - Duration::try_minutes(window_min)?;
+            // Цена на начало окна; если истории до окна нет — первое наблюдение в окне
+            let reference = price_history::price_at(series, start)
+                .or_else(|| series.iter().find(|o| o.at >= start))
+                .map(|o| o.price)?;
+            let change = (price - reference) / reference * 100.0;
+            (change.abs() >= pct, Some(reference), Some(change))
+        }
+    };
+    if !triggered {
+        return None;
+    }
+
+    Some(PriceAlertFired {
+        alert: alert.clone(),
+        item_info: item_info.cloned(),
+        price,
+        reference_price,
+        change_pct,
+        at: now,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::PriceObservationSource;
+
+    fn alert(condition: PriceAlertCondition) -> PriceAlert {
+        PriceAlert {
+            id: "a".into(),
+            game_id: 1,
+            condition,
+            cooldown_min: 60,
+            enabled: true,
+            created_at: Utc::now(),
+            last_fired_at: None,
+        }
+    }
+
+    fn obs(at: DateTime<Utc>, price: f64) -> PriceObservation {
+        PriceObservation { at, price, source: PriceObservationSource::PriceCheck }
+    }
+
+    #[test]
+    fn test_threshold_alerts() {
+        let now = Utc::now();
+        let above = alert(PriceAlertCondition::Above { price: 100.0 });
+        assert!(evaluate(&above, 100.0, &[], None, now).is_none());
+        let fired = evaluate(&above, 101.0, &[], None, now).unwrap();
+        assert_eq!(fired.price, 101.0);
+        assert_eq!(fired.reference_price, None);
+
+        let below = alert(PriceAlertCondition::Below { price: 100.0 });
+        assert!(evaluate(&below, 99.0, &[], None, now).is_some());
+        assert!(evaluate(&below, 101.0, &[], None, now).is_none());
+
+        let disabled = PriceAlert { enabled: false, ..above };
+        assert!(evaluate(&disabled, 101.0, &[], None, now).is_none());
+    }
+
+    #[test]
+    fn test_change_pct_uses_price_at_window_start() {
+        let now = Utc::now();
+        let a = alert(PriceAlertCondition::ChangePct { pct: 10.0, window_min: 60 });
+        // Цена до окна — 100, внутри окна — 200: опорная цена на начало окна — 100
+        let series = [obs(now - Duration::minutes(90), 100.0), obs(now - Duration::minutes(30), 200.0)];
+        let fired = evaluate(&a, 89.0, &series, None, now).unwrap();
+        assert_eq!(fired.reference_price, Some(100.0));
+        assert!((fired.change_pct.unwrap() + 11.0).abs() < 1e-9);
+        assert!(evaluate(&a, 105.0, &series, None, now).is_none());
+
+        // Истории до окна нет — первое наблюдение в окне
+        let fired = evaluate(&a, 230.0, &series[1..], None, now).unwrap();
+        assert_eq!(fired.reference_price, Some(200.0));
+        assert!(evaluate(&a, 230.0, &[], None, now).is_none());
+    }
+
+    #[test]
+    fn test_cooldown() {
+        let now = Utc::now();
+        let mut a = alert(PriceAlertCondition::Above { price: 100.0 });
+        a.last_fired_at = Some(now - Duration::minutes(59));
+        assert!(evaluate(&a, 150.0, &[], None, now).is_none());
+        a.last_fired_at = Some(now - Duration::minutes(60));
+        assert!(evaluate(&a, 150.0, &[], None, now).is_some());
+
+        // Значения вне диапазона не паникуют
+        a.cooldown_min = i64::MAX;
+        assert!(evaluate(&a, 150.0, &[], None, now).is_none());
+        let huge = alert(PriceAlertCondition::ChangePct { pct: 10.0, window_min: i64::MAX });
+        assert!(evaluate(&huge, 150.0, &[obs(now, 100.0)], None, now).is_none());
+        assert!(validate(&a).is_err());
+        assert!(validate(&huge).is_err());
+        a.cooldown_min = 60;
+        assert!(validate(&a).is_ok());
+    }
+}
@@ -0,0 +1,88 @@
+//! Отсев выбросов в прайсчеках
+//!
+//! Один набор манипулятивных лотов или ошибочно сопоставленный refer портит и
+//! локальный кэш, и crowd-данные. Новую цену сравниваем с медианой недавней
+//! истории предмета и с последней remote ценой; подозрительные наблюдения
+//! уходят в карантин до решения пользователя. Согласующиеся между собой
+//! подозрительные прайсчеки предмета копятся, чтобы их можно было подтвердить разом.
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::types::{PriceObservation, PriceObservationSource, QuarantineReason};
+
+/// Во сколько раз цена может отличаться от ориентира
+const MAX_DEVIATION: f64 = 3.0;
+/// Окно истории для ориентира
+const REFERENCE_WINDOW_DAYS: i64 = 7;
+/// Минимум наблюдений для медианы истории
+const MIN_HISTORY_SAMPLES: usize = 3;
+/// Сколько подозрительных прайсчеков предмета держим в карантине (старые вытесняются)
+pub const MAX_PENDING_PER_ITEM: usize = 10;
+
+fn deviates(ratio: f64) -> bool {
+    !(1.0 / MAX_DEVIATION..=MAX_DEVIATION).contains(&ratio)
+}
+
+/// Две подозрительные цены согласуются между собой (тот же сдвиг рынка)
+pub fn consistent(price: f64, other: f64) -> bool {
+    !deviates(price / other)
+}
+
+/// Проверить новую цену. None — цена выглядит правдоподобно (или сравнивать не с чем).
+pub fn check(price: f64, series: &[PriceObservation], now: DateTime<Utc>) -> Option<QuarantineReason> {
+    let from = now - Duration::days(REFERENCE_WINDOW_DAYS);
+    let recent: Vec<&PriceObservation> = series.iter().filter(|o| o.at >= from).collect();
+
+    // Remote цена — агрегат многих прайсчеков, ей доверяем больше
+    if let Some(remote) = recent.iter().rev().find(|o| o.source == PriceObservationSource::Remote) {
+        let ratio = price / remote.price;
+        if deviates(ratio) {
+            return Some(QuarantineReason::DeviatesFromRemote { remote_price: remote.price, ratio });
+        }
+    }
+
+    if recent.len() >= MIN_HISTORY_SAMPLES {
+        let mut values: Vec<f64> = recent.iter().map(|o| o.price).collect();
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let mid = values.len() / 2;
+        let median = if values.len().is_multiple_of(2) { (values[mid - 1] + values[mid]) / 2.0 } else { values[mid] };
+        let ratio = price / median;
+        if deviates(ratio) {
+            return Some(QuarantineReason::DeviatesFromHistory { median, ratio });
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn obs(hours_ago: i64, price: f64, source: PriceObservationSource) -> PriceObservation {
+        PriceObservation { at: Utc::now() - Duration::hours(hours_ago), price, source }
+    }
+
+    #[test]
+    fn test_check_against_history_and_remote() {
+        let now = Utc::now();
+        assert!(check(100.0, &[], now).is_none());
+
+        let series = vec![
+            obs(30, 10.0, PriceObservationSource::PriceCheck),
+            obs(20, 12.0, PriceObservationSource::PriceCheck),
+            obs(10, 11.0, PriceObservationSource::PriceCheck),
+        ];
+        assert!(check(20.0, &series, now).is_none());
+        assert!(matches!(check(40.0, &series, now), Some(QuarantineReason::DeviatesFromHistory { median, .. }) if median == 11.0));
+        assert!(check(3.0, &series, now).is_some());
+
+        let series = vec![obs(1, 100.0, PriceObservationSource::Remote)];
+        assert!(matches!(check(20.0, &series, now), Some(QuarantineReason::DeviatesFromRemote { .. })));
+        // Наблюдения старше окна не учитываются
+        let series = vec![obs(24 * 8, 100.0, PriceObservationSource::Remote)];
+        assert!(check(20.0, &series, now).is_none());
+
+        assert!(consistent(40.0, 35.0));
+        assert!(!consistent(40.0, 3.0));
+    }
+}
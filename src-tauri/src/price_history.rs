@@ -0,0 +1,131 @@
+//! Локальная история цен предметов
+//!
+//! Каждое обновление цены (прайсчек, remote merge, ручной ввод) добавляет
+//! наблюдение в ряд предмета. Ряд ограничен по длине и возрасту, повторы
+//! той же цены из того же источника в коротком окне не пишутся.
+
+use std::collections::HashMap;
+use chrono::{DateTime, Duration, Utc};
+
+use crate::types::{PriceChange, PriceObservation, PriceObservationSource, PriceTrend, PriceVolatility};
+
+/// Максимум наблюдений на предмет (в памяти; SQLite хранит всю историю)
+const MAX_POINTS_PER_ITEM: usize = 500;
+/// Наблюдения старше этого срока удаляются из памяти
+pub const MAX_AGE_DAYS: i64 = 90;
+/// Та же цена из того же источника раньше этого окна не пишется повторно
+const DEDUP_WINDOW_MIN: i64 = 15;
+
+/// Добавить наблюдение в ряд предмета (ряд остаётся отсортированным по времени)
+pub fn record(
+    history: &mut HashMap<i64, Vec<PriceObservation>>,
+    game_id: i64,
+    price: f64,
+    at: DateTime<Utc>,
+    source: PriceObservationSource,
+) -> bool {
+    if !price.is_finite() || price <= 0.0 {
+        return false;
+    }
+    let series = history.entry(game_id).or_default();
+    let duplicate = series.iter().rev().any(|o| {
+        o.source == source && o.price == price && (at - o.at).num_minutes().abs() < DEDUP_WINDOW_MIN
+    });
+    if duplicate {
+        return false;
+    }
+
+    let pos = series.partition_point(|o| o.at <= at);
+    series.insert(pos, PriceObservation { at, price, source });
+    trim(series);
+    true
+}
+
+/// Ограничить ряд по возрасту и длине
+pub fn trim(series: &mut Vec<PriceObservation>) {
+    let cutoff = Utc::now() - Duration::days(MAX_AGE_DAYS);
+    series.retain(|o| o.at >= cutoff);
+    if series.len() > MAX_POINTS_PER_ITEM {
+        let excess = series.len() - MAX_POINTS_PER_ITEM;
+        series.drain(..excess);
+    }
+}
+
+/// Цена на момент `at`: последнее наблюдение не позже `at`
+pub fn price_at(series: &[PriceObservation], at: DateTime<Utc>) -> Option<&PriceObservation> {
+    let pos = series.partition_point(|o| o.at <= at);
+    pos.checked_sub(1).map(|i| &series[i])
+}
+
+fn change(series: &[PriceObservation], current: &PriceObservation, window: Duration) -> Option<PriceChange> {
+    let from = price_at(series, current.at - window)?;
+    let change = current.price - from.price;
+    Some(PriceChange {
+        from_price: from.price,
+        from_at: from.at,
+        change,
+        change_pct: change / from.price * 100.0,
+    })
+}
+
+/// Изменение цены за 24 часа и 7 дней
+pub fn trend(game_id: i64, series: &[PriceObservation]) -> PriceTrend {
+    let Some(current) = series.last() else {
+        return PriceTrend { game_id, current: None, current_at: None, change_24h: None, change_7d: None };
+    };
+    PriceTrend {
+        game_id,
+        current: Some(current.price),
+        current_at: Some(current.at),
+        change_24h: change(series, current, Duration::hours(24)),
+        change_7d: change(series, current, Duration::days(7)),
+    }
+}
+
+/// Волатильность за последние `window_days` дней (None — меньше 2 наблюдений)
+pub fn volatility(game_id: i64, series: &[PriceObservation], window_days: i64, now: DateTime<Utc>) -> Option<PriceVolatility> {
+    let from = now - Duration::days(window_days);
+    let values: Vec<f64> = series.iter().filter(|o| o.at >= from).map(|o| o.price).collect();
+    if values.len() < 2 {
+        return None;
+    }
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    let std_dev = variance.sqrt();
+    Some(PriceVolatility {
+        game_id,
+        window_days,
+        samples: values.len(),
+        mean,
+        std_dev,
+        coefficient_of_variation: if mean > 0.0 { std_dev / mean } else { 0.0 },
+        min: values.iter().copied().fold(f64::INFINITY, f64::min),
+        max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_dedup_and_trend() {
+        let now = Utc::now();
+        let mut history = HashMap::new();
+        assert!(record(&mut history, 1, 10.0, now - Duration::days(8), PriceObservationSource::Remote));
+        assert!(record(&mut history, 1, 12.0, now - Duration::hours(30), PriceObservationSource::PriceCheck));
+        assert!(record(&mut history, 1, 15.0, now, PriceObservationSource::PriceCheck));
+        // Повтор той же цены из того же источника — не пишется
+        assert!(!record(&mut history, 1, 15.0, now + Duration::minutes(1), PriceObservationSource::PriceCheck));
+
+        let series = &history[&1];
+        assert_eq!(series.len(), 3);
+        let t = trend(1, series);
+        assert_eq!(t.current, Some(15.0));
+        assert_eq!(t.change_24h.as_ref().map(|c| c.from_price), Some(12.0));
+        assert_eq!(t.change_7d.as_ref().map(|c| c.change_pct), Some(50.0));
+        assert_eq!(price_at(series, now - Duration::days(2)).map(|o| o.price), Some(10.0));
+        assert!(price_at(series, now - Duration::days(9)).is_none());
+    }
+}
@@ -0,0 +1,100 @@
+//! Выбор рыночной цены из результатов прайсчека
+//!
+//! В логах есть список unitPrices (обычно по одному значению на лот). Явного
+//! объёма на каждой цене в сообщении нет, поэтому по умолчанию берём низкий
+//! перцентиль вместо min, чтобы не ловить единичные манипуляции. Для тонких
+//! рынков и валют стратегию можно поменять глобально, по категории или предмету.
+
+use std::collections::HashMap;
+
+use crate::types::{ItemInfo, PriceSelectionRules, PriceStrategy};
+
+/// Стратегия для предмета: предмет > категория > глобальная
+pub fn resolve(rules: &PriceSelectionRules, game_id: i64, items_cache: &HashMap<i64, ItemInfo>) -> PriceStrategy {
+    if let Some(s) = rules.items.get(&game_id) {
+        return *s;
+    }
+    items_cache
+        .get(&game_id)
+        .and_then(|i| rules.categories.get(&i.category))
+        .copied()
+        .unwrap_or(rules.default)
+}
+
+/// Проверка параметров стратегии
+pub fn validate(strategy: &PriceStrategy) -> Result<(), String> {
+    match strategy {
+        PriceStrategy::Percentile { percentile } if !(0.0..=100.0).contains(percentile) => {
+            Err("Percentile must be between 0 and 100".to_string())
+        }
+        PriceStrategy::TrimmedMean { trim_pct } if !(0.0..50.0).contains(trim_pct) => {
+            Err("Trim percentage must be between 0 and 50".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Выбрать цену из цен лотов. `volumes` — объёмы лотов в том же порядке, если известны.
+pub fn select(prices: &[f64], volumes: Option<&[f64]>, strategy: &PriceStrategy) -> Option<f64> {
+    // (цена, объём), только валидные цены; при несовпадении длин объёмы игнорируем
+    let volumes = volumes.filter(|v| v.len() == prices.len());
+    let mut lots: Vec<(f64, f64)> = prices
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.is_finite() && **p > 0.0)
+        .map(|(i, p)| {
+            let w = volumes.map(|v| v[i]).filter(|w| w.is_finite() && *w > 0.0).unwrap_or(1.0);
+            (*p, w)
+        })
+        .collect();
+    if lots.is_empty() {
+        return None;
+    }
+    lots.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    let percentile = |p: f64| {
+        let idx = (((lots.len() - 1) as f64) * p).round() as usize;
+        lots.get(idx).map(|l| l.0)
+    };
+
+    match strategy {
+        PriceStrategy::Min => Some(lots[0].0),
+        PriceStrategy::Percentile { percentile: p } => percentile((p / 100.0).clamp(0.0, 1.0)),
+        PriceStrategy::Median => percentile(0.5),
+        PriceStrategy::TrimmedMean { trim_pct } => {
+            let cut = ((lots.len() as f64) * (trim_pct / 100.0).clamp(0.0, 0.49)).floor() as usize;
+            let kept = &lots[cut..lots.len() - cut];
+            Some(kept.iter().map(|l| l.0).sum::<f64>() / kept.len() as f64)
+        }
+        PriceStrategy::VolumeWeighted => {
+            let total: f64 = lots.iter().map(|l| l.1).sum();
+            Some(lots.iter().map(|l| l.0 * l.1).sum::<f64>() / total)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_strategies() {
+        let prices = [5.0, 1.0, 2.0, 3.0, 4.0, 0.0, 100.0];
+        // 0.0 отбрасывается: 1, 2, 3, 4, 5, 100
+        assert_eq!(select(&prices, None, &PriceStrategy::Min), Some(1.0));
+        assert_eq!(select(&prices, None, &PriceStrategy::default()), Some(2.0));
+        assert_eq!(select(&prices, None, &PriceStrategy::Median), Some(4.0));
+        // Отрезаем по одному лоту с каждой стороны: 2, 3, 4, 5
+        assert_eq!(select(&prices, None, &PriceStrategy::TrimmedMean { trim_pct: 20.0 }), Some(3.5));
+        assert_eq!(select(&[], None, &PriceStrategy::Min), None);
+    }
+
+    #[test]
+    fn test_volume_weighted_falls_back_to_mean() {
+        let prices = [1.0, 3.0];
+        assert_eq!(select(&prices, None, &PriceStrategy::VolumeWeighted), Some(2.0));
+        assert_eq!(select(&prices, Some(&[3.0, 1.0]), &PriceStrategy::VolumeWeighted), Some(1.5));
+        // Длины не совпадают — объёмы игнорируются
+        assert_eq!(select(&prices, Some(&[3.0]), &PriceStrategy::VolumeWeighted), Some(2.0));
+    }
+}
@@ -0,0 +1,485 @@
+//! Импорт истории сессий из нашего экспорта и CSV других трекеров
+//!
+//! Файл разбирается целиком, невалидные строки пропускаются с описанием ошибки.
+//! Дубликаты ищутся по id сессии и по времени начала (с точностью до секунды):
+//! сначала в самом файле, затем в существующей истории.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+
+use crate::export::{self, ExportEnvelope, SessionExport};
+use crate::persistence::SessionHistoryRecord;
+use crate::types::{
+    CsvMapping, DurationUnit, ExportKind, ImportConflict, ImportConflictReason, ImportIssue, ImportSource, MapRun,
+};
+
+/// Максимальный размер файла импорта
+const MAX_IMPORT_FILE_BYTES: u64 = 20 * 1024 * 1024;
+
+/// Прочитать файл импорта из IPC: только .csv / .json и не больше MAX_IMPORT_FILE_BYTES,
+/// чтобы через предпросмотр нельзя было читать произвольные файлы пользователя
+pub(crate) fn read_file(path: &str) -> Result<String, String> {
+    let path = Path::new(path);
+    let allowed = path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("csv") || e.eq_ignore_ascii_case("json"));
+    if !allowed {
+        return Err("Only .csv and .json files can be imported".to_string());
+    }
+    let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    if file.metadata().map_err(|e| e.to_string())?.len() > MAX_IMPORT_FILE_BYTES {
+        return Err(format!("Import file is larger than {} MB", MAX_IMPORT_FILE_BYTES / 1024 / 1024));
+    }
+    let mut content = String::new();
+    // Файл мог вырасти после проверки размера
+    file.take(MAX_IMPORT_FILE_BYTES + 1).read_to_string(&mut content).map_err(|e| e.to_string())?;
+    if content.len() as u64 > MAX_IMPORT_FILE_BYTES {
+        return Err(format!("Import file is larger than {} MB", MAX_IMPORT_FILE_BYTES / 1024 / 1024));
+    }
+    Ok(content)
+}
+
+/// Сессия из файла
+pub(crate) struct ImportedSession {
+    pub row: usize,
+    pub record: SessionHistoryRecord,
+    pub map_runs: Vec<MapRun>,
+}
+
+pub(crate) struct ParsedImport {
+    pub total_rows: usize,
+    pub sessions: Vec<ImportedSession>,
+    pub errors: Vec<ImportIssue>,
+}
+
+impl CsvMapping {
+    /// Колонки нашего CSV экспорта истории сессий
+    pub fn native() -> Self {
+        Self {
+            delimiter: None,
+            time_format: None,
+            started_at: "started_at".to_string(),
+            ended_at: Some("ended_at".to_string()),
+            duration: Some("duration_sec".to_string()),
+            duration_unit: DurationUnit::Seconds,
+            maps_completed: Some("maps_completed".to_string()),
+            income: Some("total_income".to_string()),
+            net_income: Some("net_income".to_string()),
+            expenses: Some("total_expenses".to_string()),
+            profit: Some("total_profit".to_string()),
+            session_id: Some("session_id".to_string()),
+            preset_id: Some("preset_id".to_string()),
+        }
+    }
+}
+
+/// Разобрать CSV (RFC 4180: поля в кавычках, `""` внутри кавычек, CRLF или LF)
+pub(crate) fn parse_csv(text: &str, delimiter: char) -> Result<Vec<Vec<String>>, String> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => in_quotes = true,
+            '\r' => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            c if c == delimiter => row.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err("Unterminated quoted field".to_string());
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    // Пустые строки (в т.ч. в конце файла) не считаются записями
+    rows.retain(|r| !(r.len() == 1 && r[0].trim().is_empty()));
+    Ok(rows)
+}
+
+fn parse_number(value: &str, decimal_comma: bool) -> Result<f64, String> {
+    let cleaned: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+    let cleaned = if decimal_comma { cleaned.replace(',', ".") } else { cleaned };
+    cleaned.parse::<f64>()
+        .ok()
+        .filter(|v| v.is_finite())
+        .ok_or_else(|| format!("Invalid number: {}", value))
+}
+
+fn parse_time(value: &str, format: Option<&str>) -> Result<DateTime<Utc>, String> {
+    let value = value.trim();
+    let parsed = match format {
+        Some(fmt) => DateTime::parse_from_str(value, fmt)
+            .map(|t| t.with_timezone(&Utc))
+            .ok()
+            .or_else(|| NaiveDateTime::parse_from_str(value, fmt).ok().map(|t| t.and_utc())),
+        None => DateTime::parse_from_rfc3339(value)
+            .map(|t| t.with_timezone(&Utc))
+            .ok()
+            .or_else(|| {
+                ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"]
+                    .iter()
+                    .find_map(|f| NaiveDateTime::parse_from_str(value, f).ok())
+                    .map(|t| t.and_utc())
+            }),
+    };
+    parsed.ok_or_else(|| format!("Invalid time: {}", value))
+}
+
+/// Проверить запись сессии
+fn validate(record: &SessionHistoryRecord) -> Result<(), String> {
+    if record.id.trim().is_empty() {
+        return Err("Empty session id".to_string());
+    }
+    if record.ended_at < record.started_at {
+        return Err("Session ends before it starts".to_string());
+    }
+    if record.total_duration_sec < 0 || record.maps_completed < 0 {
+        return Err("Negative duration or map count".to_string());
+    }
+    let amounts = [record.total_income, record.total_expenses, record.total_profit];
+    if amounts.iter().chain(record.net_income.iter()).any(|v| !v.is_finite()) {
+        return Err("Invalid amount".to_string());
+    }
+    if record.total_expenses < 0.0 {
+        return Err("Negative expenses".to_string());
+    }
+    if record.ended_at > Utc::now() + Duration::days(1) {
+        return Err("Session ends in the future".to_string());
+    }
+    Ok(())
+}
+
+fn parse_native_json(content: &str) -> Result<ParsedImport, String> {
+    let value: serde_json::Value = serde_json::from_str(content).map_err(|e| format!("Invalid JSON: {}", e))?;
+    let format = value.get("format").and_then(|v| v.as_str());
+    if format != Some(export::EXPORT_FORMAT_ID) {
+        return Err("Not a TLI Companion export file".to_string());
+    }
+    let version = value.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+    if version == 0 || version > export::EXPORT_VERSION as u64 {
+        return Err(format!("Unsupported export version: {}", version));
+    }
+    let kind: ExportKind = value.get("kind").cloned()
+        .and_then(|k| serde_json::from_value(k).ok())
+        .ok_or_else(|| "Missing export kind".to_string())?;
+
+    let entries: Vec<Result<(SessionHistoryRecord, Vec<MapRun>), String>> = match kind {
+        ExportKind::Sessions => value.get("data")
+            .and_then(|d| d.as_array())
+            .ok_or_else(|| "Missing sessions data".to_string())?
+            .iter()
+            .map(|entry| {
+                serde_json::from_value::<SessionHistoryRecord>(entry.clone())
+                    .map(|record| (record, Vec::new()))
+                    .map_err(|e| e.to_string())
+            })
+            .collect(),
+        ExportKind::Session => {
+            let envelope: ExportEnvelope<SessionExport> = serde_json::from_value(value)
+                .map_err(|e| format!("Invalid session export: {}", e))?;
+            vec![Ok((envelope.data.session, envelope.data.map_runs))]
+        }
+        ExportKind::Prices => return Err("Price exports cannot be imported as sessions".to_string()),
+    };
+
+    let total_rows = entries.len();
+    let mut sessions = Vec::with_capacity(total_rows);
+    let mut errors = Vec::new();
+    for (i, entry) in entries.into_iter().enumerate() {
+        let row = i + 1;
+        match entry.and_then(|(record, map_runs)| validate(&record).map(|_| (record, map_runs))) {
+            Ok((mut record, map_runs)) => {
+                // remote_id принадлежит чужой базе — при импорте не переносим
+                record.remote_id = None;
+                sessions.push(ImportedSession { row, record, map_runs });
+            }
+            Err(message) => errors.push(ImportIssue { row, message }),
+        }
+    }
+    Ok(ParsedImport { total_rows, sessions, errors })
+}
+
+/// Индексы колонок CSV по сопоставлению
+struct CsvColumns {
+    started_at: usize,
+    ended_at: Option<usize>,
+    duration: Option<usize>,
+    maps_completed: Option<usize>,
+    income: Option<usize>,
+    net_income: Option<usize>,
+    expenses: Option<usize>,
+    profit: Option<usize>,
+    session_id: Option<usize>,
+    preset_id: Option<usize>,
+    time_format: Option<String>,
+    /// Секунд в единице длительности
+    unit_sec: f64,
+    decimal_comma: bool,
+}
+
+impl CsvColumns {
+    fn resolve(header: &[String], mapping: &CsvMapping) -> Result<Self, String> {
+        let index: HashMap<String, usize> = header.iter()
+            .enumerate()
+            .map(|(i, h)| (h.trim().to_lowercase(), i))
+            .collect();
+        let find = |name: &str| index.get(&name.trim().to_lowercase())
+            .copied()
+            .ok_or_else(|| format!("Column not found: {}", name));
+        let optional = |name: &Option<String>| name.as_deref().map(find).transpose();
+
+        let columns = Self {
+            started_at: find(&mapping.started_at)?,
+            ended_at: optional(&mapping.ended_at)?,
+            duration: optional(&mapping.duration)?,
+            maps_completed: optional(&mapping.maps_completed)?,
+            income: optional(&mapping.income)?,
+            net_income: optional(&mapping.net_income)?,
+            expenses: optional(&mapping.expenses)?,
+            profit: optional(&mapping.profit)?,
+            session_id: optional(&mapping.session_id)?,
+            preset_id: optional(&mapping.preset_id)?,
+            time_format: mapping.time_format.clone(),
+            unit_sec: match mapping.duration_unit {
+                DurationUnit::Seconds => 1.0,
+                DurationUnit::Minutes => 60.0,
+                DurationUnit::Hours => 3600.0,
+            },
+            decimal_comma: mapping.delimiter.unwrap_or(',') != ',',
+        };
+        if columns.ended_at.is_none() && columns.duration.is_none() {
+            return Err("Mapping needs an end time or duration column".to_string());
+        }
+        if columns.income.is_none() && columns.profit.is_none() {
+            return Err("Mapping needs an income or profit column".to_string());
+        }
+        Ok(columns)
+    }
+
+    fn record(&self, fields: &[String]) -> Result<SessionHistoryRecord, String> {
+        let get = |col: Option<usize>| col
+            .and_then(|c| fields.get(c))
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty());
+        let num = |col: Option<usize>| get(col).map(|v| parse_number(v, self.decimal_comma)).transpose();
+        let time_format = self.time_format.as_deref();
+
+        let started_at = parse_time(get(Some(self.started_at)).ok_or("Missing start time")?, time_format)?;
+        let ended_at = get(self.ended_at).map(|v| parse_time(v, time_format)).transpose()?;
+        let duration_sec = num(self.duration)?.map(|d| (d * self.unit_sec).round() as i32);
+        let (ended_at, duration_sec) = match (ended_at, duration_sec) {
+            (Some(end), Some(d)) => (end, d),
+            (Some(end), None) => (end, (end - started_at).num_seconds() as i32),
+            (None, Some(d)) => (started_at + Duration::seconds(d as i64), d),
+            (None, None) => return Err("Missing end time and duration".to_string()),
+        };
+        let expenses = num(self.expenses)?.unwrap_or(0.0);
+        let (income, profit) = match (num(self.income)?, num(self.profit)?) {
+            (Some(i), Some(p)) => (i, p),
+            (Some(i), None) => (i, i - expenses),
+            (None, Some(p)) => (p + expenses, p),
+            (None, None) => return Err("Missing income and profit".to_string()),
+        };
+        Ok(SessionHistoryRecord {
+            id: get(self.session_id).map(str::to_string).unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            started_at,
+            ended_at,
+            maps_completed: num(self.maps_completed)?.map(|m| m.round() as i32).unwrap_or(0),
+            total_duration_sec: duration_sec,
+            total_profit: profit,
+            total_expenses: expenses,
+            total_income: income,
+            net_income: num(self.net_income)?,
+            income_by_source: Vec::new(),
+            remote_id: None,
+            preset_id: get(self.preset_id).map(str::to_string),
+            drops: HashMap::new(),
+            drop_values: HashMap::new(),
+            manual_drops: Vec::new(),
+            expenses: Vec::new(),
+        })
+    }
+}
+
+fn parse_csv_sessions(content: &str, mapping: &CsvMapping) -> Result<ParsedImport, String> {
+    let mut rows = parse_csv(content, mapping.delimiter.unwrap_or(','))?.into_iter();
+    let header = rows.next().ok_or_else(|| "Empty CSV file".to_string())?;
+    let columns = CsvColumns::resolve(&header, mapping)?;
+
+    let mut total_rows = 0;
+    let mut sessions = Vec::new();
+    let mut errors = Vec::new();
+    for (i, fields) in rows.enumerate() {
+        // Заголовок — строка 1
+        let row = i + 2;
+        total_rows += 1;
+        match columns.record(&fields).and_then(|record| validate(&record).map(|_| record)) {
+            Ok(record) => sessions.push(ImportedSession { row, record, map_runs: Vec::new() }),
+            Err(message) => errors.push(ImportIssue { row, message }),
+        }
+    }
+    Ok(ParsedImport { total_rows, sessions, errors })
+}
+
+/// Разобрать файл импорта. Err — файл целиком не подходит (формат, заголовок, сопоставление).
+pub(crate) fn parse(content: &str, source: &ImportSource) -> Result<ParsedImport, String> {
+    let mut parsed = match source {
+        ImportSource::Native => {
+            let trimmed = content.trim_start_matches('\u{feff}').trim_start();
+            if trimmed.starts_with('{') {
+                parse_native_json(trimmed)?
+            } else {
+                parse_csv_sessions(content, &CsvMapping::native())?
+            }
+        }
+        ImportSource::Csv { mapping } => parse_csv_sessions(content, mapping)?,
+    };
+    dedup_within_file(&mut parsed);
+    Ok(parsed)
+}
+
+fn start_key(at: DateTime<Utc>) -> i64 {
+    at.timestamp()
+}
+
+/// Повторы внутри файла: остаётся первая сессия
+fn dedup_within_file(parsed: &mut ParsedImport) {
+    let mut ids = HashSet::new();
+    let mut starts = HashSet::new();
+    let mut errors = Vec::new();
+    parsed.sessions.retain(|s| {
+        let unique = !ids.contains(&s.record.id) && !starts.contains(&start_key(s.record.started_at));
+        if unique {
+            ids.insert(s.record.id.clone());
+            starts.insert(start_key(s.record.started_at));
+        } else {
+            errors.push(ImportIssue { row: s.row, message: "Duplicate session in file".to_string() });
+        }
+        unique
+    });
+    parsed.errors.extend(errors);
+    parsed.errors.sort_by_key(|e| e.row);
+}
+
+/// Новые id сессиям, id которых уже занят в другом профиле: импорт не должен
+/// заменять чужую историю. Возвращает число переименованных сессий.
+pub(crate) fn reassign_foreign_ids(sessions: &mut [ImportedSession], foreign_ids: &HashSet<String>) -> usize {
+    let mut reassigned = 0;
+    for s in sessions.iter_mut().filter(|s| foreign_ids.contains(&s.record.id)) {
+        s.record.id = uuid::Uuid::new_v4().to_string();
+        reassigned += 1;
+    }
+    reassigned
+}
+
+/// Конфликты импортируемых сессий с историей (по id, затем по времени начала)
+pub(crate) fn find_conflicts(sessions: &[ImportedSession], existing: &[SessionHistoryRecord]) -> Vec<ImportConflict> {
+    let by_id: HashSet<&str> = existing.iter().map(|r| r.id.as_str()).collect();
+    let by_start: HashMap<i64, &str> = existing.iter()
+        .map(|r| (start_key(r.started_at), r.id.as_str()))
+        .collect();
+    sessions.iter().filter_map(|s| {
+        let (existing_id, reason) = if by_id.contains(s.record.id.as_str()) {
+            (s.record.id.clone(), ImportConflictReason::SameId)
+        } else {
+            let id = by_start.get(&start_key(s.record.started_at))?;
+            (id.to_string(), ImportConflictReason::SameStartTime)
+        };
+        Some(ImportConflict {
+            row: s.row,
+            session_id: s.record.id.clone(),
+            started_at: s.record.started_at,
+            existing_id,
+            reason,
+        })
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generic_csv_mapping_and_conflicts() {
+        let csv = "\u{feff}Start;Minutes;Loot;Spent;Note\r\n\
+            2026-01-02 10:00;90;1 200,5;200;\"a;b\"\r\n\
+            2026-01-03 10:00;30;;;\r\n\
+            2026-01-02 10:00;60;10;0;dup\r\n\
+            bad;60;10;0;\r\n";
+        let mapping = CsvMapping {
+            delimiter: Some(';'),
+            started_at: "start".into(),
+            duration: Some("Minutes".into()),
+            duration_unit: DurationUnit::Minutes,
+            income: Some("Loot".into()),
+            expenses: Some("Spent".into()),
+            ..CsvMapping::native()
+        };
+        // Колонок нашего экспорта в этом файле нет
+        assert!(parse(csv, &ImportSource::Csv { mapping: Box::new(CsvMapping::native()) }).is_err());
+        let mapping = CsvMapping { ended_at: None, maps_completed: None, net_income: None, profit: None, session_id: None, preset_id: None, ..mapping };
+        let parsed = parse(csv, &ImportSource::Csv { mapping: Box::new(mapping) }).unwrap();
+
+        assert_eq!(parsed.total_rows, 4);
+        assert_eq!(parsed.sessions.len(), 1);
+        let record = parsed.sessions[0].record.clone();
+        assert_eq!(record.total_duration_sec, 5400);
+        assert_eq!(record.total_income, 1200.5);
+        assert_eq!(record.total_profit, 1000.5);
+        // Строка 3 без дохода, строка 4 — дубликат по времени начала, строка 5 — неверное время
+        assert_eq!(parsed.errors.iter().map(|e| e.row).collect::<Vec<_>>(), vec![3, 4, 5]);
+
+        let mut existing = record.clone();
+        existing.id = "other".into();
+        let conflicts = find_conflicts(&parsed.sessions, &[existing]);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].reason, ImportConflictReason::SameStartTime);
+        assert_eq!(conflicts[0].existing_id, "other");
+
+        // Тот же id в другом профиле — импортируем под новым id, а не как замену
+        let mut sessions = parsed.sessions;
+        let foreign: HashSet<String> = [record.id.clone()].into_iter().collect();
+        assert_eq!(reassign_foreign_ids(&mut sessions, &foreign), 1);
+        assert_ne!(sessions[0].record.id, record.id);
+        let mut same_id = record.clone();
+        same_id.started_at += Duration::days(1);
+        assert!(find_conflicts(&sessions, &[same_id]).is_empty());
+    }
+
+    #[test]
+    fn test_read_file_accepts_only_import_formats() {
+        let dir = std::env::temp_dir().join(format!("tli-import-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let csv = dir.join("sessions.CSV");
+        std::fs::write(&csv, "started_at\r\n").unwrap();
+        assert_eq!(read_file(csv.to_str().unwrap()).unwrap(), "started_at\r\n");
+        let other = dir.join("id_rsa");
+        std::fs::write(&other, "secret").unwrap();
+        assert!(read_file(other.to_str().unwrap()).is_err());
+        let big = dir.join("big.json");
+        std::fs::File::create(&big).unwrap().set_len(MAX_IMPORT_FILE_BYTES + 1).unwrap();
+        assert!(read_file(big.to_str().unwrap()).unwrap_err().contains("larger"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -2,27 +2,103 @@
 //! 
 //! Управляет состоянием сессии фарма, кэшем предметов и настройками.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use tokio::sync::RwLock;
 use chrono::{DateTime, Utc};
-use log::{info, debug};
-use std::sync::{Arc, Mutex};
+use log::{info, debug, warn};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::sync::atomic::AtomicBool;
 
 use crate::types::{
     AppSettings, FarmSessionState, ItemInfo, SessionStats, 
-    ItemDropEvent, MapChangeEvent, MapEventType, AggregatedDrop, ExpenseEntry, ManualDropEntry
+    ItemDropEvent, MapChangeEvent, MapEventType, AggregatedDrop, ExpenseEntry, ManualDropEntry,
+    LogSourceConfig, LogSourceInfo, MapRun, MapRunStats, MapRunSummary,
+    DropRecord, RecentDrop, ValuePoint, PauseInterval, PauseKind,
+    StrategyPreset, PresetCostMode, PresetComparison, DropCorrection, DropCorrectionKind, ValuationRules, IncomeLedger,
+    DropRateBucket, ZoneDropStats, ExpectedDrop, Goal, GoalKind, GoalProgress, GoalReachedMark,
+    ForecastTarget, SessionForecast, PriceStrategy, PriceObservation, PriceObservationSource, PriceTrend,
+    PriceVolatility, HistoricalSessionValue, IncomeSource, PriceAlert, PriceAlertFired,
+    QuarantinedPrice, PriceSearchEvent, Denomination, SessionItemLine, PriceExportRow,
+    ImportSource, ImportPreview, ImportConflictPolicy, ImportResult, BackupInfo, BackupReason
 };
 use crate::log_parser::LogParser;
-use crate::persistence;
+use crate::file_watcher::LogWatcher;
+use crate::persistence::{self, MAIN_SOURCE_ID};
 use crate::auth::{AuthSession};
+use crate::valuation::Valuation;
+use crate::events::{DomainEvent, EventBus};
+use crate::drop_stats;
+use crate::forecast;
+use crate::price_selection;
+use crate::price_history;
+use crate::price_alerts;
+use crate::price_guard;
+use crate::freshness;
+use crate::denomination;
+use crate::export;
+use crate::session_import;
+use crate::backup;
+use crate::store::Store;
+
+/// Путь лог-файла для сравнения (на Windows регистр не важен)
+fn canonical_log_path(path: &str) -> String {
+    let canonical = std::fs::canonicalize(path)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| path.to_string());
+    if cfg!(windows) { canonical.to_lowercase() } else { canonical }
+}
+
+/// Отслеживаемый лог-файл (отдельный клиент игры / аккаунт).
+/// У каждого источника свой парсер, своя сессия и своя пауза.
+pub struct LogSource {
+    /// Стабильный ID источника ("main" для основного)
+    pub id: String,
+    /// Отображаемое имя
+    pub name: String,
+    /// Путь к файлу логов
+    pub log_path: RwLock<Option<String>>,
+    /// Текущая сессия фарма этого источника
+    pub session: RwLock<FarmSessionState>,
+    /// Флаг паузы сессии — если true, дропы не записываются
+    pub is_paused: RwLock<bool>,
+    /// Парсер логов этого источника (состояние слотов у каждого клиента своё)
+    pub log_parser: Arc<Mutex<LogParser>>,
+    /// Запущенный watcher (чтобы остановить при удалении источника)
+    pub watcher: Mutex<Option<LogWatcher>>,
+    /// Последнее событие лога (дроп, карта, прайсчек) — для автопаузы
+    pub last_activity_at: RwLock<Option<DateTime<Utc>>>,
+}
+
+impl LogSource {
+    pub fn new(id: &str, name: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            name: name.to_string(),
+            log_path: RwLock::new(None),
+            session: RwLock::new(FarmSessionState::default()),
+            is_paused: RwLock::new(false),
+            log_parser: Arc::new(Mutex::new(LogParser::new())),
+            watcher: Mutex::new(None),
+            last_activity_at: RwLock::new(None),
+        }
+    }
+
+    /// Остановить watcher источника (если запущен)
+    pub fn stop_watcher(&self) {
+        if let Some(watcher) = self.watcher.lock().unwrap().take() {
+            watcher.stop();
+        }
+    }
+}
 
 /// Глобальное состояние приложения
 pub struct AppState {
     /// Настройки приложения
     pub settings: RwLock<AppSettings>,
-    /// Текущая сессия фарма
-    pub session: RwLock<FarmSessionState>,
+    /// Источники логов (source_id -> LogSource), основной всегда есть
+    pub sources: RwLock<HashMap<String, Arc<LogSource>>>,
+    /// Источник, выбранный в UI (команды без source_id работают с ним)
+    pub selected_source: RwLock<String>,
     /// Кэш информации о предметах (game_id -> ItemInfo)
     pub items_cache: RwLock<HashMap<i64, ItemInfo>>,
     /// Кэш текущих цен (game_id -> price)
@@ -30,36 +106,201 @@ pub struct AppState {
     /// Флаг подключения к серверу (зарезервировано для будущего)
     #[allow(dead_code)]
     pub is_connected: RwLock<bool>,
-    /// Путь к файлу логов
-    pub log_path: RwLock<Option<String>>,
     /// Auth session (access token in-memory)
     pub auth_session: RwLock<Option<AuthSession>>,
     /// Cancel flag for in-progress OAuth login
     pub auth_oauth_cancel: RwLock<Option<Arc<AtomicBool>>>,
-    /// Общий парсер логов (нужен, чтобы сбрасывать кэш слотов при старте сессии)
-    #[allow(dead_code)]
-    pub log_parser: Arc<Mutex<LogParser>>,
-    /// Флаг паузы сессии — если true, дропы не записываются
-    pub is_paused: RwLock<bool>,
+    /// Пресеты стратегий фарма
+    pub presets: RwLock<Vec<StrategyPreset>>,
+    /// Пользовательские правила оценки предметов
+    pub valuation_rules: RwLock<ValuationRules>,
+    /// Шина доменных событий (подписчики: frontend, сохранение на диск)
+    pub events: EventBus,
+    /// Накопленная статистика дропа по зонам и пресетам
+    pub drop_stats: RwLock<Vec<DropRateBucket>>,
+    /// Цели сессии
+    pub goals: RwLock<Vec<Goal>>,
+    /// История наблюдений цен по game_id
+    pub price_history: RwLock<HashMap<i64, Vec<PriceObservation>>>,
+    /// Принятые в историю наблюдения цен, ещё не записанные в SQLite
+    price_history_queue: RwLock<Vec<(i64, PriceObservation)>>,
+    /// Ценовые оповещения
+    pub price_alerts: RwLock<Vec<PriceAlert>>,
+    /// Подозрительные прайсчеки, ждущие подтверждения
+    pub price_quarantine: RwLock<Vec<QuarantinedPrice>>,
+    /// Локальная база SQLite (None — не открылась, работаем с JSON файлами)
+    pub store: OnceLock<Store>,
 }
 
-const PRICE_TTL_SEC: i64 = 60 * 60; // 1 hour
+/// Период heartbeat'а активных сессий (сек)
+pub const SESSION_HEARTBEAT_SEC: u64 = 30;
+
+/// Если heartbeat не приходил дольше — считаем, что приложение было закрыто
+const SESSION_OFFLINE_GAP_SEC: i64 = 90;
 
 impl AppState {
-    /// Создать новое состояние
-    pub fn new(log_parser: Arc<Mutex<LogParser>>) -> Self {
+    /// Создать новое состояние (с одним основным источником логов)
+    pub fn new() -> Self {
+        let mut sources = HashMap::new();
+        sources.insert(
+            MAIN_SOURCE_ID.to_string(),
+            Arc::new(LogSource::new(MAIN_SOURCE_ID, "Main")),
+        );
         Self {
             settings: RwLock::new(AppSettings::default()),
-            session: RwLock::new(FarmSessionState::default()),
+            sources: RwLock::new(sources),
+            selected_source: RwLock::new(MAIN_SOURCE_ID.to_string()),
             items_cache: RwLock::new(HashMap::new()),
             prices_cache: RwLock::new(HashMap::new()),
             is_connected: RwLock::new(false),
-            log_path: RwLock::new(None),
             auth_session: RwLock::new(None),
             auth_oauth_cancel: RwLock::new(None),
-            log_parser,
-            is_paused: RwLock::new(false),
+            presets: RwLock::new(Vec::new()),
+            valuation_rules: RwLock::new(ValuationRules::default()),
+            events: EventBus::new(),
+            drop_stats: RwLock::new(Vec::new()),
+            goals: RwLock::new(Vec::new()),
+            price_history: RwLock::new(HashMap::new()),
+            price_history_queue: RwLock::new(Vec::new()),
+            price_alerts: RwLock::new(Vec::new()),
+            price_quarantine: RwLock::new(Vec::new()),
+            store: OnceLock::new(),
+        }
+    }
+
+    /// Получить источник по ID; None (или неизвестный ID) — выбранный в UI, затем основной
+    pub async fn source(&self, source_id: Option<&str>) -> Arc<LogSource> {
+        let sources = self.sources.read().await;
+        if let Some(src) = source_id.and_then(|id| sources.get(id)) {
+            return src.clone();
+        }
+        let selected = self.selected_source.read().await;
+        sources
+            .get(selected.as_str())
+            .or_else(|| sources.get(MAIN_SOURCE_ID))
+            .cloned()
+            .expect("main log source always exists")
+    }
+
+    /// Все источники (основной первым, остальные по имени)
+    pub async fn all_sources(&self) -> Vec<Arc<LogSource>> {
+        let sources = self.sources.read().await;
+        let mut list: Vec<Arc<LogSource>> = sources.values().cloned().collect();
+        list.sort_by(|a, b| {
+            (a.id != MAIN_SOURCE_ID, &a.name).cmp(&(b.id != MAIN_SOURCE_ID, &b.name))
+        });
+        list
+    }
+
+    /// Зарегистрировать дополнительные источники из настроек (при старте)
+    pub async fn register_sources_from_settings(&self) {
+        let configs = self.settings.read().await.log_sources.clone();
+        let mut sources = self.sources.write().await;
+        for cfg in configs {
+            if cfg.id == MAIN_SOURCE_ID || sources.contains_key(&cfg.id) {
+                continue;
+            }
+            let src = LogSource::new(&cfg.id, &cfg.name);
+            *src.log_path.write().await = Some(cfg.path.clone());
+            sources.insert(cfg.id.clone(), Arc::new(src));
+        }
+    }
+
+    /// Добавить источник логов (второй клиент) и сохранить в настройки
+    pub async fn add_log_source(&self, name: String, path: String) -> Result<Arc<LogSource>, String> {
+        self.ensure_log_path_free(&path, None).await?;
+        let id = uuid::Uuid::new_v4().simple().to_string()[..8].to_string();
+        let src = Arc::new(LogSource::new(&id, &name));
+        *src.log_path.write().await = Some(path.clone());
+        self.sources.write().await.insert(id.clone(), src.clone());
+
+        let mut settings = self.settings.write().await;
+        settings.log_sources.push(LogSourceConfig { id: id.clone(), name, path });
+        if let Err(e) = persistence::save_settings(&settings) {
+            log::warn!("Failed to save settings with new log source: {}", e);
+        }
+        info!("Added log source: {}", id);
+        Ok(src)
+    }
+
+    /// Один и тот же лог-файл нельзя отслеживать двумя источниками: дроп посчитается дважды
+    pub async fn ensure_log_path_free(&self, path: &str, except_source: Option<&str>) -> Result<(), String> {
+        let target = canonical_log_path(path);
+        let mut used: Vec<String> = Vec::new();
+        for src in self.all_sources().await {
+            if Some(src.id.as_str()) == except_source {
+                continue;
+            }
+            used.extend(src.log_path.read().await.clone());
+        }
+        {
+            let settings = self.settings.read().await;
+            if except_source != Some(MAIN_SOURCE_ID) {
+                used.extend(settings.custom_log_path.clone());
+            }
+            used.extend(settings.log_sources.iter()
+                .filter(|s| Some(s.id.as_str()) != except_source)
+                .map(|s| s.path.clone()));
+        }
+        if used.iter().any(|p| canonical_log_path(p) == target) {
+            return Err("This log file is already watched by another log source".to_string());
+        }
+        Ok(())
+    }
+
+    /// Удалить дополнительный источник (основной удалить нельзя)
+    pub async fn remove_log_source(&self, source_id: &str) -> Result<(), String> {
+        if source_id == MAIN_SOURCE_ID {
+            return Err("Main log source cannot be removed".to_string());
+        }
+        let src = self.sources.read().await.get(source_id).cloned()
+            .ok_or_else(|| "Unknown log source".to_string())?;
+        if src.session.read().await.started_at.is_some() {
+            return Err("End the session of this log source first".to_string());
+        }
+        src.stop_watcher();
+        self.sources.write().await.remove(source_id);
+
+        {
+            let mut selected = self.selected_source.write().await;
+            if selected.as_str() == source_id {
+                *selected = MAIN_SOURCE_ID.to_string();
+            }
+        }
+
+        let mut settings = self.settings.write().await;
+        settings.log_sources.retain(|s| s.id != source_id);
+        if let Err(e) = persistence::save_settings(&settings) {
+            log::warn!("Failed to save settings after removing log source: {}", e);
+        }
+        info!("Removed log source: {}", source_id);
+        Ok(())
+    }
+
+    /// Выбрать источник для UI
+    pub async fn select_source(&self, source_id: &str) -> Result<(), String> {
+        if !self.sources.read().await.contains_key(source_id) {
+            return Err("Unknown log source".to_string());
+        }
+        *self.selected_source.write().await = source_id.to_string();
+        Ok(())
+    }
+
+    /// Список источников для UI
+    pub async fn list_sources(&self) -> Vec<LogSourceInfo> {
+        let selected = self.selected_source.read().await.clone();
+        let mut result = Vec::new();
+        for src in self.all_sources().await {
+            result.push(LogSourceInfo {
+                id: src.id.clone(),
+                name: src.name.clone(),
+                log_path: src.log_path.read().await.clone(),
+                is_selected: src.id == selected,
+                session_active: src.session.read().await.started_at.is_some(),
+                is_watching: src.watcher.lock().unwrap().as_ref().is_some_and(|w| w.is_running()),
+            });
         }
+        result
     }
 
     pub async fn set_auth_session(&self, session: Option<AuthSession>) {
@@ -141,16 +382,381 @@ impl AppState {
         Some(crate::supabase_sync::SupabaseConfig::from_env_or_compile()?)
     }
     
+    /// Загрузить пресеты с диска
+    pub async fn load_presets_from_disk(&self) {
+        match persistence::load_presets() {
+            Ok(list) => {
+                debug!("Loaded {} presets from disk", list.len());
+                *self.presets.write().await = list;
+            }
+            Err(e) => {
+                debug!("Failed to load presets: {}", e);
+            }
+        }
+    }
+
+    /// Загрузить правила оценки предметов с диска
+    pub async fn load_valuation_rules_from_disk(&self) {
+        match persistence::load_valuation_rules() {
+            Ok(rules) => {
+                *self.valuation_rules.write().await = rules;
+            }
+            Err(e) => {
+                debug!("Failed to load valuation rules: {}", e);
+            }
+        }
+    }
+
+    /// Правила оценки предметов
+    pub async fn get_valuation_rules(&self) -> ValuationRules {
+        self.valuation_rules.read().await.clone()
+    }
+
+    /// Заменить правила оценки (с проверкой значений) и сохранить на диск
+    pub async fn save_valuation_rules(&self, rules: ValuationRules) -> Result<ValuationRules, String> {
+        if rules.fixed_prices.values().any(|p| !p.is_finite() || *p < 0.0) {
+            return Err("Fixed price must be a non-negative number".to_string());
+        }
+        if rules.min_unit_value.is_some_and(|v| !v.is_finite() || v < 0.0) {
+            return Err("Minimum unit value must be a non-negative number".to_string());
+        }
+        if rules.category_multipliers.values().any(|m| !m.is_finite() || *m < 0.0) {
+            return Err("Category multiplier must be a non-negative number".to_string());
+        }
+        let selection = &rules.price_selection;
+        for strategy in std::iter::once(&selection.default)
+            .chain(selection.categories.values())
+            .chain(selection.items.values())
+        {
+            price_selection::validate(strategy)?;
+        }
+        freshness::validate(&rules.freshness)?;
+        persistence::save_valuation_rules(&rules).map_err(|e| e.to_string())?;
+        *self.valuation_rules.write().await = rules.clone();
+        info!("Saved valuation rules: {} ignored, {} fixed, {} category multipliers",
+            rules.ignored_items.len(), rules.fixed_prices.len(), rules.category_multipliers.len());
+        Ok(rules)
+    }
+
+    /// Загрузить статистику дропа с диска
+    pub async fn load_drop_stats_from_disk(&self) {
+        match persistence::load_drop_stats() {
+            Ok(buckets) => {
+                debug!("Loaded drop stats for {} zone/preset buckets", buckets.len());
+                *self.drop_stats.write().await = buckets;
+            }
+            Err(e) => {
+                debug!("Failed to load drop stats: {}", e);
+            }
+        }
+    }
+
+    /// Статистика дропа по зонам: по одному накопителю на пару (зона, пресет).
+    /// Фильтры zone/preset_id — точное совпадение.
+    pub async fn get_drop_rate_stats(&self, zone: Option<&str>, preset_id: Option<&str>) -> Vec<ZoneDropStats> {
+        let buckets = self.drop_stats.read().await;
+        let items_cache = self.items_cache.read().await;
+        let mut result: Vec<ZoneDropStats> = buckets
+            .iter()
+            .filter(|b| zone.is_none_or(|z| b.zone == z))
+            .filter(|b| preset_id.is_none_or(|p| b.preset_id.as_deref() == Some(p)))
+            .map(|b| drop_stats::zone_stats(b, &items_cache))
+            .collect();
+        result.sort_by_key(|s| std::cmp::Reverse(s.maps));
+        result
+    }
+
+    /// Ожидаемый дроп за `maps` карт в зоне (без preset_id — по всем пресетам зоны)
+    pub async fn get_expected_drops(&self, zone: &str, maps: i32, preset_id: Option<&str>) -> Vec<ExpectedDrop> {
+        let buckets = self.drop_stats.read().await;
+        let items_cache = self.items_cache.read().await;
+        let merged = drop_stats::merge(
+            buckets
+                .iter()
+                .filter(|b| b.zone == zone)
+                .filter(|b| preset_id.is_none_or(|p| b.preset_id.as_deref() == Some(p))),
+        );
+        drop_stats::expected_drops(&merged, maps, &items_cache)
+    }
+
+    /// Загрузить цели с диска
+    pub async fn load_goals_from_disk(&self) {
+        match persistence::load_goals() {
+            Ok(list) => {
+                *self.goals.write().await = list;
+            }
+            Err(e) => {
+                debug!("Failed to load goals: {}", e);
+            }
+        }
+    }
+
+    /// Все цели
+    pub async fn get_goals(&self) -> Vec<Goal> {
+        self.goals.read().await.clone()
+    }
+
+    /// Создать (пустой id) или обновить цель
+    pub async fn save_goal(&self, mut goal: Goal) -> Result<Goal, String> {
+        if !goal.target.is_finite() || goal.target <= 0.0 {
+            return Err("Goal target must be positive".to_string());
+        }
+        if goal.kind == GoalKind::Item && goal.game_id.is_none() {
+            return Err("Item goal requires an item".to_string());
+        }
+
+        let mut goals = self.goals.write().await;
+        if goal.id.is_empty() {
+            goal.id = uuid::Uuid::new_v4().to_string();
+            goal.created_at = Utc::now();
+            goals.push(goal.clone());
+        } else {
+            let Some(existing) = goals.iter_mut().find(|g| g.id == goal.id) else {
+                return Err("Goal not found".to_string());
+            };
+            // Изменённая цель может стать недостигнутой — отметки сбрасываем
+            goal.created_at = existing.created_at;
+            goal.reached.clear();
+            *existing = goal.clone();
+        }
+        persistence::save_goals(&goals).map_err(|e| e.to_string())?;
+        Ok(goal)
+    }
+
+    /// Удалить цель
+    pub async fn delete_goal(&self, id: &str) -> Result<bool, String> {
+        let mut goals = self.goals.write().await;
+        let before = goals.len();
+        goals.retain(|g| g.id != id);
+        let removed = goals.len() != before;
+        if removed {
+            persistence::save_goals(&goals).map_err(|e| e.to_string())?;
+        }
+        Ok(removed)
+    }
+
+    /// Количество предмета в сессии (авто + ручной дроп)
+    async fn session_item_quantity(source: &LogSource, game_id: Option<i64>) -> i64 {
+        let Some(game_id) = game_id else {
+            return 0;
+        };
+        let session = source.session.read().await;
+        let auto = session.drops.get(&game_id).copied().unwrap_or(0) as i64;
+        let manual: i64 = session.manual_drops.iter()
+            .filter(|m| m.game_id == Some(game_id))
+            .map(|m| m.quantity as i64)
+            .sum();
+        auto + manual
+    }
+
+    /// Прогресс целей в текущей сессии источника
+    pub async fn get_goal_progress(&self, source: &LogSource) -> Vec<GoalProgress> {
+        let goals: Vec<Goal> = self.goals.read().await.iter()
+            .filter(|g| g.source_id.as_deref().is_none_or(|id| id == source.id))
+            .cloned()
+            .collect();
+        if goals.is_empty() {
+            return Vec::new();
+        }
+        let stats = self.get_session_stats(source).await;
+        let now = Utc::now();
+        let mut result = Vec::with_capacity(goals.len());
+        for goal in &goals {
+            let qty = Self::session_item_quantity(source, goal.game_id).await;
+            result.push(forecast::goal_progress(goal, &source.id, &stats, qty, now));
+        }
+        result
+    }
+
+    /// Отметить достигнутые цели (один раз на сессию) и опубликовать GoalReached
+    pub async fn check_goals(&self, source: &LogSource) {
+        let Some(started_at) = source.session.read().await.started_at else {
+            return;
+        };
+        let reached: Vec<GoalProgress> = self.get_goal_progress(source).await
+            .into_iter()
+            .filter(|p| p.reached)
+            .filter(|p| !p.goal.reached.iter().any(|m| m.source_id == source.id && m.session_started_at == started_at))
+            .collect();
+        if reached.is_empty() {
+            return;
+        }
+
+        let now = Utc::now();
+        let mut goals = self.goals.write().await;
+        for progress in &reached {
+            if let Some(goal) = goals.iter_mut().find(|g| g.id == progress.goal.id) {
+                goal.reached.retain(|m| m.source_id != source.id);
+                goal.reached.push(GoalReachedMark {
+                    source_id: source.id.clone(),
+                    session_started_at: started_at,
+                    reached_at: now,
+                });
+            }
+        }
+        if let Err(e) = persistence::save_goals(&goals) {
+            debug!("Failed to save goals: {}", e);
+        }
+        drop(goals);
+
+        for progress in reached {
+            info!("Goal reached: source={}, goal={}", source.id, progress.goal.id);
+            self.events.publish(DomainEvent::GoalReached { source_id: source.id.clone(), progress });
+        }
+    }
+
+    /// Прогноз итогов сессии по текущему темпу
+    pub async fn get_session_forecast(&self, source: &LogSource, target: &ForecastTarget) -> Result<SessionForecast, String> {
+        if !self.is_session_active(source).await {
+            return Err("No active session".to_string());
+        }
+        let stats = self.get_session_stats(source).await;
+        forecast::project(&stats, target, Utc::now())
+    }
+
+    /// Получить все пресеты
+    pub async fn get_presets(&self) -> Vec<StrategyPreset> {
+        self.presets.read().await.clone()
+    }
+
+    /// Создать или обновить пресет (пустой id — создать новый)
+    pub async fn save_preset(&self, mut preset: StrategyPreset) -> Result<StrategyPreset, String> {
+        if preset.name.trim().is_empty() {
+            return Err("Preset name is required".to_string());
+        }
+        if preset.consumables.iter().any(|c| c.quantity <= 0) {
+            return Err("Consumable quantity must be positive".to_string());
+        }
+
+        let now = Utc::now();
+        let mut presets = self.presets.write().await;
+        match presets.iter_mut().find(|p| !preset.id.is_empty() && p.id == preset.id) {
+            Some(existing) => {
+                preset.created_at = existing.created_at;
+                preset.updated_at = now;
+                *existing = preset.clone();
+            }
+            None => {
+                if preset.id.is_empty() {
+                    preset.id = uuid::Uuid::new_v4().to_string();
+                }
+                preset.created_at = now;
+                preset.updated_at = now;
+                presets.push(preset.clone());
+            }
+        }
+        persistence::save_presets(&presets).map_err(|e| e.to_string())?;
+        info!("Saved preset: {} ({})", preset.name, preset.id);
+        Ok(preset)
+    }
+
+    /// Удалить пресет
+    pub async fn delete_preset(&self, id: &str) -> Result<bool, String> {
+        let mut presets = self.presets.write().await;
+        let before = presets.len();
+        presets.retain(|p| p.id != id);
+        let removed = presets.len() != before;
+        if removed {
+            persistence::save_presets(&presets).map_err(|e| e.to_string())?;
+            info!("Deleted preset: {}", id);
+        }
+        Ok(removed)
+    }
+
+    /// Списать расходники пресета в траты сессии (maps — на сколько карт).
+    /// Одна строка трат на предмет: id "preset:<game_id>", количество накапливается.
+    async fn apply_preset_costs(&self, session: &mut FarmSessionState, maps: i32) {
+        let Some(preset_id) = session.preset_id.clone() else {
+            return;
+        };
+        let Some(preset) = self.presets.read().await.iter().find(|p| p.id == preset_id).cloned() else {
+            return;
+        };
+        let items_cache = self.items_cache.read().await;
+        let prices = self.prices_cache.read().await;
+        // Расходники оцениваем по рынку, без правил оценки дропа
+        let no_exclusions = HashSet::new();
+        let no_rules = ValuationRules::default();
+        let valuation = Valuation::new(&items_cache, &prices, &no_exclusions, &no_rules);
+
+        for c in &preset.consumables {
+            let quantity = c.quantity * maps;
+            if quantity <= 0 {
+                continue;
+            }
+            let id = format!("preset:{}", c.game_id);
+            if let Some(existing) = session.expenses.iter_mut().find(|e| e.id == id) {
+                existing.quantity += quantity;
+                continue;
+            }
+            let item = items_cache.get(&c.game_id);
+            let price = c.price.unwrap_or_else(|| valuation.unit_price(c.game_id));
+            session.expenses.push(ExpenseEntry {
+                id,
+                game_id: Some(c.game_id),
+                name: item.map(|i| i.name.clone()).unwrap_or_else(|| format!("ID: {}", c.game_id)),
+                name_ru: item.and_then(|i| i.name_ru.clone()),
+                quantity,
+                price,
+            });
+        }
+        debug!("Applied preset costs: preset={}, maps={}", preset_id, maps);
+    }
+
+    /// Сравнить текущую сессию с прошлыми сессиями того же пресета (локальная история)
+    pub async fn get_preset_comparison(&self, source: &LogSource) -> Option<PresetComparison> {
+        let preset_id = source.session.read().await.preset_id.clone()?;
+        let stats = self.get_session_stats(source).await;
+        let expenses: f64 = source.session.read().await.expenses.iter()
+            .map(|e| e.price * e.quantity as f64)
+            .sum();
+
+        let user_id = self.history_user_id().await;
+        let history = self.load_history(&user_id, None).await.unwrap_or_default();
+        let previous: Vec<_> = history.iter()
+            .filter(|h| h.preset_id.as_deref() == Some(preset_id.as_str()))
+            .collect();
+
+        let hourly = |profit: f64, sec: i32| if sec > 0 { profit / sec as f64 * 3600.0 } else { 0.0 };
+        let per_map = |profit: f64, maps: i32| if maps > 0 { profit / maps as f64 } else { 0.0 };
+
+        let current_profit = stats.net_value - expenses;
+        let mut result = PresetComparison {
+            preset_id,
+            previous_sessions: previous.len() as i32,
+            current_profit,
+            current_hourly_profit: hourly(current_profit, stats.duration_sec),
+            current_profit_per_map: per_map(current_profit, stats.maps_completed),
+            ..Default::default()
+        };
+        if previous.is_empty() {
+            return Some(result);
+        }
+
+        let n = previous.len() as f64;
+        let hourlies: Vec<f64> = previous.iter().map(|h| hourly(h.total_profit, h.total_duration_sec)).collect();
+        result.avg_profit = previous.iter().map(|h| h.total_profit).sum::<f64>() / n;
+        result.avg_hourly_profit = hourlies.iter().sum::<f64>() / n;
+        result.avg_profit_per_map = previous.iter().map(|h| per_map(h.total_profit, h.maps_completed)).sum::<f64>() / n;
+        result.best_hourly_profit = hourlies.iter().cloned().fold(f64::MIN, f64::max);
+        if result.avg_hourly_profit.abs() > f64::EPSILON {
+            result.hourly_delta_pct = Some(
+                (result.current_hourly_profit - result.avg_hourly_profit) / result.avg_hourly_profit.abs() * 100.0,
+            );
+        }
+        Some(result)
+    }
+
     /// Начать новую сессию фарма
-    pub async fn start_session(&self, preset_id: Option<String>) {
+    pub async fn start_session(&self, source: &LogSource, preset_id: Option<String>) {
         // Сбрасываем паузу при начале новой сессии
         {
-            let mut p = self.is_paused.write().await;
+            let mut p = source.is_paused.write().await;
             *p = false;
         }
         
         let now = Utc::now();
-        let mut session = self.session.write().await;
+        let mut session = source.session.write().await;
         *session = FarmSessionState {
             session_id: None,
             started_at: Some(now),
@@ -167,26 +773,52 @@ impl AppState {
             expenses: Vec::new(),
             manual_drops: Vec::new(),
             session_duration_sec: 0,
+            map_runs: Vec::new(),
+            drop_log: Vec::new(),
+            pauses: Vec::new(),
+            last_seen_at: Some(now),
+            excluded_items: HashSet::new(),
+            drop_corrections: Vec::new(),
+        };
+        // Пресет со списанием «сразу»: траты на весь план карт
+        let up_front_maps = {
+            let presets = self.presets.read().await;
+            session.preset_id.as_ref()
+                .and_then(|id| presets.iter().find(|p| &p.id == id))
+                .filter(|p| p.cost_mode == PresetCostMode::UpFront)
+                .map(|p| p.expected_maps.unwrap_or(1).max(1))
         };
-        info!("Farm session started");
+        if let Some(maps) = up_front_maps {
+            self.apply_preset_costs(&mut session, maps).await;
+        }
+
+        *source.last_activity_at.write().await = Some(now);
+        info!("Farm session started: source={}", source.id);
         // Auto-save session
-        Self::save_session_internal(&session);
+        self.save_session_internal(source, &mut session);
+        drop(session);
+        self.events.publish(DomainEvent::SessionStarted { source_id: source.id.clone() });
     }
     
-    /// Загрузить сессию с диска (для восстановления после краша)
-    pub async fn load_session_from_disk(&self) -> bool {
-        match persistence::load_session() {
-            Ok(Some(session)) => {
+    /// Загрузить сессию источника с диска (для восстановления после краша)
+    pub async fn load_session_from_disk(&self, source: &LogSource) -> bool {
+        let loaded = match self.store() {
+            Some(store) => store.load_active_session(&source.id),
+            None => persistence::load_session(&source.id),
+        };
+        match loaded {
+            Ok(Some(mut session)) => {
+                Self::account_offline_gap(&mut session, Utc::now());
                 info!("Restored session from disk, duration: {} sec, paused: {}", 
                     session.session_duration_sec, session.is_paused);
                 // Восстанавливаем состояние паузы
                 let was_paused = session.is_paused;
                 {
-                    let mut p = self.is_paused.write().await;
+                    let mut p = source.is_paused.write().await;
                     *p = was_paused;
                 }
                 
-                let mut s = self.session.write().await;
+                let mut s = source.session.write().await;
                 *s = session;
                 info!("Restored session from disk, paused: {}", was_paused);
                 true
@@ -199,74 +831,247 @@ impl AppState {
         }
     }
     
-    /// Внутренний helper для сохранения сессии
-    fn save_session_internal(session: &FarmSessionState) {
-        let _ = persistence::save_session(session);
-    }
-    
-    /// Установить состояние паузы
-    pub async fn set_paused(&self, paused: bool) {
-        {
-            let mut p = self.is_paused.write().await;
-            *p = paused;
+    /// Время, пока приложение было закрыто, не считается активным: добавляем его как паузу.
+    fn account_offline_gap(session: &mut FarmSessionState, now: DateTime<Utc>) {
+        let Some(started) = session.started_at else {
+            return;
+        };
+        // legacy: файлы без heartbeat — активным было время, которое досчитал фронтенд
+        let last_seen = session.last_seen_at.unwrap_or_else(|| {
+            started + chrono::Duration::seconds(session.session_duration_sec.max(0) as i64)
+        });
+
+        if !session.is_paused && (now - last_seen).num_seconds() > SESSION_OFFLINE_GAP_SEC {
+            session.pauses.push(PauseInterval { started_at: last_seen, ended_at: Some(now), kind: PauseKind::Offline });
+            info!("Session was offline for {} sec, not counted as active", (now - last_seen).num_seconds());
         }
-        
-        // Сохраняем состояние паузы в сессию на диск
-        {
-            let mut session = self.session.write().await;
-            if session.started_at.is_some() {
-                session.is_paused = paused;
-                Self::save_session_internal(&session);
-                info!("Session paused: {}", paused);
-            }
+        // legacy: пауза без интервала — открываем его с последнего heartbeat
+        if session.is_paused {
+            session.open_pause(last_seen, PauseKind::Manual);
         }
+        session.last_seen_at = Some(now);
+        session.session_duration_sec = session.active_duration_sec(now);
     }
-    
-    /// Обновить время сессии (вызывается фронтендом)
-    pub async fn update_session_duration(&self, duration_sec: i32) {
-        let mut session = self.session.write().await;
-        if session.started_at.is_some() {
-            session.session_duration_sec = duration_sec;
-            Self::save_session_internal(&session);
+
+    /// Heartbeat активных сессий: фиксируем, что приложение живо, и обновляем время.
+    /// Вызывается периодически из main (SESSION_HEARTBEAT_SEC).
+    pub async fn tick_sessions(&self) {
+        let now = Utc::now();
+        for source in self.all_sources().await {
+            let mut session = source.session.write().await;
+            if session.started_at.is_none() {
+                continue;
+            }
+            session.last_seen_at = Some(now);
+            session.session_duration_sec = session.active_duration_sec(now);
+            self.save_session_internal(&source, &mut session);
         }
     }
-    
-    /// Проверить, на паузе ли сессия
-    pub async fn is_paused(&self) -> bool {
-        *self.is_paused.read().await
+
+    /// Отметить активность игрока: любое разобранное событие лога
+    pub async fn touch_activity(source: &LogSource) {
+        *source.last_activity_at.write().await = Some(Utc::now());
     }
-    
-    /// Добавить трату вручную
-    pub async fn add_expense(&self, id: String, game_id: Option<i64>, name: String, name_ru: Option<String>, quantity: i32, price: f64) {
-        let mut session = self.session.write().await;
-        // Траты можно добавлять даже без активной сессии (пресет)
-        session.expenses.push(ExpenseEntry {
-            id,
-            game_id,
-            name,
-            name_ru,
-            quantity,
-            price,
-        });
-        info!("Added expense: {} (game_id={:?}) x{} @ {}", 
-            session.expenses.last().map(|e| &e.name).unwrap_or(&"?".to_string()), 
-            game_id, quantity, price);
-        // Auto-save if session is active
-        if session.started_at.is_some() {
-            Self::save_session_internal(&session);
+
+    /// Автопауза: в убежище дольше auto_pause_idle_sec без событий лога.
+    /// Пауза ставится задним числом — с момента последней активности.
+    pub async fn check_idle(&self) {
+        let (enabled, idle_sec) = {
+            let s = self.settings.read().await;
+            (s.auto_pause_enabled, s.auto_pause_idle_sec)
+        };
+        if !enabled || idle_sec <= 0 {
+            return;
         }
-    }
-    
-    /// Удалить трату
-    pub async fn remove_expense(&self, id: &str) {
-        let mut session = self.session.write().await;
-        session.expenses.retain(|e| e.id != id);
+
+        let now = Utc::now();
+        for source in self.all_sources().await {
+            if *source.is_paused.read().await {
+                continue;
+            }
+            let mut session = source.session.write().await;
+            let Some(started) = session.started_at else {
+                continue;
+            };
+            if session.is_on_map {
+                continue;
+            }
+
+            let last_activity = {
+                let mut last = source.last_activity_at.write().await;
+                // После старта приложения активности ещё не было — считаем от текущего момента
+                *last.get_or_insert(now)
+            };
+            if (now - last_activity).num_seconds() < idle_sec {
+                continue;
+            }
+
+            // Не заходим на уже учтённое время (старт сессии, конец предыдущей паузы)
+            let prev_pause_end = session.pauses.last().and_then(|p| p.ended_at);
+            let pause_start = [Some(last_activity), Some(started), prev_pause_end]
+                .into_iter()
+                .flatten()
+                .max()
+                .unwrap_or(now);
+
+            session.open_pause(pause_start, PauseKind::Idle);
+            session.is_paused = true;
+            session.session_duration_sec = session.active_duration_sec(now);
+            self.save_session_internal(&source, &mut session);
+            drop(session);
+            *source.is_paused.write().await = true;
+
+            info!("Auto-paused session: source={}, idle since {}", source.id, pause_start);
+            self.events.publish(DomainEvent::Paused {
+                source_id: source.id.clone(),
+                paused: true,
+                kind: PauseKind::Idle,
+                at: pause_start,
+            });
+        }
+    }
+
+    /// Снять автопаузу при входе на карту (ручную паузу не трогаем)
+    pub async fn auto_resume(&self, source: &LogSource) -> bool {
+        let mut session = source.session.write().await;
+        if session.started_at.is_none() {
+            return false;
+        }
+        let is_idle_pause = session.open_pause_mut().map(|p| p.kind == PauseKind::Idle).unwrap_or(false);
+        if !is_idle_pause {
+            return false;
+        }
+
+        let now = Utc::now();
+        session.close_pause(now);
+        session.is_paused = false;
+        session.session_duration_sec = session.active_duration_sec(now);
+        self.save_session_internal(source, &mut session);
+        drop(session);
+        *source.is_paused.write().await = false;
+
+        info!("Auto-resumed session on map entry: source={}", source.id);
+        self.events.publish(DomainEvent::Paused {
+            source_id: source.id.clone(),
+            paused: false,
+            kind: PauseKind::Idle,
+            at: now,
+        });
+        true
+    }
+
+    /// Интервалы паузы текущей сессии
+    pub async fn get_pause_intervals(&self, source: &LogSource) -> Vec<PauseInterval> {
+        source.session.read().await.pauses.clone()
+    }
+
+    /// Сохранить сессию источника на диск (если она активна)
+    pub async fn persist_session(&self, source: &LogSource) {
+        let mut session = source.session.write().await;
+        if session.started_at.is_some() {
+            self.save_session_internal(source, &mut session);
+        }
+    }
+
+    /// Внутренний helper для сохранения сессии (SQLite, если доступна, иначе JSON)
+    fn save_session_internal(&self, source: &LogSource, session: &mut FarmSessionState) {
+        let result = match self.store() {
+            Some(store) => store.save_active_session(&source.id, session),
+            None => persistence::save_session(&source.id, session),
+        };
+        if let Err(e) = result {
+            debug!("Failed to save session: {}", e);
+        }
+    }
+    
+    /// Установить состояние паузы
+    pub async fn set_paused(&self, source: &LogSource, paused: bool) {
+        {
+            let mut p = source.is_paused.write().await;
+            *p = paused;
+        }
+        
+        // Сохраняем состояние паузы в сессию на диск
+        {
+            let mut session = source.session.write().await;
+            if session.started_at.is_some() {
+                let now = Utc::now();
+                if paused {
+                    session.open_pause(now, PauseKind::Manual);
+                    // Ручная пауза поверх автопаузы: EnterMap её уже не снимет
+                    if let Some(p) = session.open_pause_mut() {
+                        p.kind = PauseKind::Manual;
+                    }
+                } else {
+                    session.close_pause(now);
+                }
+                session.is_paused = paused;
+                session.session_duration_sec = session.active_duration_sec(now);
+                self.save_session_internal(source, &mut session);
+                info!("Session paused: {}", paused);
+                self.events.publish(DomainEvent::Paused {
+                    source_id: source.id.clone(),
+                    paused,
+                    kind: PauseKind::Manual,
+                    at: now,
+                });
+            }
+        }
+    }
+    
+    /// Обновить время сессии (вызывается фронтендом).
+    /// Время теперь считает бэкенд; значение фронтенда только логируем при расхождении.
+    pub async fn update_session_duration(&self, source: &LogSource, duration_sec: i32) {
+        let mut session = source.session.write().await;
+        if session.started_at.is_some() {
+            let now = Utc::now();
+            let backend_sec = session.active_duration_sec(now);
+            if (backend_sec - duration_sec).abs() > 5 {
+                debug!("Frontend duration {} differs from backend clock {}", duration_sec, backend_sec);
+            }
+            session.session_duration_sec = backend_sec;
+        }
+    }
+    
+    /// Проверить, на паузе ли сессия
+    pub async fn is_paused(&self, source: &LogSource) -> bool {
+        *source.is_paused.read().await
+    }
+    
+    /// Добавить трату вручную
+    #[allow(clippy::too_many_arguments)]
+    pub async fn add_expense(&self, source: &LogSource, id: String, game_id: Option<i64>, name: String, name_ru: Option<String>, quantity: i32, price: f64) {
+        let mut session = source.session.write().await;
+        // Траты можно добавлять даже без активной сессии (пресет)
+        session.expenses.push(ExpenseEntry {
+            id,
+            game_id,
+            name,
+            name_ru,
+            quantity,
+            price,
+        });
+        info!("Added expense: {} (game_id={:?}) x{} @ {}", 
+            session.expenses.last().map(|e| &e.name).unwrap_or(&"?".to_string()), 
+            game_id, quantity, price);
+        // Auto-save if session is active
+        if session.started_at.is_some() {
+            self.save_session_internal(source, &mut session);
+        }
+        self.events.publish(DomainEvent::SessionUpdated { source_id: source.id.clone() });
+    }
+    
+    /// Удалить трату
+    pub async fn remove_expense(&self, source: &LogSource, id: &str) {
+        let mut session = source.session.write().await;
+        session.expenses.retain(|e| e.id != id);
         info!("Removed expense: {}", id);
+        self.events.publish(DomainEvent::SessionUpdated { source_id: source.id.clone() });
     }
     
     /// Получить список трат
-    pub async fn get_expenses(&self) -> Vec<ExpenseEntry> {
-        let session = self.session.read().await;
+    pub async fn get_expenses(&self, source: &LogSource) -> Vec<ExpenseEntry> {
+        let session = source.session.read().await;
         session.expenses.clone()
     }
     
@@ -292,8 +1097,9 @@ impl AppState {
     }
     
     /// Добавить ручной дроп (для уников/экипировки)
-    pub async fn add_manual_drop(&self, id: String, game_id: Option<i64>, name: String, name_ru: Option<String>, quantity: i32, price: f64) {
-        let mut session = self.session.write().await;
+    #[allow(clippy::too_many_arguments)]
+    pub async fn add_manual_drop(&self, source: &LogSource, id: String, game_id: Option<i64>, name: String, name_ru: Option<String>, quantity: i32, price: f64) {
+        let mut session = source.session.write().await;
         // Ручной дроп можно добавлять только в активную сессию
         if session.started_at.is_some() {
             session.manual_drops.push(ManualDropEntry {
@@ -308,49 +1114,198 @@ impl AppState {
                 session.manual_drops.last().map(|e| &e.name).unwrap_or(&"?".to_string()), 
                 game_id, quantity, price);
             // Auto-save session
-            Self::save_session_internal(&session);
+            self.save_session_internal(source, &mut session);
+            self.events.publish(DomainEvent::SessionUpdated { source_id: source.id.clone() });
         }
     }
     
     /// Удалить ручной дроп
-    pub async fn remove_manual_drop(&self, id: &str) {
-        let mut session = self.session.write().await;
+    pub async fn remove_manual_drop(&self, source: &LogSource, id: &str) {
+        let mut session = source.session.write().await;
         session.manual_drops.retain(|e| e.id != id);
         info!("Removed manual drop: {}", id);
+        self.events.publish(DomainEvent::SessionUpdated { source_id: source.id.clone() });
     }
     
     /// Получить список ручного дропа
-    pub async fn get_manual_drops(&self) -> Vec<ManualDropEntry> {
-        let session = self.session.read().await;
+    pub async fn get_manual_drops(&self, source: &LogSource) -> Vec<ManualDropEntry> {
+        let session = source.session.read().await;
         session.manual_drops.clone()
     }
     
+    /// Исправить количество авто-дропа (например, парсер посчитал перенос из склада).
+    /// Разница пишется в drop_log поправкой, чтобы drops оставался агрегатом хронологии.
+    /// Дроп по картам не меняется — неизвестно, на какой карте была ошибка
+    /// (в статистику дропа по зонам исправленные предметы не попадают).
+    pub async fn adjust_drop_quantity(&self, source: &LogSource, game_id: i64, quantity: i32) -> Result<DropCorrection, String> {
+        if quantity < 0 {
+            return Err("Quantity cannot be negative".to_string());
+        }
+        let mut session = source.session.write().await;
+        if session.started_at.is_none() {
+            return Err("No active session".to_string());
+        }
+        let before = session.drops.get(&game_id).copied().unwrap_or(0);
+        let now = Utc::now();
+        if quantity != before {
+            if quantity == 0 {
+                session.drops.remove(&game_id);
+            } else {
+                session.drops.insert(game_id, quantity);
+            }
+            session.drop_log.push(DropRecord {
+                timestamp: now,
+                game_id,
+                quantity: quantity - before,
+                map_index: None,
+                correction: true,
+            });
+        }
+        let correction = DropCorrection {
+            at: now,
+            kind: DropCorrectionKind::AdjustQuantity,
+            game_id,
+            quantity_before: before,
+            quantity_after: quantity,
+            undone: None,
+        };
+        session.drop_corrections.push(correction.clone());
+        self.save_session_internal(source, &mut session);
+        info!("Adjusted drop: source={}, game_id={}, {} -> {}", source.id, game_id, before, quantity);
+        self.events.publish(DomainEvent::SessionUpdated { source_id: source.id.clone() });
+        Ok(correction)
+    }
+
+    /// Исключить предмет из подсчёта стоимости (или вернуть обратно)
+    pub async fn set_drop_excluded(&self, source: &LogSource, game_id: i64, excluded: bool) -> Result<Option<DropCorrection>, String> {
+        let mut session = source.session.write().await;
+        if session.started_at.is_none() {
+            return Err("No active session".to_string());
+        }
+        let changed = if excluded {
+            session.excluded_items.insert(game_id)
+        } else {
+            session.excluded_items.remove(&game_id)
+        };
+        if !changed {
+            return Ok(None);
+        }
+        let quantity = session.drops.get(&game_id).copied().unwrap_or(0);
+        let correction = DropCorrection {
+            at: Utc::now(),
+            kind: if excluded { DropCorrectionKind::Exclude } else { DropCorrectionKind::Include },
+            game_id,
+            quantity_before: quantity,
+            quantity_after: quantity,
+            undone: None,
+        };
+        session.drop_corrections.push(correction.clone());
+        self.save_session_internal(source, &mut session);
+        info!("Drop excluded={}: source={}, game_id={}", excluded, source.id, game_id);
+        self.events.publish(DomainEvent::SessionUpdated { source_id: source.id.clone() });
+        Ok(Some(correction))
+    }
+
+    /// Отменить последние N авто-подборов (из сессии и из карт, где они были).
+    /// Останавливается на подборе, количество которого потом правили вручную.
+    pub async fn undo_last_drops(&self, source: &LogSource, count: usize) -> Result<Vec<DropCorrection>, String> {
+        let mut session = source.session.write().await;
+        if session.started_at.is_none() {
+            return Err("No active session".to_string());
+        }
+        let now = Utc::now();
+        let mut corrections = Vec::new();
+        while corrections.len() < count {
+            let Some(pos) = session.drop_log.iter().rposition(|d| !d.correction) else {
+                break;
+            };
+            // Количество уже исправлено вручную после подбора: откат разошёлся бы с поправкой
+            let game_id = session.drop_log[pos].game_id;
+            if session.drop_log[pos + 1..].iter().any(|d| d.correction && d.game_id == game_id) {
+                if corrections.is_empty() {
+                    return Err("Drop quantity was adjusted after this pickup; adjust it instead".to_string());
+                }
+                break;
+            }
+            let record = session.drop_log.remove(pos);
+            let before = session.drops.get(&record.game_id).copied().unwrap_or(0);
+            let after = (before - record.quantity).max(0);
+            if after == 0 {
+                session.drops.remove(&record.game_id);
+            } else {
+                session.drops.insert(record.game_id, after);
+            }
+            if let Some(run) = record.map_index.and_then(|i| session.map_runs.get_mut((i - 1).max(0) as usize)) {
+                if let Some(qty) = run.drops.get_mut(&record.game_id) {
+                    *qty -= record.quantity;
+                    if *qty <= 0 {
+                        run.drops.remove(&record.game_id);
+                    }
+                }
+            }
+            corrections.push(DropCorrection {
+                at: now,
+                kind: DropCorrectionKind::UndoDrop,
+                game_id: record.game_id,
+                quantity_before: before,
+                quantity_after: after,
+                undone: Some(record),
+            });
+        }
+        if !corrections.is_empty() {
+            session.drop_corrections.extend(corrections.iter().cloned());
+            self.save_session_internal(source, &mut session);
+            info!("Undid {} drops: source={}", corrections.len(), source.id);
+            self.events.publish(DomainEvent::SessionUpdated { source_id: source.id.clone() });
+        }
+        Ok(corrections)
+    }
+
+    /// Журнал правок дропа текущей сессии
+    pub async fn get_drop_corrections(&self, source: &LogSource) -> Vec<DropCorrection> {
+        source.session.read().await.drop_corrections.clone()
+    }
+
     /// Завершить сессию
-    pub async fn end_session(&self) -> FarmSessionState {
+    pub async fn end_session(&self, source: &LogSource) -> FarmSessionState {
         // Сбрасываем паузу при завершении сессии
         {
-            let mut p = self.is_paused.write().await;
+            let mut p = source.is_paused.write().await;
             *p = false;
         }
         
-        let session = self.session.read().await;
+        let session = source.session.read().await;
         let result = session.clone();
         drop(session);
+
+        // Карты сессии — в статистику дропа по зонам
+        if result.started_at.is_some() {
+            let mut buckets = self.drop_stats.write().await;
+            drop_stats::record_session(&mut buckets, &result);
+            if let Err(e) = persistence::save_drop_stats(&buckets) {
+                debug!("Failed to save drop stats: {}", e);
+            }
+        }
         
-        let mut session = self.session.write().await;
+        let mut session = source.session.write().await;
         *session = FarmSessionState::default();
-        info!("Farm session ended");
+        info!("Farm session ended: source={}", source.id);
         
         // Delete session file (normal end)
-        let _ = persistence::delete_session();
+        let _ = match self.store() {
+            Some(store) => store.delete_active_session(&source.id),
+            None => persistence::delete_session(&source.id),
+        };
+        drop(session);
+        self.events.publish(DomainEvent::SessionEnded { source_id: source.id.clone() });
         
         result
     }
     
     /// Обработать событие входа на карту (зарезервировано)
     #[allow(dead_code)]
-    pub async fn handle_map_enter(&self, ts: DateTime<Utc>) {
-        let mut session = self.session.write().await;
+    pub async fn handle_map_enter(&self, source: &LogSource, ts: DateTime<Utc>) {
+        let mut session = source.session.write().await;
         if session.started_at.is_none() {
             return;
         }
@@ -366,8 +1321,8 @@ impl AppState {
     
     /// Обработать событие выхода с карты (зарезервировано)
     #[allow(dead_code)]
-    pub async fn handle_map_exit(&self, ts: DateTime<Utc>) {
-        let mut session = self.session.write().await;
+    pub async fn handle_map_exit(&self, source: &LogSource, ts: DateTime<Utc>) {
+        let mut session = source.session.write().await;
         if session.started_at.is_none() {
             return;
         }
@@ -386,146 +1341,863 @@ impl AppState {
                 session.total_duration_sec += duration as i32;
             }
         }
-        
-        session.is_on_map = false;
-        session.current_map_started = None;
-        debug!("Exited map, total maps: {}", session.maps_completed);
+        
+        session.is_on_map = false;
+        session.current_map_started = None;
+        debug!("Exited map, total maps: {}", session.maps_completed);
+    }
+    
+    /// Обработать событие смены карты
+    pub async fn handle_map_change(&self, source: &LogSource, event: &MapChangeEvent) {
+        let mut session = source.session.write().await;
+        if session.started_at.is_none() {
+            return;
+        }
+
+        // 1) Жёсткая дедупликация: одинаковое событие по той же сцене, пришедшее почти сразу.
+        if let (Some(last_ty), Some(last_ts), Some(last_scene)) = (
+            &session.last_map_event_type,
+            session.last_map_event_ts,
+            &session.last_map_scene,
+        ) {
+            if last_ty == &event.event_type && last_scene == &event.scene_name {
+                let dt = (event.timestamp - last_ts).num_seconds().abs();
+                if dt <= 2 {
+                    return;
+                }
+            }
+        }
+
+        // 2) Анти-дубль: два подряд Exit без Enter между ними — игнорируем второй и далее.
+        if event.event_type == MapEventType::ExitToHideout
+            && session.last_map_event_type == Some(MapEventType::ExitToHideout)
+        {
+            // Мы уже в “убежище” по логике сессии.
+            session.last_map_event_ts = Some(event.timestamp);
+            session.last_map_scene = Some(event.scene_name.clone());
+            return;
+        }
+
+        match event.event_type {
+            MapEventType::EnterMap => {
+                if !session.is_on_map {
+                    session.is_on_map = true;
+                    session.current_map_started = Some(event.timestamp);
+                    session.map_runs.push(MapRun {
+                        scene_name: event.scene_name.clone(),
+                        entered_at: event.timestamp,
+                        exited_at: None,
+                        drops: HashMap::new(),
+                    });
+
+                    // Пресет со списанием «за карту»
+                    let per_map = {
+                        let presets = self.presets.read().await;
+                        session.preset_id.as_ref()
+                            .and_then(|id| presets.iter().find(|p| &p.id == id))
+                            .map(|p| p.cost_mode == PresetCostMode::PerMap)
+                            .unwrap_or(false)
+                    };
+                    if per_map {
+                        self.apply_preset_costs(&mut session, 1).await;
+                    }
+                    self.events.publish(DomainEvent::MapEntered {
+                        source_id: source.id.clone(),
+                        event: event.clone(),
+                    });
+                }
+            }
+            MapEventType::ExitToHideout => {
+                // Считаем карту завершённой. Если EnterMap не был пойман (старт сессии внутри карты),
+                // считаем что карта началась в момент старта сессии.
+                let map_started = session.current_map_started.or(session.started_at);
+                session.maps_completed += 1;
+
+                if let Some(started) = map_started {
+                    let duration = (event.timestamp - started).num_seconds();
+                    if duration > 0 {
+                        session.total_duration_sec += duration as i32;
+                    }
+                }
+
+                match session.current_map_run_mut() {
+                    Some(run) => run.exited_at = Some(event.timestamp),
+                    None => {
+                        // Вход на карту не пойман — заводим запись задним числом
+                        let scene_name = session.last_map_scene.clone().unwrap_or_default();
+                        session.map_runs.push(MapRun {
+                            scene_name,
+                            entered_at: map_started.unwrap_or(event.timestamp),
+                            exited_at: Some(event.timestamp),
+                            drops: HashMap::new(),
+                        });
+                    }
+                }
+
+                session.is_on_map = false;
+                session.current_map_started = None;
+                self.events.publish(DomainEvent::MapCompleted {
+                    source_id: source.id.clone(),
+                    event: event.clone(),
+                    map_index: session.map_runs.len() as i32,
+                });
+            }
+        }
+
+        session.last_map_event_type = Some(event.event_type.clone());
+        session.last_map_event_ts = Some(event.timestamp);
+        session.last_map_scene = Some(event.scene_name.clone());
+    }
+    
+    /// Добавить дроп
+    /// Игнорирует предметы, которых нет в items_cache (неизвестные предметы)
+    pub async fn add_drop(&self, source: &LogSource, event: &ItemDropEvent) {
+        let session_guard = source.session.read().await;
+        if session_guard.started_at.is_none() {
+            return;
+        }
+        drop(session_guard);
+        
+        // Игнорируем дроп если сессия на паузе
+        if self.is_paused(source).await {
+            debug!("Ignoring drop while paused: game_id={}", event.game_id);
+            return;
+        }
+        
+        // Проверяем, есть ли предмет в нашей БД
+        let items = self.items_cache.read().await;
+        if !items.contains_key(&event.game_id) {
+            debug!("Ignoring drop of unknown item: game_id={}", event.game_id);
+            return;
+        }
+        drop(items);
+        
+        let mut session = source.session.write().await;
+        // Повторная проверка после получения write lock
+        if session.started_at.is_none() {
+            return;
+        }
+        
+        let current = session.drops.get(&event.game_id).copied().unwrap_or(0);
+        session.drops.insert(event.game_id, current + event.quantity);
+
+        // Дроп на карте — записываем и в текущую карту (в убежище дроп идёт только в сессию)
+        let map_count = session.map_runs.len() as i32;
+        let map_index = match session.current_map_run_mut() {
+            Some(run) => {
+                *run.drops.entry(event.game_id).or_insert(0) += event.quantity;
+                Some(map_count)
+            }
+            None => None,
+        };
+        session.drop_log.push(DropRecord {
+            timestamp: event.timestamp,
+            game_id: event.game_id,
+            quantity: event.quantity,
+            map_index,
+            correction: false,
+        });
+        
+        debug!("Added drop: source={}, game_id={}, qty={}, total={}", 
+               source.id, event.game_id, event.quantity, current + event.quantity);
+        
+        // Auto-save session (до публикации события)
+        self.save_session_internal(source, &mut session);
+        self.events.publish(DomainEvent::DropAdded {
+            source_id: source.id.clone(),
+            drop: event.clone(),
+        });
+    }
+    
+    /// Обновить цену предмета в кэше
+    pub async fn update_price(&self, game_id: i64, price: f64, strategy: Option<PriceStrategy>) {
+        self.apply_price(game_id, price, strategy, Utc::now()).await;
+    }
+
+    /// Цена, наблюдённая в момент `at`: в историю — на это время, в кэш — если там нет цены новее
+    async fn apply_price(&self, game_id: i64, price: f64, strategy: Option<PriceStrategy>, at: DateTime<Utc>) {
+        // Проверяем, является ли предмет базовой валютой
+        let items = self.items_cache.read().await;
+        if let Some(item) = items.get(&game_id) {
+            if item.is_base_currency {
+                debug!("Skipping price update for base currency: game_id={}", game_id);
+                return;
+            }
+        }
+        drop(items);
+        
+        // Без стратегии цена введена вручную, а не выбрана из лотов прайсчека
+        let source = if strategy.is_some() { PriceObservationSource::PriceCheck } else { PriceObservationSource::Manual };
+        let mut prices = self.prices_cache.write().await;
+        if prices.get(&game_id).is_some_and(|p| p.updated_at > at) {
+            debug!("Keeping newer cached price: game_id={}, observed price={} at {}", game_id, price, at);
+        } else {
+            prices.insert(game_id, persistence::PersistedPriceEntry { 
+                price, 
+                updated_at: at,
+                is_current_league: true,  // Цена получена через прайсчек = текущая лига
+                league_name: None,
+                strategy,
+                source: Some(source),
+            });
+            debug!("Updated price: game_id={}, price={}", game_id, price);
+        }
+        drop(prices);
+
+        if price_history::record(&mut *self.price_history.write().await, game_id, price, at, source) {
+            self.queue_price_observations(vec![(game_id, PriceObservation { at, price, source })]).await;
+        }
+        self.check_price_alerts(&[game_id]).await;
+
+        // На диск кэш пишет подписчик шины событий (events::spawn_persistence)
+        self.events.publish(DomainEvent::PriceUpdated { game_id, price });
+    }
+
+    /// Курс валюты прайсчека в базовой валюте (None — курс неизвестен)
+    pub async fn currency_rate(&self, currency_id: i64) -> Option<f64> {
+        let items = self.items_cache.read().await;
+        let is_base = match items.get(&currency_id) {
+            Some(item) => item.is_base_currency,
+            None => currency_id == denomination::BASE_CURRENCY_ID,
+        };
+        drop(items);
+        if is_base {
+            return Some(1.0);
+        }
+        self.prices_cache.read().await.get(&currency_id)
+            .map(|p| p.price)
+            .filter(|p| p.is_finite() && *p > 0.0)
+    }
+
+    /// Цены лотов прайсчека в базовой валюте (None — курс валюты прайсчека неизвестен)
+    pub async fn lot_prices_in_base(&self, event: &PriceSearchEvent) -> Option<Vec<f64>> {
+        let rate = self.currency_rate(event.currency_id).await?;
+        Some(event.prices.iter().map(|p| p * rate).collect())
+    }
+
+    /// Валюта отображения: None или базовая валюта — курс 1.0, иначе цена предмета из кэша
+    pub async fn resolve_denomination(&self, game_id: Option<i64>) -> Result<Denomination, String> {
+        let items = self.items_cache.read().await;
+        let item = game_id.and_then(|id| items.get(&id)).cloned();
+        drop(items);
+        let Some(game_id) = game_id.filter(|_| !item.as_ref().is_some_and(|i| i.is_base_currency)) else {
+            return Ok(Denomination { game_id: None, item_info: item, rate: 1.0, rate_updated_at: None, rate_is_stale: false });
+        };
+
+        let rules = self.valuation_rules.read().await;
+        let prices = self.prices_cache.read().await;
+        let entry = prices.get(&game_id)
+            .filter(|p| p.price.is_finite() && p.price > 0.0)
+            .ok_or_else(|| "No known price for the denomination item".to_string())?;
+        let fresh = freshness::assess(entry, game_id, item.as_ref(), &rules.freshness, Utc::now());
+        Ok(Denomination {
+            game_id: Some(game_id),
+            item_info: item,
+            rate: entry.price,
+            rate_updated_at: Some(entry.updated_at),
+            rate_is_stale: fresh.is_stale,
+        })
+    }
+
+    /// Валюта отображения для ответа: запрошенная или из настроек. None — базовая валюта
+    /// (пересчёт не нужен). Если курс валюты из настроек неизвестен — значения в базовой валюте.
+    pub async fn display_denomination(&self, requested: Option<i64>) -> Result<Option<Denomination>, String> {
+        if requested.is_some() {
+            return self.resolve_denomination(requested).await.map(Some);
+        }
+        let Some(game_id) = self.settings.read().await.display_denomination else {
+            return Ok(None);
+        };
+        match self.resolve_denomination(Some(game_id)).await {
+            Ok(d) => Ok(Some(d)),
+            Err(e) => {
+                debug!("Display denomination {} unavailable, using base currency: {}", game_id, e);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Выбрать цену из цен лотов прайсчека по стратегии предмета
+    pub async fn select_market_price(&self, game_id: i64, prices: &[f64]) -> Option<(f64, PriceStrategy)> {
+        let strategy = {
+            let rules = self.valuation_rules.read().await;
+            let items = self.items_cache.read().await;
+            price_selection::resolve(&rules.price_selection, game_id, &items)
+        };
+        // Объёмов лотов в логе нет — VolumeWeighted считает каждый лот с весом 1
+        price_selection::select(prices, None, &strategy).map(|p| (p, strategy))
+    }
+
+    /// Отправить прайсчек в карантин, если цена сильно расходится с историей или remote ценой
+    pub async fn quarantine_if_suspicious(&self, event: &PriceSearchEvent, price: f64, strategy: PriceStrategy) -> bool {
+        if self.items_cache.read().await.get(&event.game_id).is_some_and(|i| i.is_base_currency) {
+            return false;
+        }
+        let reason = {
+            let history = self.price_history.read().await;
+            let series = history.get(&event.game_id).map(|s| s.as_slice()).unwrap_or(&[]);
+            price_guard::check(price, series, Utc::now())
+        };
+        let Some(reason) = reason else {
+            return false;
+        };
+
+        let entry = QuarantinedPrice {
+            id: uuid::Uuid::new_v4().to_string(),
+            game_id: event.game_id,
+            price,
+            strategy,
+            prices: event.prices.clone(),
+            currency_id: event.currency_id,
+            observed_at: event.timestamp,
+            reason,
+            pending_checks: 1,
+        };
+        info!("Price check quarantined: game_id={}, price={}, reason={:?}", entry.game_id, price, entry.reason);
+        let mut quarantine = self.price_quarantine.write().await;
+        // Прайсчеки предмета, не согласующиеся с новым, больше не нужны;
+        // согласующиеся копятся (рынок мог сдвинуться) — решение всё равно за пользователем
+        quarantine.retain(|q| q.game_id != entry.game_id || price_guard::consistent(price, q.price));
+        quarantine.push(entry.clone());
+        let pending = quarantine.iter().filter(|q| q.game_id == entry.game_id).count();
+        if pending > price_guard::MAX_PENDING_PER_ITEM {
+            if let Some(oldest) = quarantine.iter().position(|q| q.game_id == entry.game_id) {
+                quarantine.remove(oldest);
+            }
+        }
+        Self::recount_pending(&mut quarantine, entry.game_id);
+        let entry = quarantine.last().cloned().unwrap_or(entry);
+        if let Err(e) = persistence::save_price_quarantine(&quarantine) {
+            debug!("Failed to save price quarantine: {}", e);
+        }
+        drop(quarantine);
+
+        self.events.publish(DomainEvent::PriceQuarantined { entry });
+        true
+    }
+
+    /// Прайсчеки в карантине
+    pub async fn get_quarantined_prices(&self) -> Vec<QuarantinedPrice> {
+        self.price_quarantine.read().await.clone()
+    }
+
+    /// Обновить pending_checks у прайсчеков предмета
+    fn recount_pending(quarantine: &mut [QuarantinedPrice], game_id: i64) {
+        let pending = quarantine.iter().filter(|q| q.game_id == game_id).count();
+        for q in quarantine.iter_mut().filter(|q| q.game_id == game_id) {
+            q.pending_checks = pending;
+        }
+    }
+
+    async fn take_quarantined_price(&self, id: &str) -> Result<QuarantinedPrice, String> {
+        let mut quarantine = self.price_quarantine.write().await;
+        let idx = quarantine.iter().position(|q| q.id == id)
+            .ok_or_else(|| "Quarantined price not found".to_string())?;
+        let entry = quarantine.remove(idx);
+        Self::recount_pending(&mut quarantine, entry.game_id);
+        persistence::save_price_quarantine(&quarantine).map_err(|e| e.to_string())?;
+        Ok(entry)
+    }
+
+    /// Подтвердить цену из карантина: она применяется как обычный прайсчек на момент наблюдения
+    pub async fn confirm_quarantined_price(&self, id: &str) -> Result<QuarantinedPrice, String> {
+        let entry = self.take_quarantined_price(id).await?;
+        self.apply_price(entry.game_id, entry.price, Some(entry.strategy), entry.observed_at).await;
+        Ok(entry)
+    }
+
+    /// Подтвердить разом все прайсчеки предмета в карантине (в порядке наблюдения)
+    pub async fn confirm_quarantined_item(&self, game_id: i64) -> Result<Vec<QuarantinedPrice>, String> {
+        let mut quarantine = self.price_quarantine.write().await;
+        let (mut confirmed, rest): (Vec<_>, Vec<_>) = quarantine.drain(..).partition(|q| q.game_id == game_id);
+        *quarantine = rest;
+        if confirmed.is_empty() {
+            return Err("No quarantined prices for this item".to_string());
+        }
+        persistence::save_price_quarantine(&quarantine).map_err(|e| e.to_string())?;
+        drop(quarantine);
+
+        confirmed.sort_by_key(|q| q.observed_at);
+        for entry in &confirmed {
+            self.apply_price(entry.game_id, entry.price, Some(entry.strategy), entry.observed_at).await;
+        }
+        Ok(confirmed)
+    }
+
+    /// Отклонить цену из карантина
+    pub async fn reject_quarantined_price(&self, id: &str) -> Result<QuarantinedPrice, String> {
+        self.take_quarantined_price(id).await
+    }
+
+    /// Загрузить карантин прайсчеков с диска
+    pub async fn load_price_quarantine_from_disk(&self) {
+        match persistence::load_price_quarantine() {
+            Ok(list) => {
+                *self.price_quarantine.write().await = list;
+            }
+            Err(e) => {
+                debug!("Failed to load price quarantine: {}", e);
+            }
+        }
+    }
+
+    /// Сохранить цены и историю цен, чтобы они переживали новую сессию/перезапуск.
+    /// В SQLite пишутся только изменившиеся предметы (None — все), в JSON — весь кэш.
+    /// Ошибки не фейлят приложение.
+    pub async fn persist_prices(&self, game_ids: Option<&HashSet<i64>>) {
+        let Some(store) = self.store() else {
+            let snapshot = self.prices_cache.read().await.clone();
+            if let Err(e) = persistence::save_prices_cache(&snapshot) {
+                debug!("Failed to persist prices cache: {}", e);
+            }
+            let history = self.price_history.read().await.clone();
+            if let Err(e) = persistence::save_price_history(&history) {
+                debug!("Failed to persist price history: {}", e);
+            }
+            return;
+        };
+
+        let wanted = |id: &i64| game_ids.is_none_or(|ids| ids.contains(id));
+        let entries: Vec<(i64, persistence::PersistedPriceEntry)> = self.prices_cache.read().await.iter()
+            .filter(|(id, _)| wanted(id))
+            .map(|(id, p)| (*id, p.clone()))
+            .collect();
+        // Все принятые с прошлой записи наблюдения (в т.ч. вставленные в середину ряда)
+        let observations = std::mem::take(&mut *self.price_history_queue.write().await);
+        if let Err(e) = store.upsert_prices(&entries) {
+            debug!("Failed to persist prices: {}", e);
+        }
+        if let Err(e) = store.append_price_observations(&observations) {
+            debug!("Failed to persist price history: {}", e);
+        }
+    }
+
+    /// Поставить принятые наблюдения цен в очередь записи в SQLite (JSON пишет ряд целиком)
+    async fn queue_price_observations(&self, observations: Vec<(i64, PriceObservation)>) {
+        if self.store().is_some() && !observations.is_empty() {
+            self.price_history_queue.write().await.extend(observations);
+        }
+    }
+
+    /// Открыть SQLite базу (при первом запуске — импорт JSON файлов).
+    /// Вызывать после регистрации источников логов и до загрузки данных.
+    pub async fn open_store(&self) {
+        let store = match Store::open_default() {
+            Ok(store) => store,
+            Err(e) => {
+                warn!("Failed to open local database, falling back to JSON files: {}", e);
+                return;
+            }
+        };
+        let source_ids: Vec<String> = self.all_sources().await.iter().map(|s| s.id.clone()).collect();
+        if let Err(e) = store.import_json_if_needed(&source_ids) {
+            warn!("Failed to import JSON data into the local database: {}", e);
+        }
+        let _ = self.store.set(store);
+    }
+
+    pub(crate) fn store(&self) -> Option<&Store> {
+        self.store.get()
+    }
+
+    /// Владелец локальной истории: пользователь или гостевой профиль
+    pub async fn history_user_id(&self) -> String {
+        self.get_auth_user_id().await
+            .unwrap_or_else(|| persistence::GUEST_USER_ID.to_string())
+    }
+
+    async fn count_history(&self, user_id: &str) -> Result<usize, String> {
+        match self.store() {
+            Some(store) => store.count_history(user_id),
+            None => persistence::load_session_history(user_id).map(|s| s.len()),
+        }
+        .map_err(|e| e.to_string())
+    }
+
+    /// Сколько гостевых сессий предложить перенести в аккаунт (0 — предлагать нечего)
+    pub async fn guest_history_pending(&self) -> usize {
+        let Some(user_id) = self.get_auth_user_id().await else {
+            return 0;
+        };
+        if self.settings.read().await.guest_merge_declined.contains(&user_id) {
+            return 0;
+        }
+        self.count_history(persistence::GUEST_USER_ID).await.unwrap_or(0)
+    }
+
+    /// Перенести гостевую историю в историю текущего аккаунта (дедупликация по id сессии)
+    pub async fn merge_guest_history(&self) -> Result<usize, String> {
+        let user_id = self.get_auth_user_id().await
+            .ok_or_else(|| "Not logged in".to_string())?;
+        let merged = match self.store() {
+            Some(store) => store.reassign_history(persistence::GUEST_USER_ID, &user_id),
+            None => persistence::merge_session_history(persistence::GUEST_USER_ID, &user_id),
+        }
+        .map_err(|e| e.to_string())?;
+        info!("Merged {} guest sessions into account history", merged);
+        Ok(merged)
+    }
+
+    /// Отказаться от переноса гостевой истории для текущего аккаунта
+    pub async fn decline_guest_history_merge(&self) -> Result<(), String> {
+        let user_id = self.get_auth_user_id().await
+            .ok_or_else(|| "Not logged in".to_string())?;
+        {
+            let mut settings = self.settings.write().await;
+            if settings.guest_merge_declined.contains(&user_id) {
+                return Ok(());
+            }
+            settings.guest_merge_declined.push(user_id);
+        }
+        self.save_settings_to_disk().await;
+        Ok(())
+    }
+
+    /// Добавить завершённую сессию в локальную историю пользователя
+    pub async fn add_history_record(&self, user_id: &str, record: persistence::SessionHistoryRecord, map_runs: &[MapRun]) -> Result<(), String> {
+        match self.store() {
+            Some(store) => store.insert_history_record(user_id, &record, map_runs),
+            None => persistence::add_session_to_history(user_id, record),
+        }
+        .map_err(|e| e.to_string())
+    }
+
+    /// Локальная история сессий, новые первыми (limit = None — вся)
+    pub async fn load_history(&self, user_id: &str, limit: Option<usize>) -> Result<Vec<persistence::SessionHistoryRecord>, String> {
+        match self.store() {
+            Some(store) => store.load_history(user_id, limit),
+            None => persistence::load_session_history(user_id).map(|mut sessions| {
+                if let Some(limit) = limit {
+                    sessions.truncate(limit);
+                }
+                sessions
+            }),
+        }
+        .map_err(|e| e.to_string())
+    }
+
+    /// Одна сессия из локальной истории
+    pub async fn get_history_record(&self, user_id: &str, session_id: &str) -> Result<Option<persistence::SessionHistoryRecord>, String> {
+        match self.store() {
+            Some(store) => store.get_history_record(user_id, session_id),
+            None => persistence::load_session_history(user_id)
+                .map(|sessions| sessions.into_iter().find(|s| s.id == session_id)),
+        }
+        .map_err(|e| e.to_string())
+    }
+
+    /// Id сессий из истории остальных профилей
+    pub async fn other_profiles_history_ids(&self, user_id: &str) -> Result<HashSet<String>, String> {
+        let ids = match self.store() {
+            Some(store) => store.other_users_history_ids(user_id),
+            None => persistence::list_history_user_ids().and_then(|users| {
+                let mut ids = HashSet::new();
+                for other in users.iter().filter(|u| u.as_str() != user_id) {
+                    ids.extend(persistence::load_session_history(other)?.into_iter().map(|r| r.id));
+                }
+                Ok(ids)
+            }),
+        };
+        ids.map_err(|e| e.to_string())
+    }
+
+    /// Удалить сессию из локальной истории
+    pub async fn delete_history_record(&self, user_id: &str, session_id: &str) -> Result<bool, String> {
+        match self.store() {
+            Some(store) => store.delete_history_record(user_id, session_id),
+            None => persistence::delete_session_from_history(user_id, session_id).map(|r| r.is_some()),
+        }
+        .map_err(|e| e.to_string())
+    }
+
+    /// Загрузить ценовые оповещения с диска
+    pub async fn load_price_alerts_from_disk(&self) {
+        match persistence::load_price_alerts() {
+            Ok(list) => {
+                *self.price_alerts.write().await = list;
+            }
+            Err(e) => {
+                debug!("Failed to load price alerts: {}", e);
+            }
+        }
+    }
+
+    /// Все ценовые оповещения
+    pub async fn get_price_alerts(&self) -> Vec<PriceAlert> {
+        self.price_alerts.read().await.clone()
+    }
+
+    /// Создать (пустой id) или обновить ценовое оповещение
+    pub async fn save_price_alert(&self, mut alert: PriceAlert) -> Result<PriceAlert, String> {
+        price_alerts::validate(&alert)?;
+        if !self.items_cache.read().await.contains_key(&alert.game_id) {
+            return Err("Unknown item".to_string());
+        }
+
+        let mut alerts = self.price_alerts.write().await;
+        if alert.id.is_empty() {
+            alert.id = uuid::Uuid::new_v4().to_string();
+            alert.created_at = Utc::now();
+            alert.last_fired_at = None;
+            alerts.push(alert.clone());
+        } else {
+            let Some(existing) = alerts.iter_mut().find(|a| a.id == alert.id) else {
+                return Err("Alert not found".to_string());
+            };
+            alert.created_at = existing.created_at;
+            *existing = alert.clone();
+        }
+        persistence::save_price_alerts(&alerts).map_err(|e| e.to_string())?;
+        Ok(alert)
     }
-    
-    /// Обработать событие смены карты
-    pub async fn handle_map_change(&self, event: &MapChangeEvent) {
-        let mut session = self.session.write().await;
-        if session.started_at.is_none() {
-            return;
+
+    /// Удалить ценовое оповещение
+    pub async fn delete_price_alert(&self, id: &str) -> Result<bool, String> {
+        let mut alerts = self.price_alerts.write().await;
+        let before = alerts.len();
+        alerts.retain(|a| a.id != id);
+        let removed = alerts.len() != before;
+        if removed {
+            persistence::save_price_alerts(&alerts).map_err(|e| e.to_string())?;
         }
+        Ok(removed)
+    }
 
-        // 1) Жёсткая дедупликация: одинаковое событие по той же сцене, пришедшее почти сразу.
-        if let (Some(last_ty), Some(last_ts), Some(last_scene)) = (
-            &session.last_map_event_type,
-            session.last_map_event_ts,
-            &session.last_map_scene,
-        ) {
-            if last_ty == &event.event_type && last_scene == &event.scene_name {
-                let dt = (event.timestamp - last_ts).num_seconds().abs();
-                if dt <= 2 {
-                    return;
-                }
-            }
+    /// Проверить оповещения по предметам с изменившейся ценой
+    async fn check_price_alerts(&self, game_ids: &[i64]) {
+        let ids: HashSet<i64> = game_ids.iter().copied().collect();
+        let candidates: Vec<PriceAlert> = self.price_alerts.read().await.iter()
+            .filter(|a| a.enabled && ids.contains(&a.game_id))
+            .cloned()
+            .collect();
+        if candidates.is_empty() {
+            return;
         }
 
-        // 2) Анти-дубль: два подряд Exit без Enter между ними — игнорируем второй и далее.
-        if event.event_type == MapEventType::ExitToHideout
-            && session.last_map_event_type == Some(MapEventType::ExitToHideout)
-        {
-            // Мы уже в “убежище” по логике сессии.
-            session.last_map_event_ts = Some(event.timestamp);
-            session.last_map_scene = Some(event.scene_name.clone());
+        let now = Utc::now();
+        let fired: Vec<PriceAlertFired> = {
+            let prices = self.prices_cache.read().await;
+            let history = self.price_history.read().await;
+            let items = self.items_cache.read().await;
+            candidates.iter().filter_map(|alert| {
+                let price = prices.get(&alert.game_id)?.price;
+                let series = history.get(&alert.game_id).map(|s| s.as_slice()).unwrap_or(&[]);
+                price_alerts::evaluate(alert, price, series, items.get(&alert.game_id), now)
+            }).collect()
+        };
+        if fired.is_empty() {
             return;
         }
 
-        match event.event_type {
-            MapEventType::EnterMap => {
-                if !session.is_on_map {
-                    session.is_on_map = true;
-                    session.current_map_started = Some(event.timestamp);
-                }
+        let mut alerts = self.price_alerts.write().await;
+        for f in &fired {
+            if let Some(a) = alerts.iter_mut().find(|a| a.id == f.alert.id) {
+                a.last_fired_at = Some(now);
             }
-            MapEventType::ExitToHideout => {
-                // Считаем карту завершённой. Если EnterMap не был пойман (старт сессии внутри карты),
-                // считаем что карта началась в момент старта сессии.
-                let map_started = session.current_map_started.or(session.started_at);
-                session.maps_completed += 1;
+        }
+        if let Err(e) = persistence::save_price_alerts(&alerts) {
+            debug!("Failed to save price alerts: {}", e);
+        }
+        drop(alerts);
 
-                if let Some(started) = map_started {
-                    let duration = (event.timestamp - started).num_seconds();
-                    if duration > 0 {
-                        session.total_duration_sec += duration as i32;
+        for f in fired {
+            info!("Price alert fired: alert={}, game_id={}, price={}", f.alert.id, f.alert.game_id, f.price);
+            self.events.publish(DomainEvent::PriceAlertFired { fired: f });
+        }
+    }
+
+    /// Загрузить историю цен с диска
+    pub async fn load_price_history_from_disk(&self) {
+        let loaded = match self.store() {
+            Some(store) => store.load_price_history(None, Utc::now() - chrono::Duration::days(price_history::MAX_AGE_DAYS)),
+            None => persistence::load_price_history(),
+        };
+        match loaded {
+            Ok(items) => {
+                debug!("Loaded price history for {} items", items.len());
+                let mut history = self.price_history.write().await;
+                // merge: наблюдения, записанные до загрузки, не теряем
+                for (game_id, mut series) in items {
+                    if let Some(fresh) = history.remove(&game_id) {
+                        series.extend(fresh);
+                        series.sort_by_key(|o| o.at);
                     }
+                    price_history::trim(&mut series);
+                    history.insert(game_id, series);
                 }
-
-                session.is_on_map = false;
-                session.current_map_started = None;
+            }
+            Err(e) => {
+                debug!("Failed to load price history: {}", e);
             }
         }
-
-        session.last_map_event_type = Some(event.event_type.clone());
-        session.last_map_event_ts = Some(event.timestamp);
-        session.last_map_scene = Some(event.scene_name.clone());
     }
-    
-    /// Добавить дроп
-    /// Игнорирует предметы, которых нет в items_cache (неизвестные предметы)
-    pub async fn add_drop(&self, event: &ItemDropEvent) {
-        let session_guard = self.session.read().await;
-        if session_guard.started_at.is_none() {
-            return;
+
+    /// Наблюдения цены предмета за последние `days` дней (None — вся история)
+    pub async fn get_price_history(&self, game_id: i64, days: Option<i64>) -> Vec<PriceObservation> {
+        // В SQLite история не ограничена по длине и возрасту
+        if let Some(store) = self.store() {
+            let since = days.map(|d| Utc::now() - chrono::Duration::days(d)).unwrap_or(DateTime::<Utc>::MIN_UTC);
+            match store.load_price_history(Some(game_id), since) {
+                Ok(mut items) => return items.remove(&game_id).unwrap_or_default(),
+                Err(e) => debug!("Failed to query price history: {}", e),
+            }
         }
-        drop(session_guard);
-        
-        // Игнорируем дроп если сессия на паузе
-        if self.is_paused().await {
-            debug!("Ignoring drop while paused: game_id={}", event.game_id);
-            return;
+        let history = self.price_history.read().await;
+        let Some(series) = history.get(&game_id) else {
+            return Vec::new();
+        };
+        match days {
+            Some(days) => {
+                let from = Utc::now() - chrono::Duration::days(days);
+                series.iter().filter(|o| o.at >= from).cloned().collect()
+            }
+            None => series.clone(),
         }
-        
-        // Проверяем, есть ли предмет в нашей БД
+    }
+
+    /// Изменение цены за 24ч и 7 дней
+    pub async fn get_price_trend(&self, game_id: i64) -> PriceTrend {
+        let history = self.price_history.read().await;
+        price_history::trend(game_id, history.get(&game_id).map(|s| s.as_slice()).unwrap_or(&[]))
+    }
+
+    /// Волатильность цены за `days` дней
+    pub async fn get_price_volatility(&self, game_id: i64, days: i64) -> Option<PriceVolatility> {
+        let history = self.price_history.read().await;
+        let series = history.get(&game_id)?;
+        price_history::volatility(game_id, series, days, Utc::now())
+    }
+
+    /// Стоимость сессии из истории по ценам на момент `at`
+    pub async fn value_at_prices(&self, record: &persistence::SessionHistoryRecord, at: DateTime<Utc>) -> HistoricalSessionValue {
         let items = self.items_cache.read().await;
-        if !items.contains_key(&event.game_id) {
-            debug!("Ignoring drop of unknown item: game_id={}", event.game_id);
-            return;
+        let history = self.price_history.read().await;
+        let mut value = 0.0;
+        let mut unpriced_items = Vec::new();
+        for (game_id, qty) in &record.drops {
+            let unit = if items.get(game_id).is_some_and(|i| i.is_base_currency) {
+                Some(1.0)
+            } else {
+                history.get(game_id).and_then(|s| price_history::price_at(s, at)).map(|o| o.price)
+            };
+            match unit {
+                Some(p) => value += p * *qty as f64,
+                None => unpriced_items.push(*game_id),
+            }
         }
-        drop(items);
-        
-        let mut session = self.session.write().await;
-        // Повторная проверка после получения write lock
-        if session.started_at.is_none() {
-            return;
+        unpriced_items.sort_unstable();
+        value += record.income_by_source.iter()
+            .filter(|t| t.source == IncomeSource::ManualDrop)
+            .map(|t| t.gross_value)
+            .sum::<f64>();
+        HistoricalSessionValue {
+            session_id: record.id.clone(),
+            at,
+            value,
+            recorded_value: record.total_income,
+            unpriced_items,
         }
-        
-        let current = session.drops.get(&event.game_id).copied().unwrap_or(0);
-        session.drops.insert(event.game_id, current + event.quantity);
-        
-        debug!("Added drop: game_id={}, qty={}, total={}", 
-               event.game_id, event.quantity, current + event.quantity);
-        
-        // Auto-save session
-        Self::save_session_internal(&session);
     }
-    
-    /// Обновить цену предмета в кэше
-    pub async fn update_price(&self, game_id: i64, price: f64) {
-        // Проверяем, является ли предмет базовой валютой
-        let items = self.items_cache.read().await;
-        if let Some(item) = items.get(&game_id) {
-            if item.is_base_currency {
-                debug!("Skipping price update for base currency: game_id={}", game_id);
-                return;
-            }
+
+    /// Dry-run импорта истории сессий: разбор, валидация и конфликты без записи
+    pub async fn preview_session_import(&self, path: &str, source: &ImportSource) -> Result<ImportPreview, String> {
+        let content = session_import::read_file(path)?;
+        let mut parsed = session_import::parse(&content, source)?;
+        let user_id = self.history_user_id().await;
+        let existing = self.load_history(&user_id, None).await?;
+        let foreign_ids = self.other_profiles_history_ids(&user_id).await?;
+        session_import::reassign_foreign_ids(&mut parsed.sessions, &foreign_ids);
+        let conflicts = session_import::find_conflicts(&parsed.sessions, &existing);
+        let starts = parsed.sessions.iter().map(|s| s.record.started_at);
+        Ok(ImportPreview {
+            total_rows: parsed.total_rows,
+            new_sessions: parsed.sessions.len() - conflicts.len(),
+            first_started_at: starts.clone().min(),
+            last_started_at: starts.max(),
+            conflicts,
+            errors: parsed.errors,
+        })
+    }
+
+    /// Импорт истории сессий в профиль текущего пользователя (невалидные строки пропускаются)
+    pub async fn import_sessions(&self, path: &str, source: &ImportSource, policy: ImportConflictPolicy) -> Result<ImportResult, String> {
+        let content = session_import::read_file(path)?;
+        let mut parsed = session_import::parse(&content, source)?;
+        let user_id = self.history_user_id().await;
+        let existing = self.load_history(&user_id, None).await?;
+        let foreign_ids = self.other_profiles_history_ids(&user_id).await?;
+        let reassigned = session_import::reassign_foreign_ids(&mut parsed.sessions, &foreign_ids);
+        if reassigned > 0 {
+            info!("{} imported sessions got new ids: id is taken by another profile", reassigned);
         }
-        drop(items);
-        
-        let mut prices = self.prices_cache.write().await;
-        let now = Utc::now();
-        prices.insert(game_id, persistence::PersistedPriceEntry { 
-            price, 
-            updated_at: now,
-            is_current_league: true,  // Цена получена через прайсчек = текущая лига
-            league_name: None,
-        });
-        debug!("Updated price: game_id={}, price={}", game_id, price);
+        let conflicts: HashMap<usize, String> = session_import::find_conflicts(&parsed.sessions, &existing)
+            .into_iter()
+            .map(|c| (c.row, c.existing_id))
+            .collect();
 
-        // Персистим на диск, чтобы цена переживала новую сессию/перезапуск.
-        // Ошибки не фейлят приложение.
-        let snapshot = prices.clone();
-        drop(prices);
-        if let Err(e) = persistence::save_prices_cache(&snapshot) {
-            debug!("Failed to persist prices cache: {}", e);
+        self.create_backup(BackupReason::BeforeImport, None).await
+            .map_err(|e| format!("Failed to back up data before import: {}", e))?;
+
+        let mut result = ImportResult { imported: 0, replaced: 0, skipped: 0, errors: parsed.errors.len() };
+        for session in parsed.sessions {
+            if let Some(existing_id) = conflicts.get(&session.row) {
+                if policy == ImportConflictPolicy::Skip {
+                    result.skipped += 1;
+                    continue;
+                }
+                self.delete_history_record(&user_id, existing_id).await?;
+                result.replaced += 1;
+            } else {
+                result.imported += 1;
+            }
+            self.add_history_record(&user_id, session.record, &session.map_runs).await?;
         }
+        info!(
+            "Imported sessions: {} new, {} replaced, {} skipped, {} invalid",
+            result.imported, result.replaced, result.skipped, result.errors
+        );
+        Ok(result)
+    }
+
+    /// Резервная копия данных (dest = None — каталог backups)
+    pub async fn create_backup(&self, reason: BackupReason, dest: Option<&str>) -> Result<BackupInfo, String> {
+        backup::create(reason, dest.map(std::path::Path::new), self.store()).map_err(|e| e.to_string())
+    }
+
+    /// Восстановить данные из копии (после этого запись отключена до перезапуска приложения)
+    pub async fn restore_backup(&self, path: &str) -> Result<BackupInfo, String> {
+        backup::restore(std::path::Path::new(path), self.store()).map_err(|e| e.to_string())
+    }
+
+    /// Карты сессии из истории (только SQLite; для JSON истории — пусто)
+    pub async fn history_map_runs(&self, session_id: &str) -> Vec<MapRun> {
+        self.store()
+            .and_then(|store| store.history_map_runs(session_id).ok())
+            .unwrap_or_default()
+    }
+
+    /// Детализация сессии из истории для экспорта
+    pub async fn export_session_items(&self, record: &persistence::SessionHistoryRecord) -> Vec<SessionItemLine> {
+        let items = self.items_cache.read().await;
+        let history = self.price_history.read().await;
+        export::session_items(record, &items, &history)
+    }
+
+    /// Кэш цен со свежестью для экспорта
+    pub async fn export_price_rows(&self) -> Vec<PriceExportRow> {
+        let items = self.items_cache.read().await;
+        let rules = self.valuation_rules.read().await;
+        let prices = self.prices_cache.read().await;
+        export::price_rows(&prices, &items, &rules.freshness, Utc::now())
     }
 
     /// Загрузить кэш цен с диска (best-effort)
     pub async fn load_prices_cache_from_disk(&self) {
-        match persistence::load_prices_cache() {
+        let loaded = match self.store() {
+            Some(store) => store.load_prices(),
+            None => persistence::load_prices_cache(),
+        };
+        match loaded {
             Ok(map) => {
                 let mut prices = self.prices_cache.write().await;
                 // merge: не затираем уже обновлённые значения, если они есть
@@ -545,7 +2217,9 @@ impl AppState {
     pub async fn merge_remote_prices(&self, rows: Vec<(i64, f64, DateTime<Utc>)>) {
         let items = self.items_cache.read().await;
         let mut prices = self.prices_cache.write().await;
-        let mut updated = 0usize;
+        let mut history = self.price_history.write().await;
+        let mut updated_ids: Vec<i64> = Vec::new();
+        let mut accepted: Vec<(i64, PriceObservation)> = Vec::new();
         for (game_id, price, ts) in rows {
             // Не обновляем цену базовой валюты
             if let Some(item) = items.get(&game_id) {
@@ -567,12 +2241,22 @@ impl AppState {
                     updated_at: ts,
                     is_current_league: true,
                     league_name: None,
+                    strategy: None,
+                    source: Some(PriceObservationSource::Remote),
                 });
-                updated += 1;
+                if price_history::record(&mut history, game_id, price, ts, PriceObservationSource::Remote) {
+                    accepted.push((game_id, PriceObservation { at: ts, price, source: PriceObservationSource::Remote }));
+                }
+                updated_ids.push(game_id);
             }
         }
-        if updated > 0 {
+        drop((items, prices, history));
+        self.queue_price_observations(accepted).await;
+        if !updated_ids.is_empty() {
+            let updated = updated_ids.len();
             debug!("Merged remote prices: {} updated", updated);
+            self.check_price_alerts(&updated_ids).await;
+            self.events.publish(DomainEvent::PricesMerged { updated, game_ids: updated_ids });
         }
     }
 
@@ -580,7 +2264,9 @@ impl AppState {
     pub async fn merge_prices_with_league(&self, rows: Vec<crate::supabase_sync::PriceWithLeague>) {
         let items = self.items_cache.read().await;
         let mut prices = self.prices_cache.write().await;
-        let mut updated = 0usize;
+        let mut history = self.price_history.write().await;
+        let mut updated_ids: Vec<i64> = Vec::new();
+        let mut accepted: Vec<(i64, PriceObservation)> = Vec::new();
         
         for row in rows {
             // Не обновляем цену базовой валюты
@@ -604,40 +2290,48 @@ impl AppState {
             };
             
             if replace {
+                // Цены прошлого сезона в историю не пишем — это не рынок текущей лиги
+                if row.is_current_league
+                    && price_history::record(&mut history, row.game_id, row.price, row.last_updated, PriceObservationSource::Remote)
+                {
+                    let observation = PriceObservation { at: row.last_updated, price: row.price, source: PriceObservationSource::Remote };
+                    accepted.push((row.game_id, observation));
+                }
                 prices.insert(row.game_id, persistence::PersistedPriceEntry { 
                     price: row.price, 
                     updated_at: row.last_updated,
                     is_current_league: row.is_current_league,
                     league_name: Some(row.league_name),
+                    strategy: None,
+                    source: Some(PriceObservationSource::Remote),
                 });
-                updated += 1;
+                updated_ids.push(row.game_id);
             }
         }
         
-        if updated > 0 {
+        drop((items, prices, history));
+        self.queue_price_observations(accepted).await;
+        if !updated_ids.is_empty() {
+            let updated = updated_ids.len();
             debug!("Merged prices with league info: {} updated", updated);
+            self.check_price_alerts(&updated_ids).await;
+            self.events.publish(DomainEvent::PricesMerged { updated, game_ids: updated_ids });
         }
     }
 
-    fn is_price_stale_internal(entry: &persistence::PersistedPriceEntry) -> bool {
-        (Utc::now() - entry.updated_at).num_seconds() > PRICE_TTL_SEC
-    }
-
     /// Цена для расчётов (None если устарела)
     #[allow(dead_code)]
     pub async fn get_effective_price(&self, game_id: i64) -> Option<f64> {
         // Для базовой валюты всегда возвращаем 1.0 (цена никогда не устаревает)
-        let items = self.items_cache.read().await;
-        if let Some(item) = items.get(&game_id) {
-            if item.is_base_currency {
-                return Some(1.0);
-            }
+        let item = self.items_cache.read().await.get(&game_id).cloned();
+        if item.as_ref().is_some_and(|i| i.is_base_currency) {
+            return Some(1.0);
         }
-        drop(items);
-        
+
+        let rules = self.valuation_rules.read().await;
         let prices = self.prices_cache.read().await;
         let entry = prices.get(&game_id)?;
-        if Self::is_price_stale_internal(entry) {
+        if freshness::assess(entry, game_id, item.as_ref(), &rules.freshness, Utc::now()).is_stale {
             return None;
         }
         Some(entry.price)
@@ -686,6 +2380,8 @@ impl AppState {
                     updated_at: Utc::now(),
                     is_current_league: true,
                     league_name: None,
+                    strategy: None,
+                    source: None,
                 }
             );
             debug!("Initialized base currency price: game_id={}, price=1.0", game_id);
@@ -698,40 +2394,29 @@ impl AppState {
         cache.get(&game_id).cloned()
     }
     
+    /// Ставка комиссии аукциона из настроек
+    async fn auction_fee_rate(&self) -> f64 {
+        self.settings.read().await.auction_fee_rate
+    }
+
     /// Получить статистику сессии
-    pub async fn get_session_stats(&self) -> SessionStats {
-        let session = self.session.read().await;
+    pub async fn get_session_stats(&self, source: &LogSource) -> SessionStats {
+        let fee_rate = self.auction_fee_rate().await;
+        let session = source.session.read().await;
         let items_cache = self.items_cache.read().await;
         let prices = self.prices_cache.read().await;
+        let rules = self.valuation_rules.read().await;
         
         let total_items: i32 = session.drops.values().sum();
         let unique_items = session.drops.len() as i32;
         
-        // Вычисляем общую стоимость
-        let mut total_value = 0.0;
-        let mut stale_price_lines = 0i32;
-        for (game_id, qty) in &session.drops {
-            // Проверяем является ли предмет базовой валютой
-            let is_base_currency = items_cache.get(game_id)
-                .map(|i| i.is_base_currency)
-                .unwrap_or(false);
-            
-            if is_base_currency {
-                // Для базовой валюты цена всегда 1.0 и никогда не устаревает
-                total_value += 1.0 * (*qty as f64);
-            } else if let Some(price_entry) = prices.get(game_id) {
-                // Доход считаем всегда (даже по устаревшим ценам), но помечаем что часть цен старые,
-                // чтобы UI мог попросить пользователя обновить прайсчек.
-                total_value += price_entry.price * (*qty as f64);
-                if Self::is_price_stale_internal(price_entry) {
-                    stale_price_lines += 1;
-                }
-            }
-        }
+        // Доход из журнала: авто-дроп + ручной дроп (до и после комиссии аукциона)
+        let ledger = Valuation::new(&items_cache, &prices, &session.excluded_items, &rules)
+            .with_fee_rate(fee_rate)
+            .ledger(&session.drops, &session.manual_drops);
         
-        // Длительность сессии — просто значение из session_duration_sec
-        // (обновляется фронтендом каждую секунду)
-        let duration_sec = session.session_duration_sec;
+        // Активное время сессии: от started_at минус интервалы паузы
+        let duration_sec = session.active_duration_sec(Utc::now());
 
         // Средняя длительность карты: используем map-only время (total_duration_sec).
         // Если карт ещё нет, но мы на карте — показываем время текущей карты как “среднее” (удобно для первой карты).
@@ -750,8 +2435,8 @@ impl AppState {
         };
         
         // Доход в час
-        let hourly_profit = if duration_sec > 0 {
-            total_value / (duration_sec as f64) * 3600.0
+        let hourly = |value: f64| if duration_sec > 0 {
+            value / (duration_sec as f64) * 3600.0
         } else {
             0.0
         };
@@ -762,92 +2447,270 @@ impl AppState {
         drop(session);
         drop(items_cache);
         drop(prices);
+        drop(rules);
         
         // Получаем состояние паузы
-        let is_paused = *self.is_paused.read().await;
+        let is_paused = *source.is_paused.read().await;
         
         SessionStats {
             total_items,
             unique_items,
-            total_value,
+            total_value: ledger.gross_total,
+            net_value: ledger.net_total,
+            auction_fee: ledger.gross_total - ledger.net_total,
             maps_completed,
             duration_sec,
             avg_map_duration_sec,
-            stale_price_lines,
-            hourly_profit,
+            stale_price_lines: ledger.stale_price_lines,
+            hourly_profit: hourly(ledger.gross_total),
+            net_hourly_profit: hourly(ledger.net_total),
             is_paused,
+            denomination: None,
         }
     }
     
+    /// Журнал дохода сессии (авто-дроп + ручной дроп)
+    pub async fn get_income_ledger(&self, source: &LogSource) -> IncomeLedger {
+        let fee_rate = self.auction_fee_rate().await;
+        let session = source.session.read().await;
+        let items_cache = self.items_cache.read().await;
+        let prices = self.prices_cache.read().await;
+        let rules = self.valuation_rules.read().await;
+        Valuation::new(&items_cache, &prices, &session.excluded_items, &rules)
+            .with_fee_rate(fee_rate)
+            .ledger(&session.drops, &session.manual_drops)
+    }
+
     /// Получить агрегированные дропы для отображения
-    pub async fn get_aggregated_drops(&self) -> Vec<AggregatedDrop> {
-        let session = self.session.read().await;
+    pub async fn get_aggregated_drops(&self, source: &LogSource) -> Vec<AggregatedDrop> {
+        let session = source.session.read().await;
+        self.build_aggregated_drops(&session.drops, &session.excluded_items).await
+    }
+
+    /// Строки дропа с ценами, отсортированные по стоимости
+    async fn build_aggregated_drops(&self, drops: &HashMap<i64, i32>, excluded: &HashSet<i64>) -> Vec<AggregatedDrop> {
+        let fee_rate = self.auction_fee_rate().await;
         let items_cache = self.items_cache.read().await;
         let prices = self.prices_cache.read().await;
-        
-        let mut drops: Vec<AggregatedDrop> = session.drops.iter().map(|(game_id, qty)| {
-            let item_info = items_cache.get(game_id).cloned();
-            
-            // Для базовой валюты цена всегда 1.0 и никогда не устаревает
-            let is_base_currency = item_info.as_ref().map(|i| i.is_base_currency).unwrap_or(false);
-            
-            let (unit_price, price_updated_at, price_is_stale, is_previous_season, league_name) = if is_base_currency {
-                (1.0, Some(Utc::now()), false, false, None)
-            } else {
-                match prices.get(game_id) {
-                    Some(p) => (
-                        p.price, 
-                        Some(p.updated_at), 
-                        Self::is_price_stale_internal(p),
-                        !p.is_current_league,  // Если НЕ текущая лига = предыдущий сезон
-                        p.league_name.clone(),
-                    ),
-                    None => (0.0, None, false, false, None),
-                }
-            };
-            let total_value = unit_price * (*qty as f64);
-            
-            AggregatedDrop {
-                game_id: *game_id,
-                item_info,
-                quantity: *qty,
+        let rules = self.valuation_rules.read().await;
+        Valuation::new(&items_cache, &prices, excluded, &rules)
+            .with_fee_rate(fee_rate)
+            .lines(drops)
+    }
+
+    /// Карты текущей сессии с дропом и стоимостью
+    pub async fn get_map_runs(&self, source: &LogSource) -> Vec<MapRunStats> {
+        let session = source.session.read().await;
+        let items_cache = self.items_cache.read().await;
+        let prices = self.prices_cache.read().await;
+        let rules = self.valuation_rules.read().await;
+        let valuation = Valuation::new(&items_cache, &prices, &session.excluded_items, &rules);
+        let now = Utc::now();
+
+        session.map_runs.iter().enumerate().map(|(i, run)| {
+            let total_value = valuation.value(&run.drops).gross;
+            let end = run.exited_at.unwrap_or(now);
+            MapRunStats {
+                index: i as i32 + 1,
+                scene_name: run.scene_name.clone(),
+                entered_at: run.entered_at,
+                exited_at: run.exited_at,
+                duration_sec: (end - run.entered_at).num_seconds().max(0) as i32,
+                is_current: run.exited_at.is_none(),
+                total_items: run.drops.values().sum(),
                 total_value,
+                drops: valuation.lines(&run.drops),
+            }
+        }).collect()
+    }
+
+    /// Последние N подборов (новые первыми)
+    pub async fn get_recent_drops(&self, source: &LogSource, limit: usize) -> Vec<RecentDrop> {
+        let session = source.session.read().await;
+        let items_cache = self.items_cache.read().await;
+        let prices = self.prices_cache.read().await;
+        let rules = self.valuation_rules.read().await;
+        let valuation = Valuation::new(&items_cache, &prices, &session.excluded_items, &rules);
+
+        // Поправки количества — не подборы, в ленту их не выводим
+        session.drop_log.iter().rev().filter(|d| !d.correction).take(limit).map(|d| {
+            let unit_price = valuation.unit_price(d.game_id);
+            RecentDrop {
+                timestamp: d.timestamp,
+                game_id: d.game_id,
+                item_info: items_cache.get(&d.game_id).cloned(),
+                quantity: d.quantity,
+                map_index: d.map_index,
                 unit_price,
-                price_updated_at,
-                price_is_stale,
-                is_previous_season,
-                league_name,
+                total_value: unit_price * d.quantity as f64,
             }
-        }).collect();
-        
-        // Сортируем по стоимости (от большей к меньшей)
-        drops.sort_by(|a, b| b.total_value.partial_cmp(&a.total_value).unwrap_or(std::cmp::Ordering::Equal));
-        
-        drops
+        }).collect()
+    }
+
+    /// Накопленная стоимость дропа во времени (по текущим ценам).
+    /// bucket_sec > 0 — группировать подборы в интервалы такой длины.
+    pub async fn get_value_timeline(&self, source: &LogSource, bucket_sec: Option<i64>) -> Vec<ValuePoint> {
+        let session = source.session.read().await;
+        let items_cache = self.items_cache.read().await;
+        let prices = self.prices_cache.read().await;
+        let rules = self.valuation_rules.read().await;
+        let valuation = Valuation::new(&items_cache, &prices, &session.excluded_items, &rules);
+
+        let mut log: Vec<&DropRecord> = session.drop_log.iter().collect();
+        log.sort_by_key(|d| d.timestamp);
+
+        let bucket = bucket_sec.filter(|b| *b > 0);
+        let mut points: Vec<ValuePoint> = Vec::new();
+        let mut cumulative = 0.0;
+        for d in log {
+            let value = valuation.unit_price(d.game_id) * d.quantity as f64;
+            cumulative += value;
+            let ts = match bucket {
+                Some(b) => DateTime::from_timestamp(d.timestamp.timestamp() / b * b, 0).unwrap_or(d.timestamp),
+                None => d.timestamp,
+            };
+            match points.last_mut() {
+                Some(last) if bucket.is_some() && last.timestamp == ts => {
+                    last.value += value;
+                    last.cumulative_value = cumulative;
+                }
+                _ => points.push(ValuePoint { timestamp: ts, value, cumulative_value: cumulative }),
+            }
+        }
+        points
+    }
+
+    /// Лучшая/худшая карта и распределение стоимости (только завершённые карты)
+    pub async fn get_map_run_summary(&self, source: &LogSource) -> MapRunSummary {
+        let mut runs: Vec<MapRunStats> = self.get_map_runs(source).await
+            .into_iter()
+            .filter(|r| !r.is_current)
+            .collect();
+        if runs.is_empty() {
+            return MapRunSummary::default();
+        }
+        runs.sort_by(|a, b| a.total_value.partial_cmp(&b.total_value).unwrap_or(std::cmp::Ordering::Equal));
+
+        let values: Vec<f64> = runs.iter().map(|r| r.total_value).collect();
+        let percentile = |p: f64| values[(((values.len() - 1) as f64) * p).round() as usize];
+
+        MapRunSummary {
+            completed_maps: runs.len() as i32,
+            avg_value: values.iter().sum::<f64>() / values.len() as f64,
+            median_value: percentile(0.5),
+            p25_value: percentile(0.25),
+            p75_value: percentile(0.75),
+            worst: runs.first().cloned(),
+            best: runs.last().cloned(),
+        }
+    }
+
+    /// Дропы всех источников с активной сессией, сложенные вместе
+    /// Исключённые предметы: если предмет исключён не во всех источниках,
+    /// в общий итог идёт только неисключённое количество.
+    async fn merged_drops(&self) -> (HashMap<i64, i32>, HashSet<i64>) {
+        let mut counted: HashMap<i64, i32> = HashMap::new();
+        let mut excluded_qty: HashMap<i64, i32> = HashMap::new();
+        for src in self.all_sources().await {
+            let session = src.session.read().await;
+            for (game_id, qty) in &session.drops {
+                let target = if session.excluded_items.contains(game_id) { &mut excluded_qty } else { &mut counted };
+                *target.entry(*game_id).or_insert(0) += qty;
+            }
+        }
+        let mut excluded = HashSet::new();
+        for (game_id, qty) in excluded_qty {
+            if let std::collections::hash_map::Entry::Vacant(e) = counted.entry(game_id) {
+                e.insert(qty);
+                excluded.insert(game_id);
+            }
+        }
+        (counted, excluded)
+    }
+
+    /// Общая статистика по всем источникам (мультибокс).
+    /// Доход в час складывается: клиенты фармят параллельно.
+    pub async fn get_aggregate_stats(&self) -> SessionStats {
+        let mut result = SessionStats::default();
+        let mut map_time_sec = 0i64;
+        let mut all_paused = true;
+        let mut any_active = false;
+
+        for src in self.all_sources().await {
+            if src.session.read().await.started_at.is_none() {
+                continue;
+            }
+            any_active = true;
+            let stats = self.get_session_stats(&src).await;
+            result.total_items += stats.total_items;
+            result.maps_completed += stats.maps_completed;
+            result.duration_sec = result.duration_sec.max(stats.duration_sec);
+            result.hourly_profit += stats.hourly_profit;
+            result.net_hourly_profit += stats.net_hourly_profit;
+            map_time_sec += stats.avg_map_duration_sec as i64 * stats.maps_completed as i64;
+            all_paused &= stats.is_paused;
+        }
+
+        let (merged, excluded) = self.merged_drops().await;
+        let mut manual_drops = Vec::new();
+        for src in self.all_sources().await {
+            manual_drops.extend(src.session.read().await.manual_drops.iter().cloned());
+        }
+        let fee_rate = self.auction_fee_rate().await;
+        let items_cache = self.items_cache.read().await;
+        let prices = self.prices_cache.read().await;
+        let rules = self.valuation_rules.read().await;
+        let ledger = Valuation::new(&items_cache, &prices, &excluded, &rules)
+            .with_fee_rate(fee_rate)
+            .ledger(&merged, &manual_drops);
+        result.unique_items = merged.len() as i32;
+        result.total_value = ledger.gross_total;
+        result.net_value = ledger.net_total;
+        result.auction_fee = ledger.gross_total - ledger.net_total;
+        result.stale_price_lines = ledger.stale_price_lines;
+        result.avg_map_duration_sec = if result.maps_completed > 0 {
+            (map_time_sec as f64 / result.maps_completed as f64).round() as i32
+        } else {
+            0
+        };
+        result.is_paused = any_active && all_paused;
+        result
+    }
+
+    /// Общий список дропов по всем источникам
+    pub async fn get_aggregate_drops(&self) -> Vec<AggregatedDrop> {
+        let (merged, excluded) = self.merged_drops().await;
+        self.build_aggregated_drops(&merged, &excluded).await
     }
     
     /// Проверить, активна ли сессия
-    pub async fn is_session_active(&self) -> bool {
-        let session = self.session.read().await;
+    pub async fn is_session_active(&self, source: &LogSource) -> bool {
+        let session = source.session.read().await;
         session.started_at.is_some()
     }
     
     /// Установить путь к логам (и сохранить в настройки)
-    pub async fn set_log_path(&self, path: Option<String>) {
-        let mut log_path = self.log_path.write().await;
+    pub async fn set_log_path(&self, source: &LogSource, path: Option<String>) {
+        let mut log_path = source.log_path.write().await;
         *log_path = path.clone();
         
         // Сохраняем в настройки для персистентности
         let mut settings = self.settings.write().await;
-        settings.custom_log_path = path;
+        if source.id == MAIN_SOURCE_ID {
+            settings.custom_log_path = path;
+        } else if let Some(cfg) = settings.log_sources.iter_mut().find(|s| s.id == source.id) {
+            if let Some(path) = path {
+                cfg.path = path;
+            }
+        }
         if let Err(e) = persistence::save_settings(&settings) {
             log::warn!("Failed to save settings with custom log path: {}", e);
         }
     }
     
     /// Получить путь к логам
-    pub async fn get_log_path(&self) -> Option<String> {
-        let log_path = self.log_path.read().await;
+    pub async fn get_log_path(&self, source: &LogSource) -> Option<String> {
+        let log_path = source.log_path.read().await;
         log_path.clone()
     }
     
@@ -860,6 +2723,133 @@ impl AppState {
 
 impl Default for AppState {
     fn default() -> Self {
-        Self::new(Arc::new(Mutex::new(LogParser::new())))
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    /// Состояние с базой в памяти: сохранение сессии не трогает файлы пользователя
+    fn state_in_memory() -> AppState {
+        let state = AppState::new();
+        let _ = state.store.set(Store::open_in_memory().unwrap());
+        state
+    }
+
+    async fn start_session(source: &LogSource, started_at: DateTime<Utc>) {
+        let mut session = source.session.write().await;
+        session.started_at = Some(started_at);
+        session.last_seen_at = Some(started_at);
+    }
+
+    #[tokio::test]
+    async fn test_set_paused_nested_keeps_one_interval() {
+        let state = state_in_memory();
+        let source = state.source(None).await;
+        start_session(&source, Utc::now() - Duration::seconds(600)).await;
+
+        // Автопауза, поверх неё ручная — интервал один, и он уже ручной
+        source.session.write().await.open_pause(Utc::now() - Duration::seconds(60), PauseKind::Idle);
+        state.set_paused(&source, true).await;
+        state.set_paused(&source, true).await;
+        {
+            let session = source.session.read().await;
+            assert_eq!(session.pauses.len(), 1);
+            assert_eq!(session.pauses[0].kind, PauseKind::Manual);
+            assert!(session.pauses[0].ended_at.is_none());
+        }
+
+        state.set_paused(&source, false).await;
+        state.set_paused(&source, false).await;
+        let session = source.session.read().await;
+        assert_eq!(session.pauses.len(), 1);
+        assert!(session.pauses[0].ended_at.is_some());
+        assert!(!session.is_paused);
+        assert!((539..=541).contains(&session.session_duration_sec));
+    }
+
+    #[tokio::test]
+    async fn test_open_pause_not_counted_at_session_end() {
+        let state = state_in_memory();
+        let source = state.source(None).await;
+        start_session(&source, Utc::now() - Duration::seconds(600)).await;
+        source.session.write().await.open_pause(Utc::now() - Duration::seconds(200), PauseKind::Manual);
+        *source.is_paused.write().await = true;
+
+        // Итог сессии (как в команде end_session) берётся до сброса, пауза ещё открыта
+        let stats = state.get_session_stats(&source).await;
+        assert!((399..=401).contains(&stats.duration_sec));
+
+        // Heartbeat на паузе не добавляет активного времени
+        state.tick_sessions().await;
+        let session = source.session.read().await;
+        assert!((399..=401).contains(&session.session_duration_sec));
+        assert!(session.pauses[0].ended_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_undo_refuses_pickup_adjusted_later() {
+        let state = state_in_memory();
+        let source = state.source(None).await;
+        start_session(&source, Utc::now() - Duration::seconds(600)).await;
+        {
+            let mut session = source.session.write().await;
+            session.drops.insert(1, 5);
+            session.drop_log.push(DropRecord {
+                timestamp: Utc::now() - Duration::seconds(60),
+                game_id: 1,
+                quantity: 5,
+                map_index: None,
+                correction: false,
+            });
+        }
+
+        state.adjust_drop_quantity(&source, 1, 3).await.unwrap();
+        let drop_log = source.session.read().await.drop_log.clone();
+
+        let err = state.undo_last_drops(&source, 1).await.unwrap_err();
+        assert_eq!(err, "Drop quantity was adjusted after this pickup; adjust it instead");
+        let session = source.session.read().await;
+        assert_eq!(session.drop_log, drop_log);
+        assert_eq!(session.drops.get(&1), Some(&3));
+    }
+
+    #[test]
+    fn test_offline_gap_after_restart() {
+        let now = Utc::now();
+        let started = now - Duration::seconds(3600);
+        let last_seen = now - Duration::seconds(1800);
+        let mut session = FarmSessionState { started_at: Some(started), last_seen_at: Some(last_seen), ..Default::default() };
+
+        AppState::account_offline_gap(&mut session, now);
+        assert_eq!(session.pauses.len(), 1);
+        assert_eq!(session.pauses[0].kind, PauseKind::Offline);
+        assert_eq!(session.pauses[0].started_at, last_seen);
+        assert_eq!(session.pauses[0].ended_at, Some(now));
+        assert_eq!(session.session_duration_sec, 1800);
+        assert_eq!(session.last_seen_at, Some(now));
+
+        // Короткий перезапуск — не пауза
+        let mut session = FarmSessionState { started_at: Some(started), last_seen_at: Some(now - Duration::seconds(30)), ..Default::default() };
+        AppState::account_offline_gap(&mut session, now);
+        assert!(session.pauses.is_empty());
+        assert_eq!(session.session_duration_sec, 3600);
+
+        // Закрыли на паузе: открытая пауза тянется с последнего heartbeat, отдельного offline нет
+        let mut session = FarmSessionState {
+            started_at: Some(started),
+            last_seen_at: Some(last_seen),
+            is_paused: true,
+            ..Default::default()
+        };
+        AppState::account_offline_gap(&mut session, now);
+        assert_eq!(session.pauses.len(), 1);
+        assert_eq!(session.pauses[0].kind, PauseKind::Manual);
+        assert_eq!(session.pauses[0].started_at, last_seen);
+        assert!(session.pauses[0].ended_at.is_none());
+        assert_eq!(session.session_duration_sec, 1800);
     }
 }
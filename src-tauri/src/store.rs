@@ -0,0 +1,919 @@
+//! Локальное хранилище SQLite (tli-companion.db)
+//!
+//! История сессий (с картами и дропом), активные сессии, кэш цен и история цен.
+//! Схема версионируется через PRAGMA user_version: миграции применяются по порядку,
+//! каждая в своей транзакции. При первом запуске импортируются старые JSON файлы
+//! (сами файлы не удаляются). Настройки и правила остаются в JSON (см. persistence).
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use rusqlite::{backup::Backup, params, Connection, OpenFlags, OptionalExtension, Transaction};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::backup;
+use crate::persistence::{self, PersistedPriceEntry, SessionHistoryRecord};
+use crate::types::{BackupReason, DropRecord, FarmSessionState, MapRun, PriceObservation, RecordedDropValue};
+
+pub(crate) const DB_FILE_NAME: &str = "tli-companion.db";
+
+/// Миграции схемы: индекс + 1 = версия схемы после применения
+const MIGRATIONS: &[&str] = &[
+    // v1: сессии, карты, дроп, цены, история цен, активные сессии
+    r#"
+    CREATE TABLE meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE sessions (
+        id TEXT PRIMARY KEY,
+        user_id TEXT NOT NULL,
+        started_at TEXT NOT NULL,
+        ended_at TEXT NOT NULL,
+        maps_completed INTEGER NOT NULL,
+        total_duration_sec INTEGER NOT NULL,
+        total_profit REAL NOT NULL,
+        total_expenses REAL NOT NULL,
+        total_income REAL NOT NULL,
+        net_income REAL,
+        income_by_source TEXT NOT NULL DEFAULT '[]',
+        remote_id TEXT,
+        preset_id TEXT,
+        manual_drops TEXT NOT NULL DEFAULT '[]',
+        expenses TEXT NOT NULL DEFAULT '[]'
+    );
+    CREATE INDEX idx_sessions_user_started ON sessions(user_id, started_at DESC);
+    CREATE TABLE session_drops (
+        session_id TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+        game_id INTEGER NOT NULL,
+        quantity INTEGER NOT NULL,
+        -- оценка при завершении сессии (NULL — записи, импортированные из JSON без неё)
+        unit_price REAL,
+        total_value REAL,
+        net_value REAL,
+        PRIMARY KEY (session_id, game_id)
+    );
+    CREATE TABLE map_runs (
+        session_id TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+        map_index INTEGER NOT NULL,
+        scene_name TEXT NOT NULL,
+        entered_at TEXT NOT NULL,
+        exited_at TEXT,
+        PRIMARY KEY (session_id, map_index)
+    );
+    CREATE TABLE map_run_drops (
+        session_id TEXT NOT NULL,
+        map_index INTEGER NOT NULL,
+        game_id INTEGER NOT NULL,
+        quantity INTEGER NOT NULL,
+        PRIMARY KEY (session_id, map_index, game_id),
+        FOREIGN KEY (session_id, map_index) REFERENCES map_runs(session_id, map_index) ON DELETE CASCADE
+    );
+    -- активная сессия: скаляр в state, хронология подборов и карты — строками
+    CREATE TABLE active_sessions (
+        source_id TEXT PRIMARY KEY,
+        state TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+    CREATE TABLE active_session_drops (
+        source_id TEXT NOT NULL,
+        seq INTEGER NOT NULL,
+        at TEXT NOT NULL,
+        game_id INTEGER NOT NULL,
+        quantity INTEGER NOT NULL,
+        map_index INTEGER,
+        correction INTEGER NOT NULL,
+        PRIMARY KEY (source_id, seq)
+    );
+    CREATE TABLE active_map_runs (
+        source_id TEXT NOT NULL,
+        map_index INTEGER NOT NULL,
+        scene_name TEXT NOT NULL,
+        entered_at TEXT NOT NULL,
+        exited_at TEXT,
+        PRIMARY KEY (source_id, map_index)
+    );
+    CREATE TABLE active_map_run_drops (
+        source_id TEXT NOT NULL,
+        map_index INTEGER NOT NULL,
+        game_id INTEGER NOT NULL,
+        quantity INTEGER NOT NULL,
+        PRIMARY KEY (source_id, map_index, game_id),
+        FOREIGN KEY (source_id, map_index) REFERENCES active_map_runs(source_id, map_index) ON DELETE CASCADE
+    );
+    CREATE TABLE prices (
+        game_id INTEGER PRIMARY KEY,
+        price REAL NOT NULL,
+        updated_at TEXT NOT NULL,
+        is_current_league INTEGER NOT NULL,
+        league_name TEXT,
+        strategy TEXT,
+        source TEXT
+    );
+    CREATE TABLE price_history (
+        game_id INTEGER NOT NULL,
+        at TEXT NOT NULL,
+        price REAL NOT NULL,
+        source TEXT NOT NULL,
+        UNIQUE (game_id, at, source)
+    );
+    CREATE INDEX idx_price_history_item_at ON price_history(game_id, at);
+    "#,
+];
+
+/// Ключ meta: JSON файлы уже импортированы
+const META_JSON_IMPORTED: &str = "json_imported";
+
+fn db_err(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+fn to_json<T: Serialize>(value: &T) -> io::Result<String> {
+    serde_json::to_string(value).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn from_json<T: DeserializeOwned>(data: &str) -> io::Result<T> {
+    serde_json::from_str(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Строка таблицы prices: game_id, price, updated_at, is_current_league, league_name, strategy, source
+type PriceRow = (i64, f64, DateTime<Utc>, bool, Option<String>, Option<String>, Option<String>);
+
+pub struct Store {
+    conn: Mutex<Connection>,
+}
+
+impl Store {
+    /// Открыть базу в каталоге данных приложения
+    pub fn open_default() -> io::Result<Self> {
+        let dir = persistence::app_data_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No local data directory"))?;
+        std::fs::create_dir_all(&dir)?;
+        Self::open(&dir.join(DB_FILE_NAME))
+    }
+
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let conn = Connection::open(path).map_err(db_err)?;
+        // WAL: запись дропа не блокирует чтение истории
+        conn.pragma_update(None, "journal_mode", "WAL").map_err(db_err)?;
+        Self::init(conn, true)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn open_in_memory() -> io::Result<Self> {
+        Self::init(Connection::open_in_memory().map_err(db_err)?, false)
+    }
+
+    fn init(conn: Connection, backup_before_migration: bool) -> io::Result<Self> {
+        conn.pragma_update(None, "foreign_keys", "ON").map_err(db_err)?;
+        let store = Self { conn: Mutex::new(conn) };
+        let version = store.schema_version()?;
+        // Пустая база (первый запуск) — копировать нечего
+        if backup_before_migration && version > 0 && version < MIGRATIONS.len() {
+            if let Err(e) = backup::create(BackupReason::BeforeMigration, None, Some(&store)) {
+                warn!("Failed to back up data before database migration: {}", e);
+            }
+        }
+        Self::migrate(&mut store.conn())?;
+        Ok(store)
+    }
+
+    /// Текущая версия схемы (PRAGMA user_version)
+    pub(crate) fn schema_version(&self) -> io::Result<usize> {
+        Self::version_of(&self.conn())
+    }
+
+    fn version_of(conn: &Connection) -> io::Result<usize> {
+        conn.pragma_query_value(None, "user_version", |r| r.get::<_, i64>(0))
+            .map(|v| v as usize)
+            .map_err(db_err)
+    }
+
+    /// Применить недостающие миграции
+    fn migrate(conn: &mut Connection) -> io::Result<()> {
+        let version = Self::version_of(conn)?;
+        if version > MIGRATIONS.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Database schema v{} is newer than supported v{}", version, MIGRATIONS.len()),
+            ));
+        }
+        for (i, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.transaction().map_err(db_err)?;
+            tx.execute_batch(sql).map_err(db_err)?;
+            tx.pragma_update(None, "user_version", (i + 1) as i64).map_err(db_err)?;
+            tx.commit().map_err(db_err)?;
+            info!("Database migrated to schema v{}", i + 1);
+        }
+        Ok(())
+    }
+
+    fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        // Паника в другом потоке не портит соединение — продолжаем с ним работать
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Согласованный снимок базы в отдельный файл (для резервной копии)
+    pub(crate) fn snapshot_to(&self, path: &Path) -> io::Result<()> {
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        let target = path.to_string_lossy().to_string();
+        self.conn().execute("VACUUM INTO ?1", [target]).map_err(db_err)?;
+        Ok(())
+    }
+
+    /// Заменить содержимое базы снимком из файла (проверка целостности и версии схемы, затем миграции)
+    pub(crate) fn restore_from(&self, path: &Path) -> io::Result<()> {
+        let src = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(db_err)?;
+        let check: String = src.query_row("PRAGMA integrity_check", [], |r| r.get(0)).map_err(db_err)?;
+        if check != "ok" {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Database integrity check failed: {}", check)));
+        }
+        let version = Self::version_of(&src)?;
+        if version > MIGRATIONS.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Database schema v{} is newer than supported v{}", version, MIGRATIONS.len()),
+            ));
+        }
+
+        let mut conn = self.conn();
+        {
+            let backup = Backup::new(&src, &mut conn).map_err(db_err)?;
+            backup.run_to_completion(256, std::time::Duration::ZERO, None).map_err(db_err)?;
+        }
+        Self::migrate(&mut conn)?;
+        // Состояние в памяти старше восстановленных данных: до перезапуска база только для чтения
+        conn.pragma_update(None, "query_only", true).map_err(db_err)
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // Импорт JSON
+    // ─────────────────────────────────────────────────────────────────────────
+
+    /// Один раз перенести данные из JSON файлов (цены, история цен, активные сессии, история сессий)
+    pub fn import_json_if_needed(&self, source_ids: &[String]) -> io::Result<bool> {
+        let imported: Option<String> = self.conn()
+            .query_row("SELECT value FROM meta WHERE key = ?1", [META_JSON_IMPORTED], |r| r.get(0))
+            .optional()
+            .map_err(db_err)?;
+        if imported.is_some() {
+            return Ok(false);
+        }
+
+        match persistence::load_prices_cache() {
+            Ok(prices) => {
+                let entries: Vec<(i64, PersistedPriceEntry)> = prices.into_iter().collect();
+                self.upsert_prices(&entries)?;
+            }
+            Err(e) => warn!("Skipping prices cache import: {}", e),
+        }
+        match persistence::load_price_history() {
+            Ok(history) => {
+                let observations: Vec<(i64, PriceObservation)> = history
+                    .into_iter()
+                    .flat_map(|(game_id, series)| series.into_iter().map(move |o| (game_id, o)))
+                    .collect();
+                self.append_price_observations(&observations)?;
+            }
+            Err(e) => warn!("Skipping price history import: {}", e),
+        }
+        for source_id in source_ids {
+            match persistence::load_session(source_id) {
+                Ok(Some(mut session)) => self.save_active_session(source_id, &mut session)?,
+                Ok(None) => {}
+                Err(e) => warn!("Skipping active session import for {}: {}", source_id, e),
+            }
+        }
+        for user_id in persistence::list_history_user_ids()? {
+            match persistence::load_session_history(&user_id) {
+                Ok(records) => {
+                    for record in &records {
+                        self.insert_history_record(&user_id, record, &[])?;
+                    }
+                }
+                Err(e) => warn!("Skipping session history import for {}: {}", user_id, e),
+            }
+        }
+
+        self.conn()
+            .execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)",
+                params![META_JSON_IMPORTED, Utc::now().to_rfc3339()],
+            )
+            .map_err(db_err)?;
+        info!("Imported JSON data into the local database");
+        Ok(true)
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // История сессий
+    // ─────────────────────────────────────────────────────────────────────────
+
+    /// Записать завершённую сессию с дропом и картами (повторная запись заменяет старую).
+    /// Сессию с тем же id в другом профиле не трогает — вставка тогда падает.
+    pub fn insert_history_record(&self, user_id: &str, record: &SessionHistoryRecord, map_runs: &[MapRun]) -> io::Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(db_err)?;
+        tx.execute("DELETE FROM sessions WHERE id = ?1 AND user_id = ?2", params![record.id, user_id])
+            .map_err(db_err)?;
+        tx.execute(
+            "INSERT INTO sessions (id, user_id, started_at, ended_at, maps_completed, total_duration_sec,
+                total_profit, total_expenses, total_income, net_income, income_by_source, remote_id, preset_id,
+                manual_drops, expenses)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+            params![
+                record.id,
+                user_id,
+                record.started_at,
+                record.ended_at,
+                record.maps_completed,
+                record.total_duration_sec,
+                record.total_profit,
+                record.total_expenses,
+                record.total_income,
+                record.net_income,
+                to_json(&record.income_by_source)?,
+                record.remote_id,
+                record.preset_id,
+                to_json(&record.manual_drops)?,
+                to_json(&record.expenses)?,
+            ],
+        )
+        .map_err(db_err)?;
+        {
+            let mut stmt = tx
+                .prepare(
+                    "INSERT INTO session_drops (session_id, game_id, quantity, unit_price, total_value, net_value)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )
+                .map_err(db_err)?;
+            for (game_id, qty) in &record.drops {
+                let value = record.drop_values.get(game_id);
+                stmt.execute(params![
+                    record.id,
+                    game_id,
+                    qty,
+                    value.map(|v| v.unit_price),
+                    value.map(|v| v.total_value),
+                    value.map(|v| v.net_value),
+                ])
+                .map_err(db_err)?;
+            }
+            let mut run_stmt = tx
+                .prepare("INSERT INTO map_runs (session_id, map_index, scene_name, entered_at, exited_at) VALUES (?1, ?2, ?3, ?4, ?5)")
+                .map_err(db_err)?;
+            let mut run_drop_stmt = tx
+                .prepare("INSERT INTO map_run_drops (session_id, map_index, game_id, quantity) VALUES (?1, ?2, ?3, ?4)")
+                .map_err(db_err)?;
+            for (i, run) in map_runs.iter().enumerate() {
+                // map_index как в MapRunStats.index (с 1)
+                let map_index = i as i64 + 1;
+                run_stmt
+                    .execute(params![record.id, map_index, run.scene_name, run.entered_at, run.exited_at])
+                    .map_err(db_err)?;
+                for (game_id, qty) in &run.drops {
+                    run_drop_stmt.execute(params![record.id, map_index, game_id, qty]).map_err(db_err)?;
+                }
+            }
+        }
+        tx.commit().map_err(db_err)
+    }
+
+    fn read_history_rows(&self, sql: &str, args: &[&dyn rusqlite::ToSql]) -> io::Result<Vec<SessionHistoryRecord>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(sql).map_err(db_err)?;
+        let rows = stmt
+            .query_map(args, |r| {
+                Ok((
+                    SessionHistoryRecord {
+                        id: r.get("id")?,
+                        started_at: r.get("started_at")?,
+                        ended_at: r.get("ended_at")?,
+                        maps_completed: r.get("maps_completed")?,
+                        total_duration_sec: r.get("total_duration_sec")?,
+                        total_profit: r.get("total_profit")?,
+                        total_expenses: r.get("total_expenses")?,
+                        total_income: r.get("total_income")?,
+                        net_income: r.get("net_income")?,
+                        income_by_source: Vec::new(),
+                        remote_id: r.get("remote_id")?,
+                        preset_id: r.get("preset_id")?,
+                        drops: HashMap::new(),
+                        drop_values: HashMap::new(),
+                        manual_drops: Vec::new(),
+                        expenses: Vec::new(),
+                    },
+                    [
+                        r.get::<_, String>("income_by_source")?,
+                        r.get::<_, String>("manual_drops")?,
+                        r.get::<_, String>("expenses")?,
+                    ],
+                ))
+            })
+            .map_err(db_err)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(db_err)?;
+
+        let mut drops_stmt = conn
+            .prepare("SELECT game_id, quantity, unit_price, total_value, net_value FROM session_drops WHERE session_id = ?1")
+            .map_err(db_err)?;
+        let mut records = Vec::with_capacity(rows.len());
+        for (mut record, [income_by_source, manual_drops, expenses]) in rows {
+            record.income_by_source = from_json(&income_by_source)?;
+            record.manual_drops = from_json(&manual_drops)?;
+            record.expenses = from_json(&expenses)?;
+            let drops: Vec<(i64, i32, Option<RecordedDropValue>)> = drops_stmt
+                .query_map([&record.id], |r| {
+                    let value = match (r.get(2)?, r.get(3)?, r.get(4)?) {
+                        (Some(unit_price), Some(total_value), Some(net_value)) => {
+                            Some(RecordedDropValue { unit_price, total_value, net_value })
+                        }
+                        _ => None,
+                    };
+                    Ok((r.get(0)?, r.get(1)?, value))
+                })
+                .map_err(db_err)?
+                .collect::<Result<_, _>>()
+                .map_err(db_err)?;
+            for (game_id, qty, value) in drops {
+                record.drops.insert(game_id, qty);
+                if let Some(value) = value {
+                    record.drop_values.insert(game_id, value);
+                }
+            }
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    /// История сессий пользователя, новые первыми (limit = None — вся история)
+    pub fn load_history(&self, user_id: &str, limit: Option<usize>) -> io::Result<Vec<SessionHistoryRecord>> {
+        let limit = limit.map(|l| l as i64).unwrap_or(-1);
+        self.read_history_rows(
+            "SELECT * FROM sessions WHERE user_id = ?1 ORDER BY started_at DESC LIMIT ?2",
+            &[&user_id, &limit],
+        )
+    }
+
+    /// Одна сессия из истории пользователя
+    pub fn get_history_record(&self, user_id: &str, session_id: &str) -> io::Result<Option<SessionHistoryRecord>> {
+        Ok(self
+            .read_history_rows("SELECT * FROM sessions WHERE user_id = ?1 AND id = ?2", &[&user_id, &session_id])?
+            .into_iter()
+            .next())
+    }
+
+    /// Удалить сессию из истории (карты и дроп удаляются каскадно)
+    pub fn delete_history_record(&self, user_id: &str, session_id: &str) -> io::Result<bool> {
+        let n = self.conn()
+            .execute("DELETE FROM sessions WHERE user_id = ?1 AND id = ?2", params![user_id, session_id])
+            .map_err(db_err)?;
+        Ok(n > 0)
+    }
+
+    /// Id сессий всех остальных профилей
+    pub fn other_users_history_ids(&self, user_id: &str) -> io::Result<HashSet<String>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT id FROM sessions WHERE user_id != ?1").map_err(db_err)?;
+        let ids = stmt
+            .query_map([user_id], |r| r.get(0))
+            .map_err(db_err)?
+            .collect::<Result<_, _>>()
+            .map_err(db_err)?;
+        Ok(ids)
+    }
+
+    /// Количество сессий в истории пользователя
+    pub fn count_history(&self, user_id: &str) -> io::Result<usize> {
+        self.conn()
+            .query_row("SELECT COUNT(*) FROM sessions WHERE user_id = ?1", [user_id], |r| r.get::<_, i64>(0))
+            .map(|n| n as usize)
+            .map_err(db_err)
+    }
+
+    /// Перенести историю одного пользователя другому.
+    /// id сессии — первичный ключ, поэтому дубликатов после переноса не бывает.
+    pub fn reassign_history(&self, from_user: &str, to_user: &str) -> io::Result<usize> {
+        self.conn()
+            .execute("UPDATE sessions SET user_id = ?2 WHERE user_id = ?1", params![from_user, to_user])
+            .map_err(db_err)
+    }
+
+    /// Карты сессии из истории (пусто для сессий, импортированных из JSON)
+    pub fn history_map_runs(&self, session_id: &str) -> io::Result<Vec<MapRun>> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare("SELECT map_index, scene_name, entered_at, exited_at FROM map_runs WHERE session_id = ?1 ORDER BY map_index")
+            .map_err(db_err)?;
+        let runs: Vec<(i64, MapRun)> = stmt
+            .query_map([session_id], |r| {
+                Ok((r.get(0)?, MapRun { scene_name: r.get(1)?, entered_at: r.get(2)?, exited_at: r.get(3)?, drops: HashMap::new() }))
+            })
+            .map_err(db_err)?
+            .collect::<Result<_, _>>()
+            .map_err(db_err)?;
+
+        let mut drops_stmt = conn
+            .prepare("SELECT game_id, quantity FROM map_run_drops WHERE session_id = ?1 AND map_index = ?2")
+            .map_err(db_err)?;
+        let mut result = Vec::with_capacity(runs.len());
+        for (map_index, mut run) in runs {
+            run.drops = drops_stmt
+                .query_map(params![session_id, map_index], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, i32>(1)?)))
+                .map_err(db_err)?
+                .collect::<Result<_, _>>()
+                .map_err(db_err)?;
+            result.push(run);
+        }
+        Ok(result)
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // Активные сессии
+    // ─────────────────────────────────────────────────────────────────────────
+
+    /// Сохранить активную сессию. Хронология подборов и карты пишутся строками и только
+    /// с места изменения (обычно — новый подбор и текущая карта), скалярное состояние — JSON.
+    /// Списки на время сериализации JSON вынимаются из сессии, чтобы не копировать их.
+    pub fn save_active_session(&self, source_id: &str, session: &mut FarmSessionState) -> io::Result<()> {
+        let drop_log = std::mem::take(&mut session.drop_log);
+        let map_runs = std::mem::take(&mut session.map_runs);
+        let state = to_json(session);
+        session.drop_log = drop_log;
+        session.map_runs = map_runs;
+
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(db_err)?;
+        tx.execute(
+            "INSERT OR REPLACE INTO active_sessions (source_id, state, updated_at) VALUES (?1, ?2, ?3)",
+            params![source_id, state?, Utc::now()],
+        )
+        .map_err(db_err)?;
+        let rewritten = Self::sync_active_drops(&tx, source_id, &session.drop_log)?;
+        Self::sync_active_map_runs(&tx, source_id, &session.map_runs, rewritten)?;
+        tx.commit().map_err(db_err)
+    }
+
+    /// Дописать новые подборы. Если сохранённая хронология не префикс текущей (отмена подбора,
+    /// новая сессия) — переписать целиком. Возвращает true, если хронология переписана.
+    fn sync_active_drops(tx: &Transaction, source_id: &str, drop_log: &[DropRecord]) -> io::Result<bool> {
+        let last: Option<(usize, DropRecord)> = tx
+            .query_row(
+                "SELECT seq, at, game_id, quantity, map_index, correction FROM active_session_drops
+                 WHERE source_id = ?1 ORDER BY seq DESC LIMIT 1",
+                [source_id],
+                |r| Ok((r.get::<_, i64>(0)? as usize, DropRecord {
+                    timestamp: r.get(1)?,
+                    game_id: r.get(2)?,
+                    quantity: r.get(3)?,
+                    map_index: r.get(4)?,
+                    correction: r.get(5)?,
+                })),
+            )
+            .optional()
+            .map_err(db_err)?;
+        let (start, rewrite) = match last {
+            None => (0, false),
+            Some((seq, record)) if drop_log.get(seq) == Some(&record) => (seq + 1, false),
+            Some(_) => (0, true),
+        };
+        if rewrite {
+            tx.execute("DELETE FROM active_session_drops WHERE source_id = ?1", [source_id]).map_err(db_err)?;
+        }
+        let mut stmt = tx
+            .prepare(
+                "INSERT INTO active_session_drops (source_id, seq, at, game_id, quantity, map_index, correction)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )
+            .map_err(db_err)?;
+        for (seq, d) in drop_log.iter().enumerate().skip(start) {
+            stmt.execute(params![source_id, seq as i64, d.timestamp, d.game_id, d.quantity, d.map_index, d.correction])
+                .map_err(db_err)?;
+        }
+        Ok(rewrite)
+    }
+
+    /// Записать новые карты и последнюю сохранённую (она могла быть открытой).
+    /// Закрытые карты меняет только отмена подбора — тогда (rewrite) пишутся все.
+    fn sync_active_map_runs(tx: &Transaction, source_id: &str, map_runs: &[MapRun], rewrite: bool) -> io::Result<()> {
+        let stored: i64 = tx
+            .query_row("SELECT COUNT(*) FROM active_map_runs WHERE source_id = ?1", [source_id], |r| r.get(0))
+            .map_err(db_err)?;
+        let stored = stored as usize;
+        let start = if rewrite || stored > map_runs.len() { 0 } else { stored.saturating_sub(1) };
+        // map_index как в MapRunStats.index (с 1)
+        tx.execute(
+            "DELETE FROM active_map_runs WHERE source_id = ?1 AND map_index > ?2",
+            params![source_id, start as i64],
+        )
+        .map_err(db_err)?;
+        let mut run_stmt = tx
+            .prepare(
+                "INSERT INTO active_map_runs (source_id, map_index, scene_name, entered_at, exited_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )
+            .map_err(db_err)?;
+        let mut drop_stmt = tx
+            .prepare("INSERT INTO active_map_run_drops (source_id, map_index, game_id, quantity) VALUES (?1, ?2, ?3, ?4)")
+            .map_err(db_err)?;
+        for (i, run) in map_runs.iter().enumerate().skip(start) {
+            let map_index = i as i64 + 1;
+            run_stmt
+                .execute(params![source_id, map_index, run.scene_name, run.entered_at, run.exited_at])
+                .map_err(db_err)?;
+            for (game_id, qty) in &run.drops {
+                drop_stmt.execute(params![source_id, map_index, game_id, qty]).map_err(db_err)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load_active_session(&self, source_id: &str) -> io::Result<Option<FarmSessionState>> {
+        let conn = self.conn();
+        let state: Option<String> = conn
+            .query_row("SELECT state FROM active_sessions WHERE source_id = ?1", [source_id], |r| r.get(0))
+            .optional()
+            .map_err(db_err)?;
+        let Some(state) = state else {
+            return Ok(None);
+        };
+        let mut session: FarmSessionState = from_json(&state)?;
+        if session.started_at.is_none() {
+            return Ok(None);
+        }
+
+        let drop_log: Vec<DropRecord> = conn
+            .prepare(
+                "SELECT at, game_id, quantity, map_index, correction FROM active_session_drops
+                 WHERE source_id = ?1 ORDER BY seq",
+            )
+            .map_err(db_err)?
+            .query_map([source_id], |r| Ok(DropRecord {
+                timestamp: r.get(0)?,
+                game_id: r.get(1)?,
+                quantity: r.get(2)?,
+                map_index: r.get(3)?,
+                correction: r.get(4)?,
+            }))
+            .map_err(db_err)?
+            .collect::<Result<_, _>>()
+            .map_err(db_err)?;
+        let mut runs: Vec<(i64, MapRun)> = conn
+            .prepare("SELECT map_index, scene_name, entered_at, exited_at FROM active_map_runs WHERE source_id = ?1 ORDER BY map_index")
+            .map_err(db_err)?
+            .query_map([source_id], |r| {
+                Ok((r.get(0)?, MapRun { scene_name: r.get(1)?, entered_at: r.get(2)?, exited_at: r.get(3)?, drops: HashMap::new() }))
+            })
+            .map_err(db_err)?
+            .collect::<Result<_, _>>()
+            .map_err(db_err)?;
+        let mut drops_stmt = conn
+            .prepare("SELECT game_id, quantity FROM active_map_run_drops WHERE source_id = ?1 AND map_index = ?2")
+            .map_err(db_err)?;
+        for (map_index, run) in &mut runs {
+            run.drops = drops_stmt
+                .query_map(params![source_id, *map_index], |r| Ok((r.get::<_, i64>(0)?, r.get::<_, i32>(1)?)))
+                .map_err(db_err)?
+                .collect::<Result<_, _>>()
+                .map_err(db_err)?;
+        }
+
+        session.drop_log = drop_log;
+        session.map_runs = runs.into_iter().map(|(_, run)| run).collect();
+        Ok(Some(session))
+    }
+
+    pub fn delete_active_session(&self, source_id: &str) -> io::Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(db_err)?;
+        for table in ["active_sessions", "active_session_drops", "active_map_runs"] {
+            tx.execute(&format!("DELETE FROM {} WHERE source_id = ?1", table), [source_id])
+                .map_err(db_err)?;
+        }
+        tx.commit().map_err(db_err)
+    }
+
+    // ─────────────────────────────────────────────────────────────────────────
+    // Цены
+    // ─────────────────────────────────────────────────────────────────────────
+
+    /// Записать изменившиеся цены (одна транзакция на пачку)
+    pub fn upsert_prices(&self, entries: &[(i64, PersistedPriceEntry)]) -> io::Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(db_err)?;
+        {
+            let mut stmt = tx
+                .prepare(
+                    "INSERT OR REPLACE INTO prices (game_id, price, updated_at, is_current_league, league_name, strategy, source)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                )
+                .map_err(db_err)?;
+            for (game_id, p) in entries {
+                if !p.price.is_finite() || p.price <= 0.0 {
+                    continue;
+                }
+                let strategy = p.strategy.as_ref().map(to_json).transpose()?;
+                let source = p.source.as_ref().map(to_json).transpose()?;
+                stmt.execute(params![game_id, p.price, p.updated_at, p.is_current_league, p.league_name, strategy, source])
+                    .map_err(db_err)?;
+            }
+        }
+        tx.commit().map_err(db_err)
+    }
+
+    pub fn load_prices(&self) -> io::Result<HashMap<i64, PersistedPriceEntry>> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare("SELECT game_id, price, updated_at, is_current_league, league_name, strategy, source FROM prices")
+            .map_err(db_err)?;
+        let rows: Vec<PriceRow> = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?, r.get(5)?, r.get(6)?)))
+            .map_err(db_err)?
+            .collect::<Result<_, _>>()
+            .map_err(db_err)?;
+
+        let mut prices = HashMap::with_capacity(rows.len());
+        for (game_id, price, updated_at, is_current_league, league_name, strategy, source) in rows {
+            prices.insert(game_id, PersistedPriceEntry {
+                price,
+                updated_at,
+                is_current_league,
+                league_name,
+                strategy: strategy.as_deref().map(from_json).transpose()?,
+                source: source.as_deref().map(from_json).transpose()?,
+            });
+        }
+        Ok(prices)
+    }
+
+    /// Дописать наблюдения цен (повторы игнорируются)
+    pub fn append_price_observations(&self, observations: &[(i64, PriceObservation)]) -> io::Result<()> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(db_err)?;
+        {
+            let mut stmt = tx
+                .prepare("INSERT OR IGNORE INTO price_history (game_id, at, price, source) VALUES (?1, ?2, ?3, ?4)")
+                .map_err(db_err)?;
+            for (game_id, o) in observations {
+                stmt.execute(params![game_id, o.at, o.price, to_json(&o.source)?]).map_err(db_err)?;
+            }
+        }
+        tx.commit().map_err(db_err)
+    }
+
+    /// Наблюдения цен не старше `since` (все предметы или один), по времени
+    pub fn load_price_history(&self, game_id: Option<i64>, since: DateTime<Utc>) -> io::Result<HashMap<i64, Vec<PriceObservation>>> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare(
+                "SELECT game_id, at, price, source FROM price_history
+                 WHERE at >= ?1 AND (?2 IS NULL OR game_id = ?2)
+                 ORDER BY game_id, at",
+            )
+            .map_err(db_err)?;
+        let rows: Vec<(i64, DateTime<Utc>, f64, String)> = stmt
+            .query_map(params![since, game_id], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)))
+            .map_err(db_err)?
+            .collect::<Result<_, _>>()
+            .map_err(db_err)?;
+
+        let mut history: HashMap<i64, Vec<PriceObservation>> = HashMap::new();
+        for (game_id, at, price, source) in rows {
+            history.entry(game_id).or_default().push(PriceObservation { at, price, source: from_json(&source)? });
+        }
+        Ok(history)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use crate::types::{ExpenseEntry, IncomeSource, IncomeSourceTotal, PriceObservationSource};
+
+    fn record(id: &str, started_at: DateTime<Utc>) -> SessionHistoryRecord {
+        SessionHistoryRecord {
+            id: id.to_string(),
+            started_at,
+            ended_at: started_at + Duration::hours(1),
+            maps_completed: 2,
+            total_duration_sec: 3600,
+            total_profit: 100.0,
+            total_expenses: 10.0,
+            total_income: 110.0,
+            net_income: Some(100.0),
+            income_by_source: vec![IncomeSourceTotal { source: IncomeSource::AutoDrop, quantity: 3, gross_value: 110.0, net_value: 100.0 }],
+            remote_id: None,
+            preset_id: Some("p1".into()),
+            drops: [(1, 3)].into_iter().collect(),
+            drop_values: [(1, RecordedDropValue { unit_price: 36.0, total_value: 108.0, net_value: 98.0 })].into_iter().collect(),
+            manual_drops: Vec::new(),
+            expenses: vec![ExpenseEntry { id: "e1".into(), game_id: None, name: "Compass".into(), name_ru: None, quantity: 2, price: 5.0 }],
+        }
+    }
+
+    #[test]
+    fn test_history_roundtrip_and_cascade_delete() {
+        let store = Store::open_in_memory().unwrap();
+        let now = Utc::now();
+        let run = MapRun { scene_name: "ZoneA".into(), entered_at: now, exited_at: Some(now), drops: [(1, 3)].into_iter().collect() };
+        store.insert_history_record("u1", &record("s1", now - Duration::days(1)), &[run]).unwrap();
+        store.insert_history_record("u1", &record("s2", now), &[]).unwrap();
+        store.insert_history_record("u2", &record("s3", now), &[]).unwrap();
+
+        let history = store.load_history("u1", None).unwrap();
+        assert_eq!(history.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), vec!["s2", "s1"]);
+        assert_eq!(history[1].drops.get(&1), Some(&3));
+        assert_eq!(history[1].drop_values[&1].total_value, 108.0);
+        assert_eq!(history[1].income_by_source.len(), 1);
+        assert_eq!(history[1].expenses.len(), 1);
+        assert_eq!(store.load_history("u1", Some(1)).unwrap().len(), 1);
+        assert_eq!(store.history_map_runs("s1").unwrap()[0].drops.get(&1), Some(&3));
+
+        assert!(store.delete_history_record("u1", "s1").unwrap());
+        assert!(store.history_map_runs("s1").unwrap().is_empty());
+        assert!(!store.delete_history_record("u1", "s3").unwrap());
+    }
+
+    #[test]
+    fn test_prices_and_history_roundtrip() {
+        let store = Store::open_in_memory().unwrap();
+        let now = Utc::now();
+        let entry = PersistedPriceEntry {
+            price: 2.5,
+            updated_at: now,
+            is_current_league: true,
+            league_name: None,
+            strategy: Some(crate::types::PriceStrategy::Median),
+            source: Some(PriceObservationSource::PriceCheck),
+        };
+        store.upsert_prices(&[(7, entry)]).unwrap();
+        let prices = store.load_prices().unwrap();
+        assert_eq!(prices[&7].strategy, Some(crate::types::PriceStrategy::Median));
+
+        let obs = PriceObservation { at: now, price: 2.5, source: PriceObservationSource::PriceCheck };
+        store.append_price_observations(&[(7, obs.clone()), (7, obs)]).unwrap();
+        let history = store.load_price_history(None, now - Duration::days(1)).unwrap();
+        assert_eq!(history[&7].len(), 1);
+        assert!(store.load_price_history(Some(8), now - Duration::days(1)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_active_session_rows() {
+        let store = Store::open_in_memory().unwrap();
+        let now = Utc::now();
+        let pickup = |game_id, map_index| DropRecord { timestamp: now, game_id, quantity: 1, map_index, correction: false };
+        let mut session = FarmSessionState { started_at: Some(now), ..Default::default() };
+        session.map_runs.push(MapRun { scene_name: "ZoneA".into(), entered_at: now, exited_at: None, drops: HashMap::new() });
+        session.drop_log = vec![pickup(1, Some(1)), pickup(2, Some(1))];
+        session.map_runs[0].drops = [(1, 1), (2, 1)].into_iter().collect();
+        store.save_active_session("main", &mut session).unwrap();
+
+        // Карта закрыта, новая карта с подбором — дописываются только изменения
+        session.map_runs[0].exited_at = Some(now);
+        session.map_runs.push(MapRun { scene_name: "ZoneB".into(), entered_at: now, exited_at: None, drops: [(3, 1)].into_iter().collect() });
+        session.drop_log.push(pickup(3, Some(2)));
+        store.save_active_session("main", &mut session).unwrap();
+        let loaded = store.load_active_session("main").unwrap().unwrap();
+        assert_eq!(loaded.drop_log, session.drop_log);
+        assert_eq!(loaded.map_runs.len(), 2);
+        assert!(loaded.map_runs[0].exited_at.is_some());
+        assert_eq!(loaded.map_runs[1].drops.get(&3), Some(&1));
+
+        // Отмена подбора из закрытой карты — хронология и карты переписываются
+        session.drop_log.remove(1);
+        session.map_runs[0].drops.remove(&2);
+        session.drop_log.push(pickup(4, Some(2)));
+        store.save_active_session("main", &mut session).unwrap();
+        let loaded = store.load_active_session("main").unwrap().unwrap();
+        assert_eq!(loaded.drop_log, session.drop_log);
+        assert!(!loaded.map_runs[0].drops.contains_key(&2));
+
+        store.delete_active_session("main").unwrap();
+        assert!(store.load_active_session("main").unwrap().is_none());
+        let rows: i64 = store.conn().query_row("SELECT COUNT(*) FROM active_session_drops", [], |r| r.get(0)).unwrap();
+        assert_eq!(rows, 0);
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let path = std::env::temp_dir().join(format!("tli-store-{}.db", uuid::Uuid::new_v4()));
+        let source = Store::open_in_memory().unwrap();
+        source.insert_history_record("u1", &record("s1", Utc::now()), &[]).unwrap();
+        source.snapshot_to(&path).unwrap();
+
+        let target = Store::open_in_memory().unwrap();
+        target.insert_history_record("u1", &record("old", Utc::now()), &[]).unwrap();
+        target.restore_from(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let history = target.load_history("u1", None).unwrap();
+        assert_eq!(history.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), vec!["s1"]);
+        assert_eq!(target.schema_version().unwrap(), MIGRATIONS.len());
+    }
+}
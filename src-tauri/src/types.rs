@@ -106,9 +106,352 @@ pub struct FarmSessionState {
     /// Ручной дроп за сессию (для уников/экипировки)
     #[serde(default)]
     pub manual_drops: Vec<ManualDropEntry>,
-    /// Общее время сессии в секундах (обновляется фронтендом)
+    /// Активное время сессии в секундах (кэш часов бэкенда, см. active_duration_sec)
     #[serde(default)]
     pub session_duration_sec: i32,
+    /// Интервалы паузы (последний может быть открытым — сессия сейчас на паузе)
+    #[serde(default)]
+    pub pauses: Vec<PauseInterval>,
+    /// Последний heartbeat приложения (для учёта времени, когда приложение было закрыто)
+    #[serde(default)]
+    pub last_seen_at: Option<DateTime<Utc>>,
+    /// Пройденные карты (последняя может быть открытой — мы сейчас на ней)
+    #[serde(default)]
+    pub map_runs: Vec<MapRun>,
+    /// Хронология подборов (drops — это её агрегат по game_id)
+    #[serde(default)]
+    pub drop_log: Vec<DropRecord>,
+    /// Предметы, исключённые из подсчёта стоимости (например, перенос из склада)
+    #[serde(default)]
+    pub excluded_items: std::collections::HashSet<i64>,
+    /// Журнал ручных правок авто-дропа
+    #[serde(default)]
+    pub drop_corrections: Vec<DropCorrection>,
+}
+
+impl FarmSessionState {
+    /// Текущая (незакрытая) карта
+    pub fn current_map_run_mut(&mut self) -> Option<&mut MapRun> {
+        self.map_runs.last_mut().filter(|r| r.exited_at.is_none())
+    }
+
+    /// Суммарное время пауз до момента now
+    pub fn paused_duration_sec(&self, now: DateTime<Utc>) -> i64 {
+        self.pauses
+            .iter()
+            .map(|p| (p.ended_at.unwrap_or(now).min(now) - p.started_at).num_seconds().max(0))
+            .sum()
+    }
+
+    /// Активное время сессии: от started_at до now минус паузы
+    pub fn active_duration_sec(&self, now: DateTime<Utc>) -> i32 {
+        let Some(started) = self.started_at else {
+            return 0;
+        };
+        let total = (now - started).num_seconds().max(0);
+        (total - self.paused_duration_sec(now)).max(0) as i32
+    }
+
+    /// Открытый интервал паузы (сессия сейчас на паузе)
+    pub fn open_pause_mut(&mut self) -> Option<&mut PauseInterval> {
+        self.pauses.last_mut().filter(|p| p.ended_at.is_none())
+    }
+
+    /// Открыть интервал паузы (если ещё не открыт)
+    pub fn open_pause(&mut self, at: DateTime<Utc>, kind: PauseKind) {
+        if self.open_pause_mut().is_some() {
+            return;
+        }
+        self.pauses.push(PauseInterval { started_at: at, ended_at: None, kind });
+    }
+
+    /// Закрыть открытый интервал паузы
+    pub fn close_pause(&mut self, at: DateTime<Utc>) {
+        if let Some(p) = self.open_pause_mut() {
+            p.ended_at = Some(at.max(p.started_at));
+        }
+    }
+}
+
+/// Интервал паузы сессии
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PauseInterval {
+    pub started_at: DateTime<Utc>,
+    /// None — пауза продолжается
+    #[serde(default)]
+    pub ended_at: Option<DateTime<Utc>>,
+    /// Откуда пауза
+    #[serde(default)]
+    pub kind: PauseKind,
+}
+
+/// Тип паузы
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+pub enum PauseKind {
+    /// Пользователь нажал паузу
+    #[default]
+    Manual,
+    /// Автопауза по бездействию в убежище
+    Idle,
+    /// Приложение было закрыто
+    Offline,
+}
+
+/// Событие автопаузы/автовозобновления (для UI)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutoPauseEvent {
+    pub source_id: String,
+    /// true — поставили на паузу, false — сняли
+    pub paused: bool,
+    /// Начало паузы (задним числом — с последней активности) или момент возобновления
+    pub at: DateTime<Utc>,
+}
+
+/// Один подбор предмета в хронологии сессии
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DropRecord {
+    /// Время подбора (из лога)
+    pub timestamp: DateTime<Utc>,
+    pub game_id: i64,
+    pub quantity: i32,
+    /// Номер карты в сессии (как MapRunStats.index), None — подбор вне карты
+    #[serde(default)]
+    pub map_index: Option<i32>,
+    /// Запись-поправка (ручная правка количества), в ленте подборов не показывается
+    #[serde(default)]
+    pub correction: bool,
+}
+
+/// Тип ручной правки авто-дропа
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DropCorrectionKind {
+    /// Изменено количество предмета
+    AdjustQuantity,
+    /// Предмет исключён из подсчёта стоимости
+    Exclude,
+    /// Предмет снова учитывается в стоимости
+    Include,
+    /// Отменён последний подбор
+    UndoDrop,
+}
+
+/// Запись журнала правок дропа (для аудита, хранится в сессии)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DropCorrection {
+    pub at: DateTime<Utc>,
+    pub kind: DropCorrectionKind,
+    pub game_id: i64,
+    /// Количество в сессии до правки
+    pub quantity_before: i32,
+    /// Количество в сессии после правки
+    pub quantity_after: i32,
+    /// Отменённый подбор (для UndoDrop)
+    #[serde(default)]
+    pub undone: Option<DropRecord>,
+}
+
+/// Подбор с ценой (лента последних дропов)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentDrop {
+    pub timestamp: DateTime<Utc>,
+    pub game_id: i64,
+    pub item_info: Option<ItemInfo>,
+    pub quantity: i32,
+    pub map_index: Option<i32>,
+    pub unit_price: f64,
+    pub total_value: f64,
+}
+
+/// Точка графика дохода: накопленная стоимость на момент времени
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValuePoint {
+    pub timestamp: DateTime<Utc>,
+    /// Стоимость дропа в этой точке (за интервал, если точки сгруппированы)
+    pub value: f64,
+    /// Накопленная стоимость с начала сессии
+    pub cumulative_value: f64,
+}
+
+/// Накопленный дроп предмета в одной зоне (по завершённым картам)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ItemDropTally {
+    /// Всего штук
+    pub total: i64,
+    /// Сумма квадратов количества за карту (для дисперсии)
+    pub sum_sq: f64,
+    /// Карт, на которых предмет выпал
+    pub maps_with_drop: i64,
+}
+
+/// Накопитель статистики дропа: зона + пресет
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DropRateBucket {
+    /// Сцена карты (MapRun.scene_name)
+    pub zone: String,
+    #[serde(default)]
+    pub preset_id: Option<String>,
+    pub sessions: i64,
+    pub maps: i64,
+    /// Суммарное время на картах
+    pub duration_sec: i64,
+    #[serde(default)]
+    pub items: std::collections::HashMap<i64, ItemDropTally>,
+}
+
+/// Оценка ставки с 95% доверительным интервалом (None — мало данных)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RateEstimate {
+    pub value: f64,
+    pub ci_low: Option<f64>,
+    pub ci_high: Option<f64>,
+}
+
+/// Частота дропа предмета в зоне
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemDropRate {
+    pub game_id: i64,
+    pub item_info: Option<ItemInfo>,
+    pub total_quantity: i64,
+    pub maps_with_drop: i64,
+    /// Доля карт, на которых предмет выпал
+    pub drop_chance: f64,
+    pub per_map: RateEstimate,
+    pub per_hour: RateEstimate,
+}
+
+/// Статистика дропа зоны (для одного пресета или по всем)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZoneDropStats {
+    pub zone: String,
+    /// None — объединено по нескольким пресетам (или без пресета)
+    pub preset_id: Option<String>,
+    pub sessions: i64,
+    pub maps: i64,
+    pub duration_sec: i64,
+    pub items: Vec<ItemDropRate>,
+}
+
+/// Ожидаемый дроп предмета за N карт
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExpectedDrop {
+    pub game_id: i64,
+    pub item_info: Option<ItemInfo>,
+    pub expected: RateEstimate,
+}
+
+/// Откуда пришло наблюдение цены
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceObservationSource {
+    /// Прайсчек из лога
+    PriceCheck,
+    /// Remote цены (Supabase)
+    Remote,
+    /// Введена вручную
+    Manual,
+}
+
+/// Наблюдение цены предмета
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceObservation {
+    pub at: DateTime<Utc>,
+    pub price: f64,
+    pub source: PriceObservationSource,
+}
+
+/// Изменение цены за окно
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceChange {
+    /// Цена в начале окна (последнее наблюдение не позже начала окна)
+    pub from_price: f64,
+    pub from_at: DateTime<Utc>,
+    pub change: f64,
+    pub change_pct: f64,
+}
+
+/// Тренд цены предмета
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceTrend {
+    pub game_id: i64,
+    pub current: Option<f64>,
+    pub current_at: Option<DateTime<Utc>>,
+    /// None — нет наблюдений старше окна
+    pub change_24h: Option<PriceChange>,
+    pub change_7d: Option<PriceChange>,
+}
+
+/// Волатильность цены за окно
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceVolatility {
+    pub game_id: i64,
+    pub window_days: i64,
+    pub samples: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    /// std_dev / mean
+    pub coefficient_of_variation: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Стоимость сессии из истории по ценам на заданный момент
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoricalSessionValue {
+    pub session_id: String,
+    pub at: DateTime<Utc>,
+    /// Авто-дроп по ценам на момент `at` + ручной дроп по введённой цене (до комиссии)
+    pub value: f64,
+    /// Стоимость на момент завершения сессии (до комиссии)
+    pub recorded_value: f64,
+    /// Предметы без наблюдений цены на момент `at`
+    pub unpriced_items: Vec<i64>,
+}
+
+/// Запись об одной карте внутри сессии
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MapRun {
+    /// Сцена карты (NextSceneName)
+    pub scene_name: String,
+    /// Время входа на карту
+    pub entered_at: DateTime<Utc>,
+    /// Время выхода в убежище (None — карта ещё идёт)
+    #[serde(default)]
+    pub exited_at: Option<DateTime<Utc>>,
+    /// Дропы за карту: game_id -> количество
+    #[serde(default)]
+    pub drops: std::collections::HashMap<i64, i32>,
+}
+
+/// Карта с посчитанной стоимостью (для UI)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MapRunStats {
+    /// Порядковый номер карты в сессии (с 1)
+    pub index: i32,
+    pub scene_name: String,
+    pub entered_at: DateTime<Utc>,
+    pub exited_at: Option<DateTime<Utc>>,
+    /// Длительность карты (для текущей — сколько уже идёт)
+    pub duration_sec: i32,
+    /// Карта ещё не завершена
+    pub is_current: bool,
+    pub total_items: i32,
+    /// Стоимость дропа за карту (по текущим ценам)
+    pub total_value: f64,
+    pub drops: Vec<AggregatedDrop>,
+}
+
+/// Распределение стоимости по завершённым картам
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct MapRunSummary {
+    /// Завершённых карт в выборке
+    pub completed_maps: i32,
+    pub best: Option<MapRunStats>,
+    pub worst: Option<MapRunStats>,
+    pub avg_value: f64,
+    pub median_value: f64,
+    pub p25_value: f64,
+    pub p75_value: f64,
 }
 
 /// Запись о расходе (ручной ввод)
@@ -149,6 +492,128 @@ pub struct ManualDropEntry {
     pub price: f64,
 }
 
+/// Источник дохода в журнале дохода сессии
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IncomeSource {
+    /// Дроп из лога (session.drops)
+    AutoDrop,
+    /// Ручной дроп (уники/экипировка)
+    ManualDrop,
+}
+
+/// Строка журнала дохода
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncomeEntry {
+    pub source: IncomeSource,
+    /// ID записи в источнике (id ручного дропа), None для авто-дропа
+    #[serde(default)]
+    pub entry_id: Option<String>,
+    #[serde(default)]
+    pub game_id: Option<i64>,
+    pub name: String,
+    pub quantity: i32,
+    pub unit_price: f64,
+    /// Стоимость до комиссии аукциона (0 для неучитываемых строк)
+    pub gross_value: f64,
+    /// Стоимость после комиссии аукциона
+    pub net_value: f64,
+}
+
+/// Оценка предмета авто-дропа при завершении сессии (вошла в доход записи истории)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RecordedDropValue {
+    /// Цена за единицу после правил оценки
+    pub unit_price: f64,
+    /// Стоимость до комиссии аукциона (0 для неучитываемых предметов)
+    pub total_value: f64,
+    /// Стоимость после комиссии
+    pub net_value: f64,
+}
+
+/// Итог дохода по одному источнику
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncomeSourceTotal {
+    pub source: IncomeSource,
+    pub quantity: i32,
+    pub gross_value: f64,
+    pub net_value: f64,
+}
+
+/// Единый журнал дохода сессии: авто-дроп, ручной дроп и т.д.
+/// Статистика, история и экспорт считают доход только через него.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IncomeLedger {
+    pub entries: Vec<IncomeEntry>,
+    pub by_source: Vec<IncomeSourceTotal>,
+    pub gross_total: f64,
+    pub net_total: f64,
+    /// Кол-во позиций авто-дропа с устаревшей ценой
+    pub stale_price_lines: i32,
+}
+
+/// Пресет стратегии фарма (сохраняется на диск)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrategyPreset {
+    pub id: String,
+    pub name: String,
+    /// Целевая зона / тип контента (свободный текст или сцена карты)
+    #[serde(default)]
+    pub target_zone: Option<String>,
+    /// Расходники на одну карту
+    #[serde(default)]
+    pub consumables: Vec<PresetConsumable>,
+    /// Плановое кол-во карт (None — без плана)
+    #[serde(default)]
+    pub expected_maps: Option<i32>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// Как списывать расходники при сессии с этим пресетом
+    #[serde(default)]
+    pub cost_mode: PresetCostMode,
+    #[serde(default = "Utc::now")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default = "Utc::now")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Расходник пресета (на одну карту)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PresetConsumable {
+    pub game_id: i64,
+    pub quantity: i32,
+    /// Фиксированная цена за единицу; None — цена из кэша на момент списания
+    #[serde(default)]
+    pub price: Option<f64>,
+}
+
+/// Режим списания расходников пресета
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Default)]
+pub enum PresetCostMode {
+    /// При каждом входе на карту
+    #[default]
+    PerMap,
+    /// Сразу при старте сессии на expected_maps карт
+    UpFront,
+}
+
+/// Сравнение текущей сессии с прошлыми сессиями того же пресета
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PresetComparison {
+    pub preset_id: String,
+    /// Сколько прошлых сессий с этим пресетом в истории
+    pub previous_sessions: i32,
+    pub avg_profit: f64,
+    pub avg_hourly_profit: f64,
+    pub avg_profit_per_map: f64,
+    pub best_hourly_profit: f64,
+    pub current_profit: f64,
+    pub current_hourly_profit: f64,
+    pub current_profit_per_map: f64,
+    /// Отклонение дохода в час от среднего, % (None — нет истории)
+    pub hourly_delta_pct: Option<f64>,
+}
+
 /// Агрегированный дроп для отображения
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AggregatedDrop {
@@ -156,6 +621,9 @@ pub struct AggregatedDrop {
     pub item_info: Option<ItemInfo>,
     pub quantity: i32,
     pub total_value: f64,
+    /// Стоимость после комиссии аукциона (базовая валюта и оставляемые предметы — без комиссии)
+    #[serde(default)]
+    pub net_value: f64,
     /// Цена за 1 штуку (последняя известная)
     pub unit_price: f64,
     /// Дата обновления цены (если известна)
@@ -166,6 +634,161 @@ pub struct AggregatedDrop {
     pub is_previous_season: bool,
     /// Название лиги откуда цена (SS10, SS11, etc)
     pub league_name: Option<String>,
+    /// Исключён пользователем из подсчёта стоимости (total_value = 0)
+    #[serde(default)]
+    pub is_excluded: bool,
+    /// Правило оценки, изменившее цену или учёт строки (None — рыночная цена)
+    #[serde(default)]
+    pub valuation_rule: Option<ValuationRuleKind>,
+    /// Откуда рыночная цена (None — нет цены, базовая валюта или фикс. цена)
+    #[serde(default)]
+    pub price_source: Option<PriceOrigin>,
+    /// Доверие к цене 0..1 с учётом возраста, TTL и источника
+    #[serde(default)]
+    pub price_confidence: f64,
+}
+
+/// Пользовательские правила оценки предметов (на базовую валюту не действуют)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ValuationRules {
+    /// Предметы, которые не продаём — в стоимость не идут
+    #[serde(default)]
+    pub ignored_items: std::collections::HashSet<i64>,
+    /// Фиксированная цена за единицу (вместо рыночной)
+    #[serde(default)]
+    pub fixed_prices: std::collections::HashMap<i64, f64>,
+    /// Минимальная цена за единицу: более дешёвые предметы в стоимость не идут
+    #[serde(default)]
+    pub min_unit_value: Option<f64>,
+    /// Множитель рыночной цены по категории (ItemInfo.category)
+    #[serde(default)]
+    pub category_multipliers: std::collections::HashMap<String, f64>,
+    /// Предметы, которые оставляем себе — считаются по цене, но без комиссии аукциона
+    #[serde(default)]
+    pub kept_items: std::collections::HashSet<i64>,
+    /// Как выбирать рыночную цену из списка лотов прайсчека
+    #[serde(default)]
+    pub price_selection: PriceSelectionRules,
+    /// Когда цена считается устаревшей
+    #[serde(default)]
+    pub freshness: FreshnessRules,
+}
+
+/// Источник цены в кэше для политики свежести
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceOrigin {
+    /// Свой прайсчек из лога
+    PriceCheck,
+    /// Введена вручную
+    Manual,
+    /// Crowd цены (Supabase)
+    Crowd,
+    /// Цена из предыдущей лиги (fallback)
+    PreviousLeague,
+}
+
+/// Множители TTL по источнику цены (0 — цена из источника всегда устаревшая)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceSourceWeights {
+    pub price_check: f64,
+    pub manual: f64,
+    pub crowd: f64,
+    pub previous_league: f64,
+}
+
+impl Default for PriceSourceWeights {
+    fn default() -> Self {
+        Self { price_check: 1.0, manual: 1.0, crowd: 0.5, previous_league: 0.25 }
+    }
+}
+
+/// Политика свежести цен: TTL предмета > категории > общий, с поправкой на источник
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FreshnessRules {
+    #[serde(default = "default_price_ttl_sec")]
+    pub default_ttl_sec: i64,
+    /// TTL по категории (ItemInfo.category)
+    #[serde(default = "default_category_ttl_sec")]
+    pub categories: std::collections::HashMap<String, i64>,
+    #[serde(default)]
+    pub items: std::collections::HashMap<i64, i64>,
+    #[serde(default)]
+    pub source_weights: PriceSourceWeights,
+}
+
+impl Default for FreshnessRules {
+    fn default() -> Self {
+        Self {
+            default_ttl_sec: default_price_ttl_sec(),
+            categories: default_category_ttl_sec(),
+            items: std::collections::HashMap::new(),
+            source_weights: PriceSourceWeights::default(),
+        }
+    }
+}
+
+fn default_price_ttl_sec() -> i64 { 60 * 60 }
+
+/// Волатильные предметы устаревают за минуты, билеты на карты держат цену днями
+fn default_category_ttl_sec() -> std::collections::HashMap<String, i64> {
+    [
+        ("memory_fluorescence", 15 * 60),
+        ("memory_material", 2 * 60 * 60),
+        ("cube_material", 6 * 60 * 60),
+        ("equipment_material", 6 * 60 * 60),
+        ("currency", 6 * 60 * 60),
+        ("gameplay_ticket", 24 * 60 * 60),
+        ("map_ticket", 3 * 24 * 60 * 60),
+    ]
+    .into_iter()
+    .map(|(c, ttl)| (c.to_string(), ttl))
+    .collect()
+}
+
+/// Стратегия выбора цены из списка цен лотов (unitPrices)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PriceStrategy {
+    /// Самый дешёвый лот
+    Min,
+    /// Перцентиль (0-100)
+    Percentile { percentile: f64 },
+    Median,
+    /// Среднее без trim_pct% самых дешёвых и самых дорогих лотов (0-50)
+    TrimmedMean { trim_pct: f64 },
+    /// Среднее, взвешенное по объёму лотов (без объёмов — каждый лот с весом 1)
+    VolumeWeighted,
+}
+
+impl Default for PriceStrategy {
+    /// p20: для маленьких выборок это фактически min, для больших — устойчивее
+    fn default() -> Self {
+        PriceStrategy::Percentile { percentile: 20.0 }
+    }
+}
+
+/// Стратегии выбора цены: предмет > категория > глобальная
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PriceSelectionRules {
+    #[serde(default)]
+    pub default: PriceStrategy,
+    /// По категории (ItemInfo.category)
+    #[serde(default)]
+    pub categories: std::collections::HashMap<String, PriceStrategy>,
+    #[serde(default)]
+    pub items: std::collections::HashMap<i64, PriceStrategy>,
+}
+
+/// Какое правило оценки применено к строке дропа
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValuationRuleKind {
+    Ignored,
+    FixedPrice,
+    CategoryMultiplier,
+    /// Цена ниже min_unit_value
+    BelowMinValue,
 }
 
 /// Настройки приложения
@@ -200,6 +823,46 @@ pub struct AppSettings {
     /// Всегда поверх окон
     #[serde(default = "default_true")]
     pub always_on_top: bool,
+    /// Дополнительные лог-файлы (второй клиент / мультибокс), основной задаётся custom_log_path
+    #[serde(default)]
+    pub log_sources: Vec<LogSourceConfig>,
+    /// Автопауза, если в убежище нет активности (включается пользователем)
+    #[serde(default)]
+    pub auto_pause_enabled: bool,
+    /// Сколько секунд без событий лога (дроп, карта, прайсчек) до автопаузы
+    #[serde(default = "default_auto_pause_idle_sec")]
+    pub auto_pause_idle_sec: i64,
+    /// Валюта отображения значений (game_id предмета, None — базовая валюта)
+    #[serde(default)]
+    pub display_denomination: Option<i64>,
+    /// Аккаунты, отказавшиеся от переноса гостевой истории (предложение больше не показывается)
+    #[serde(default)]
+    pub guest_merge_declined: Vec<String>,
+}
+
+/// Дополнительный источник логов (второй клиент игры)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogSourceConfig {
+    /// Стабильный ID источника (используется в именах файлов сессии)
+    pub id: String,
+    /// Отображаемое имя (например, ник аккаунта)
+    pub name: String,
+    /// Путь к UE_game.log
+    pub path: String,
+}
+
+/// Информация об источнике логов для UI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogSourceInfo {
+    pub id: String,
+    pub name: String,
+    pub log_path: Option<String>,
+    /// Выбран в UI (команды без source_id работают с ним)
+    pub is_selected: bool,
+    /// Есть активная сессия
+    pub session_active: bool,
+    /// Watcher запущен
+    pub is_watching: bool,
 }
 
 fn default_true() -> bool { true }
@@ -209,6 +872,7 @@ fn default_orientation() -> String { "vertical".to_string() }
 fn default_panel_direction() -> String { "right".to_string() }
 fn default_auction_fee() -> f64 { 0.125 }
 fn default_opacity() -> f64 { 1.0 }
+fn default_auto_pause_idle_sec() -> i64 { 300 }
 
 impl Default for AppSettings {
     fn default() -> Self {
@@ -223,6 +887,11 @@ impl Default for AppSettings {
             auction_fee_rate: 0.125,
             opacity: 1.0,
             always_on_top: true,
+            log_sources: Vec::new(),
+            auto_pause_enabled: false,
+            auto_pause_idle_sec: 300,
+            display_denomination: None,
+            guest_merge_declined: Vec::new(),
         }
     }
 }
@@ -243,20 +912,204 @@ pub struct SessionStats {
     pub total_items: i32,
     /// Уникальных типов предметов
     pub unique_items: i32,
-    /// Общая стоимость (расчётная)
+    /// Общая стоимость (расчётная, до комиссии аукциона)
     pub total_value: f64,
+    /// Стоимость после комиссии аукциона
+    #[serde(default)]
+    pub net_value: f64,
+    /// Сумма комиссии аукциона (total_value - net_value)
+    #[serde(default)]
+    pub auction_fee: f64,
     /// Карт завершено
     pub maps_completed: i32,
-    /// Время сессии в секундах
+    /// Активное время сессии в секундах (без пауз)
     pub duration_sec: i32,
     /// Среднее время на карту (сек). Если карт ещё нет, может показывать текущую карту (если мы на карте).
     pub avg_map_duration_sec: i32,
     /// Кол-во позиций дропа, у которых цена устарела (старше TTL)
     pub stale_price_lines: i32,
-    /// Доход в час (расчётный)
+    /// Доход в час (расчётный, до комиссии)
     pub hourly_profit: f64,
+    /// Доход в час после комиссии аукциона
+    #[serde(default)]
+    pub net_hourly_profit: f64,
     /// Сессия на паузе
     pub is_paused: bool,
+    /// Валюта значений (None — базовая валюта)
+    #[serde(default)]
+    pub denomination: Option<Denomination>,
+}
+
+/// Валюта отображения значений: базовая валюта или любой предмет с известной ценой
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Denomination {
+    /// None — базовая валюта
+    pub game_id: Option<i64>,
+    pub item_info: Option<ItemInfo>,
+    /// Сколько базовой валюты стоит 1 единица
+    pub rate: f64,
+    pub rate_updated_at: Option<DateTime<Utc>>,
+    /// Курс по устаревшей цене
+    pub rate_is_stale: bool,
+}
+
+/// Цель прогноза: длительность (активное время), момент времени или число карт
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ForecastTarget {
+    Duration { duration_sec: i64 },
+    At { at: DateTime<Utc> },
+    Maps { maps: i32 },
+}
+
+/// Прогноз итогов сессии по текущему темпу
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionForecast {
+    /// Сколько ещё активного времени до цели
+    pub remaining_sec: i64,
+    pub projected_duration_sec: i64,
+    pub projected_maps: f64,
+    /// Доход до комиссии аукциона
+    pub projected_value: f64,
+    /// Доход после комиссии аукциона
+    pub projected_net_value: f64,
+    pub hourly_value: f64,
+    pub net_hourly_value: f64,
+    pub maps_per_hour: f64,
+    /// Когда будет достигнута цель (без учёта будущих пауз)
+    pub finish_at: DateTime<Utc>,
+}
+
+/// Тип цели сессии
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GoalKind {
+    /// Доход после комиссии (FE)
+    Income,
+    /// Завершённые карты
+    Maps,
+    /// Количество предмета (game_id)
+    Item,
+}
+
+/// Отметка о достижении цели в конкретной сессии источника
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoalReachedMark {
+    pub source_id: String,
+    pub session_started_at: DateTime<Utc>,
+    pub reached_at: DateTime<Utc>,
+}
+
+/// Цель на сессию ("2000 FE", "50 карт", "10 Divinity Stones")
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Goal {
+    /// Пустой id при сохранении — создать новую цель
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub name: String,
+    pub kind: GoalKind,
+    pub target: f64,
+    /// Предмет для GoalKind::Item
+    #[serde(default)]
+    pub game_id: Option<i64>,
+    /// Источник логов (None — цель для каждого источника)
+    #[serde(default)]
+    pub source_id: Option<String>,
+    #[serde(default = "Utc::now")]
+    pub created_at: DateTime<Utc>,
+    /// В каких сессиях цель уже достигнута (по одной отметке на источник)
+    #[serde(default)]
+    pub reached: Vec<GoalReachedMark>,
+}
+
+/// Прогресс цели в текущей сессии источника
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoalProgress {
+    pub goal: Goal,
+    pub source_id: String,
+    pub current: f64,
+    pub remaining: f64,
+    /// 0..100
+    pub progress_pct: f64,
+    pub rate_per_hour: f64,
+    /// Оценка в активном времени (None — темпа ещё нет)
+    pub eta_sec: Option<i64>,
+    pub eta_at: Option<DateTime<Utc>>,
+    pub reached: bool,
+}
+
+/// Условие ценового оповещения
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PriceAlertCondition {
+    /// Цена выше порога
+    Above { price: f64 },
+    /// Цена ниже порога
+    Below { price: f64 },
+    /// Изменение больше pct% (в любую сторону) за window_min минут
+    ChangePct { pct: f64, window_min: i64 },
+}
+
+/// Ценовое оповещение по предмету
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceAlert {
+    /// Пустой id при сохранении — создать новое оповещение
+    #[serde(default)]
+    pub id: String,
+    pub game_id: i64,
+    pub condition: PriceAlertCondition,
+    /// Не срабатывать повторно раньше, чем через cooldown_min минут
+    #[serde(default = "default_alert_cooldown_min")]
+    pub cooldown_min: i64,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default = "Utc::now")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub last_fired_at: Option<DateTime<Utc>>,
+}
+
+fn default_alert_cooldown_min() -> i64 { 60 }
+
+/// Сработавшее ценовое оповещение
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceAlertFired {
+    pub alert: PriceAlert,
+    pub item_info: Option<ItemInfo>,
+    pub price: f64,
+    /// Цена в начале окна (для ChangePct)
+    pub reference_price: Option<f64>,
+    pub change_pct: Option<f64>,
+    pub at: DateTime<Utc>,
+}
+
+/// Почему наблюдение цены отправлено в карантин
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QuarantineReason {
+    /// Сильно отличается от медианы недавней истории цен
+    DeviatesFromHistory { median: f64, ratio: f64 },
+    /// Сильно отличается от remote цены
+    DeviatesFromRemote { remote_price: f64, ratio: f64 },
+}
+
+/// Подозрительный прайсчек, ждёт подтверждения пользователя
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantinedPrice {
+    pub id: String,
+    pub game_id: i64,
+    /// Выбранная по стратегии цена
+    pub price: f64,
+    pub strategy: PriceStrategy,
+    /// Исходные цены лотов (уйдут в crowd upload после подтверждения)
+    pub prices: Vec<f64>,
+    pub currency_id: i64,
+    pub observed_at: DateTime<Utc>,
+    pub reason: QuarantineReason,
+    /// Сколько согласующихся прайсчеков предмета ждут решения (вместе с этим)
+    #[serde(default)]
+    pub pending_checks: usize,
 }
 
 /// Профиль пользователя kripika.com (public.profiles)
@@ -269,3 +1122,235 @@ pub struct UserProfile {
     pub level: Option<i32>,
     pub total_xp: Option<i32>,
 }
+
+/// Формат файла экспорта
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// Что экспортировано (поле `kind` JSON конверта)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportKind {
+    Sessions,
+    Session,
+    Prices,
+}
+
+/// Тип строки детализации сессии
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionItemKind {
+    Drop,
+    ManualDrop,
+    Expense,
+}
+
+/// Строка детализации сессии: дроп, ручной дроп или расход
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionItemLine {
+    pub kind: SessionItemKind,
+    pub game_id: Option<i64>,
+    pub name: String,
+    pub category: Option<String>,
+    pub quantity: i32,
+    /// Цена за единицу (для авто-дропа — на момент завершения сессии, None — нет наблюдений)
+    pub unit_price: Option<f64>,
+    pub total_value: Option<f64>,
+}
+
+/// Строка экспорта кэша цен
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceExportRow {
+    pub game_id: i64,
+    pub name: Option<String>,
+    pub category: Option<String>,
+    pub price: f64,
+    pub updated_at: DateTime<Utc>,
+    pub origin: PriceOrigin,
+    pub is_current_league: bool,
+    pub league_name: Option<String>,
+    pub is_stale: bool,
+    pub confidence: f64,
+}
+
+/// Единица длительности в импортируемом CSV
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DurationUnit {
+    #[default]
+    Seconds,
+    Minutes,
+    Hours,
+}
+
+/// Сопоставление колонок CSV другого трекера полям сессии (значения — имена колонок заголовка)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CsvMapping {
+    /// Разделитель (по умолчанию `,`; при `;` допускается десятичная запятая)
+    #[serde(default)]
+    pub delimiter: Option<char>,
+    /// Формат времени chrono (None — RFC 3339 или `%Y-%m-%d %H:%M[:%S]`); время без зоны считается UTC
+    #[serde(default)]
+    pub time_format: Option<String>,
+    pub started_at: String,
+    #[serde(default)]
+    pub ended_at: Option<String>,
+    #[serde(default)]
+    pub duration: Option<String>,
+    #[serde(default)]
+    pub duration_unit: DurationUnit,
+    #[serde(default)]
+    pub maps_completed: Option<String>,
+    /// Доход до комиссии аукциона
+    #[serde(default)]
+    pub income: Option<String>,
+    /// Доход после комиссии
+    #[serde(default)]
+    pub net_income: Option<String>,
+    #[serde(default)]
+    pub expenses: Option<String>,
+    #[serde(default)]
+    pub profit: Option<String>,
+    #[serde(default)]
+    pub session_id: Option<String>,
+    #[serde(default)]
+    pub preset_id: Option<String>,
+}
+
+/// Источник импорта истории сессий
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImportSource {
+    /// Наш экспорт: JSON конверт (sessions / session) или CSV истории сессий
+    Native,
+    /// CSV другого трекера / таблицы
+    Csv { mapping: Box<CsvMapping> },
+}
+
+/// Что делать с сессиями, которые уже есть в истории
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportConflictPolicy {
+    #[default]
+    Skip,
+    Replace,
+}
+
+/// Почему импортируемая сессия считается дубликатом
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportConflictReason {
+    SameId,
+    SameStartTime,
+}
+
+/// Конфликт импортируемой сессии с историей
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportConflict {
+    /// Номер строки CSV (заголовок — 1) или записи JSON (с 1)
+    pub row: usize,
+    pub session_id: String,
+    pub started_at: DateTime<Utc>,
+    pub existing_id: String,
+    pub reason: ImportConflictReason,
+}
+
+/// Ошибка валидации строки (строка пропускается)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportIssue {
+    pub row: usize,
+    pub message: String,
+}
+
+/// Dry-run импорта: ничего не записано
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportPreview {
+    pub total_rows: usize,
+    /// Сессии, которых нет в истории
+    pub new_sessions: usize,
+    pub conflicts: Vec<ImportConflict>,
+    pub errors: Vec<ImportIssue>,
+    pub first_started_at: Option<DateTime<Utc>>,
+    pub last_started_at: Option<DateTime<Utc>>,
+}
+
+/// Итог импорта
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportResult {
+    pub imported: usize,
+    pub replaced: usize,
+    pub skipped: usize,
+    pub errors: usize,
+}
+
+/// Почему создана резервная копия
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupReason {
+    /// Создана пользователем (не ротируется)
+    Manual,
+    BeforeMigration,
+    BeforeImport,
+    BeforeRestore,
+}
+
+/// Резервная копия данных приложения
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub path: String,
+    pub file_name: String,
+    pub created_at: DateTime<Utc>,
+    pub reason: BackupReason,
+    /// Версия приложения, создавшего копию
+    pub app_version: String,
+    /// Версия формата архива
+    pub format_version: u32,
+    /// Версия схемы SQLite базы в копии (None — базы нет)
+    pub db_schema_version: Option<usize>,
+    pub files: Vec<String>,
+    pub size_bytes: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn session_started(started_at: DateTime<Utc>) -> FarmSessionState {
+        FarmSessionState { started_at: Some(started_at), ..Default::default() }
+    }
+
+    #[test]
+    fn test_nested_pause_counts_once() {
+        let t0 = Utc::now() - Duration::seconds(600);
+        let mut session = session_started(t0);
+
+        session.open_pause(t0 + Duration::seconds(100), PauseKind::Idle);
+        // Повторная пауза внутри открытой не начинает новый интервал
+        session.open_pause(t0 + Duration::seconds(150), PauseKind::Manual);
+        session.close_pause(t0 + Duration::seconds(300));
+        // Возобновление без паузы — ничего не меняет
+        session.close_pause(t0 + Duration::seconds(400));
+
+        assert_eq!(session.pauses.len(), 1);
+        assert_eq!(session.pauses[0].started_at, t0 + Duration::seconds(100));
+        assert_eq!(session.pauses[0].ended_at, Some(t0 + Duration::seconds(300)));
+        assert_eq!(session.active_duration_sec(t0 + Duration::seconds(500)), 300);
+    }
+
+    #[test]
+    fn test_open_pause_stops_the_clock() {
+        let t0 = Utc::now() - Duration::seconds(600);
+        let mut session = session_started(t0);
+        session.open_pause(t0 + Duration::seconds(200), PauseKind::Manual);
+
+        assert_eq!(session.active_duration_sec(t0 + Duration::seconds(200)), 200);
+        assert_eq!(session.active_duration_sec(t0 + Duration::seconds(500)), 200);
+        // Интервал, открытый позже now, не уводит время в минус
+        assert_eq!(session.active_duration_sec(t0 + Duration::seconds(100)), 100);
+    }
+}
@@ -0,0 +1,360 @@
+//! Оценка дропа по кэшу цен
+//!
+//! Единое место, где считается стоимость предметов: базовая валюта, рыночная цена,
+//! исключённые из сессии предметы, пользовательские правила оценки и комиссия аукциона.
+
+use std::collections::{HashMap, HashSet};
+use chrono::{DateTime, Utc};
+
+use crate::freshness::{self, Freshness};
+use crate::persistence::PersistedPriceEntry;
+use crate::types::{
+    AggregatedDrop, IncomeEntry, IncomeLedger, IncomeSource, IncomeSourceTotal, ItemInfo, ManualDropEntry,
+    ValuationRuleKind, ValuationRules,
+};
+
+/// Цена предмета после применения правил
+struct LinePrice<'a> {
+    unit_price: f64,
+    /// Входит ли предмет в стоимость
+    counted: bool,
+    rule: Option<ValuationRuleKind>,
+    /// Рыночная цена, на которой основан расчёт (None — базовая валюта, фикс. цена или нет цены)
+    market: Option<&'a PersistedPriceEntry>,
+    is_base_currency: bool,
+    /// Продаётся через аукцион (платит комиссию)
+    pays_fee: bool,
+}
+
+/// Итог оценки набора дропов
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ValueTotals {
+    /// Стоимость по ценам (до комиссии)
+    pub gross: f64,
+    /// Стоимость после комиссии аукциона
+    pub net: f64,
+    /// Кол-во позиций с устаревшей ценой
+    pub stale_price_lines: i32,
+}
+
+pub(crate) struct Valuation<'a> {
+    items_cache: &'a HashMap<i64, ItemInfo>,
+    prices: &'a HashMap<i64, PersistedPriceEntry>,
+    /// Исключённые пользователем предметы сессии (стоимость 0)
+    excluded: &'a HashSet<i64>,
+    rules: &'a ValuationRules,
+    /// Ставка комиссии аукциона (0.0 - 1.0)
+    fee_rate: f64,
+}
+
+impl<'a> Valuation<'a> {
+    pub(crate) fn new(
+        items_cache: &'a HashMap<i64, ItemInfo>,
+        prices: &'a HashMap<i64, PersistedPriceEntry>,
+        excluded: &'a HashSet<i64>,
+        rules: &'a ValuationRules,
+    ) -> Self {
+        Self { items_cache, prices, excluded, rules, fee_rate: 0.0 }
+    }
+
+    /// Учитывать комиссию аукциона (AppSettings.auction_fee_rate)
+    pub(crate) fn with_fee_rate(mut self, rate: f64) -> Self {
+        self.fee_rate = if rate.is_finite() { rate.clamp(0.0, 1.0) } else { 0.0 };
+        self
+    }
+
+    /// Стоимость строки после комиссии
+    fn net(&self, p: &LinePrice, gross: f64) -> f64 {
+        if p.pays_fee { gross * (1.0 - self.fee_rate) } else { gross }
+    }
+
+    fn price(&self, game_id: i64) -> LinePrice<'a> {
+        let item = self.items_cache.get(&game_id);
+        let is_base_currency = item.map(|i| i.is_base_currency).unwrap_or(false);
+        let excluded = self.excluded.contains(&game_id);
+        // Базовая валюта и оставляемые себе предметы не продаются — комиссии нет
+        let pays_fee = !is_base_currency && !self.rules.kept_items.contains(&game_id);
+
+        // Для базовой валюты цена всегда 1.0 и никогда не устаревает (правила не применяются)
+        if is_base_currency {
+            return LinePrice { unit_price: 1.0, counted: !excluded, rule: None, market: None, is_base_currency, pays_fee };
+        }
+        if self.rules.ignored_items.contains(&game_id) {
+            let market = self.prices.get(&game_id);
+            return LinePrice {
+                unit_price: market.map(|p| p.price).unwrap_or(0.0),
+                counted: false,
+                rule: Some(ValuationRuleKind::Ignored),
+                market,
+                is_base_currency,
+                pays_fee,
+            };
+        }
+        if let Some(fixed) = self.rules.fixed_prices.get(&game_id) {
+            return LinePrice { unit_price: *fixed, counted: !excluded, rule: Some(ValuationRuleKind::FixedPrice), market: None, is_base_currency, pays_fee };
+        }
+
+        let Some(market) = self.prices.get(&game_id) else {
+            return LinePrice { unit_price: 0.0, counted: !excluded, rule: None, market: None, is_base_currency, pays_fee };
+        };
+        let mut unit_price = market.price;
+        let mut rule = None;
+        if let Some(m) = item.and_then(|i| self.rules.category_multipliers.get(&i.category)) {
+            unit_price *= m;
+            rule = Some(ValuationRuleKind::CategoryMultiplier);
+        }
+        let mut counted = !excluded;
+        if self.rules.min_unit_value.is_some_and(|min| unit_price < min) {
+            counted = false;
+            rule = Some(ValuationRuleKind::BelowMinValue);
+        }
+        LinePrice { unit_price, counted, rule, market: Some(market), is_base_currency, pays_fee }
+    }
+
+    /// Свежесть рыночной цены по политике TTL
+    fn freshness(&self, game_id: i64, market: &PersistedPriceEntry) -> Freshness {
+        freshness::assess(market, game_id, self.items_cache.get(&game_id), &self.rules.freshness, Utc::now())
+    }
+
+    /// Цена за единицу для расчётов (базовая валюта = 1.0, без цены или не учитывается = 0.0)
+    pub(crate) fn unit_price(&self, game_id: i64) -> f64 {
+        let p = self.price(game_id);
+        if p.counted { p.unit_price } else { 0.0 }
+    }
+
+    /// Стоимость набора дропов до и после комиссии
+    pub(crate) fn value(&self, drops: &HashMap<i64, i32>) -> ValueTotals {
+        let mut totals = ValueTotals::default();
+        for (game_id, qty) in drops {
+            let p = self.price(*game_id);
+            if !p.counted {
+                continue;
+            }
+            // Доход считаем всегда (даже по устаревшим ценам), но помечаем что часть цен старые,
+            // чтобы UI мог попросить пользователя обновить прайсчек.
+            let gross = p.unit_price * (*qty as f64);
+            totals.gross += gross;
+            totals.net += self.net(&p, gross);
+            if p.market.is_some_and(|m| self.freshness(*game_id, m).is_stale) {
+                totals.stale_price_lines += 1;
+            }
+        }
+        totals
+    }
+
+    /// Строки дропа с ценами, отсортированные по стоимости
+    pub(crate) fn lines(&self, drops: &HashMap<i64, i32>) -> Vec<AggregatedDrop> {
+        let mut drops: Vec<AggregatedDrop> = drops.iter().map(|(game_id, qty)| {
+            let p = self.price(*game_id);
+
+            let fresh = p.market.map(|m| self.freshness(*game_id, m));
+
+            let (price_updated_at, price_is_stale, is_previous_season, league_name): (Option<DateTime<Utc>>, bool, bool, Option<String>) =
+                match (p.market, fresh) {
+                    (Some(m), Some(f)) => (
+                        Some(m.updated_at),
+                        f.is_stale,
+                        !m.is_current_league,  // Если НЕ текущая лига = предыдущий сезон
+                        m.league_name.clone(),
+                    ),
+                    _ if p.is_base_currency => (Some(Utc::now()), false, false, None),
+                    _ => (None, false, false, None),
+                };
+            // Базовая валюта и фикс. цена — полное доверие, без цены — нулевое
+            let (price_source, price_confidence) = match fresh {
+                Some(f) => (Some(f.origin), f.confidence),
+                None if p.is_base_currency || p.rule == Some(ValuationRuleKind::FixedPrice) => (None, 1.0),
+                None => (None, 0.0),
+            };
+            // Неучитываемый предмет показываем с ценой, но в стоимость не считаем
+            let total_value = if p.counted { p.unit_price * (*qty as f64) } else { 0.0 };
+            let net_value = self.net(&p, total_value);
+
+            AggregatedDrop {
+                game_id: *game_id,
+                item_info: self.items_cache.get(game_id).cloned(),
+                quantity: *qty,
+                total_value,
+                net_value,
+                unit_price: p.unit_price,
+                price_updated_at,
+                price_is_stale,
+                is_previous_season,
+                league_name,
+                is_excluded: self.excluded.contains(game_id),
+                valuation_rule: p.rule,
+                price_source,
+                price_confidence,
+            }
+        }).collect();
+
+        // Сортируем по стоимости (от большей к меньшей)
+        drops.sort_by(|a, b| b.total_value.partial_cmp(&a.total_value).unwrap_or(std::cmp::Ordering::Equal));
+
+        drops
+    }
+
+    /// Журнал дохода: авто-дроп по ценам и правилам, ручной дроп по введённой цене.
+    /// Комиссия для ручного дропа — как для авто-дропа (кроме базовой валюты и оставляемых предметов).
+    pub(crate) fn ledger(&self, drops: &HashMap<i64, i32>, manual_drops: &[ManualDropEntry]) -> IncomeLedger {
+        let mut entries: Vec<IncomeEntry> = self.lines(drops).into_iter().map(|d| IncomeEntry {
+            source: IncomeSource::AutoDrop,
+            entry_id: None,
+            game_id: Some(d.game_id),
+            name: d.item_info.map(|i| i.name).unwrap_or_else(|| format!("ID: {}", d.game_id)),
+            quantity: d.quantity,
+            unit_price: d.unit_price,
+            gross_value: d.total_value,
+            net_value: d.net_value,
+        }).collect();
+
+        for m in manual_drops {
+            let gross = m.price * m.quantity as f64;
+            let pays_fee = match m.game_id {
+                Some(id) => !self.items_cache.get(&id).map(|i| i.is_base_currency).unwrap_or(false)
+                    && !self.rules.kept_items.contains(&id),
+                None => true,
+            };
+            entries.push(IncomeEntry {
+                source: IncomeSource::ManualDrop,
+                entry_id: Some(m.id.clone()),
+                game_id: m.game_id,
+                name: m.name.clone(),
+                quantity: m.quantity,
+                unit_price: m.price,
+                gross_value: gross,
+                net_value: if pays_fee { gross * (1.0 - self.fee_rate) } else { gross },
+            });
+        }
+
+        let mut by_source: Vec<IncomeSourceTotal> = Vec::new();
+        for e in &entries {
+            match by_source.iter_mut().find(|t| t.source == e.source) {
+                Some(t) => {
+                    t.quantity += e.quantity;
+                    t.gross_value += e.gross_value;
+                    t.net_value += e.net_value;
+                }
+                None => by_source.push(IncomeSourceTotal {
+                    source: e.source,
+                    quantity: e.quantity,
+                    gross_value: e.gross_value,
+                    net_value: e.net_value,
+                }),
+            }
+        }
+
+        IncomeLedger {
+            gross_total: by_source.iter().map(|t| t.gross_value).sum(),
+            net_total: by_source.iter().map(|t| t.net_value).sum(),
+            stale_price_lines: self.value(drops).stale_price_lines,
+            entries,
+            by_source,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(game_id: i64, category: &str, is_base_currency: bool) -> (i64, ItemInfo) {
+        (game_id, ItemInfo {
+            game_id,
+            name: format!("item {}", game_id),
+            name_en: None,
+            name_ru: None,
+            name_cn: None,
+            category: category.into(),
+            icon_url: None,
+            is_base_currency,
+        })
+    }
+
+    fn price(price: f64) -> PersistedPriceEntry {
+        PersistedPriceEntry {
+            price,
+            updated_at: Utc::now(),
+            is_current_league: true,
+            league_name: None,
+            strategy: None,
+            source: None,
+        }
+    }
+
+    /// 1 — базовая валюта, 2..=6 — материалы с множителем x2, min 30 за единицу
+    fn fixture() -> (HashMap<i64, ItemInfo>, HashMap<i64, PersistedPriceEntry>, ValuationRules) {
+        let items = [item(1, "currency", true), item(2, "mat", false), item(3, "mat", false),
+            item(4, "mat", false), item(5, "mat", false), item(6, "mat", false)].into_iter().collect();
+        let prices = [(2, price(10.0)), (3, price(10.0)), (4, price(10.0)), (5, price(40.0)), (6, price(40.0))]
+            .into_iter().collect();
+        let rules = ValuationRules {
+            ignored_items: [1, 2].into_iter().collect(),
+            fixed_prices: [(2, 50.0), (3, 5.0)].into_iter().collect(),
+            min_unit_value: Some(30.0),
+            category_multipliers: [("mat".to_string(), 2.0)].into_iter().collect(),
+            kept_items: [6].into_iter().collect(),
+            ..Default::default()
+        };
+        (items, prices, rules)
+    }
+
+    fn rule(lines: &[AggregatedDrop], game_id: i64) -> Option<ValuationRuleKind> {
+        lines.iter().find(|d| d.game_id == game_id).unwrap().valuation_rule
+    }
+
+    #[test]
+    fn test_rule_precedence() {
+        let (items, prices, rules) = fixture();
+        let excluded = HashSet::new();
+        let v = Valuation::new(&items, &prices, &excluded, &rules);
+        let drops: HashMap<i64, i32> = (1..=6).map(|id| (id, 1)).collect();
+        let lines = v.lines(&drops);
+
+        // Базовая валюта — правила не действуют
+        assert_eq!(v.unit_price(1), 1.0);
+        assert_eq!(rule(&lines, 1), None);
+        // Игнор сильнее фиксированной цены
+        assert_eq!(v.unit_price(2), 0.0);
+        assert_eq!(rule(&lines, 2), Some(ValuationRuleKind::Ignored));
+        // Фиксированная цена сильнее минимума и множителя
+        assert_eq!(v.unit_price(3), 5.0);
+        assert_eq!(rule(&lines, 3), Some(ValuationRuleKind::FixedPrice));
+        // Минимум проверяется по цене с множителем: 10 * 2 < 30
+        assert_eq!(v.unit_price(4), 0.0);
+        assert_eq!(rule(&lines, 4), Some(ValuationRuleKind::BelowMinValue));
+        assert_eq!(v.unit_price(5), 80.0);
+        assert_eq!(rule(&lines, 5), Some(ValuationRuleKind::CategoryMultiplier));
+
+        let totals = v.value(&drops);
+        assert_eq!(totals.gross, 1.0 + 5.0 + 80.0 + 80.0);
+    }
+
+    #[test]
+    fn test_fee_kept_items_and_exclusions() {
+        let (items, prices, rules) = fixture();
+        let excluded: HashSet<i64> = [3].into_iter().collect();
+        let v = Valuation::new(&items, &prices, &excluded, &rules).with_fee_rate(0.1);
+        let drops: HashMap<i64, i32> = [(1, 100), (3, 1), (5, 2), (6, 1)].into_iter().collect();
+
+        // Комиссия только с продаваемого: не с базовой валюты и не с оставляемых предметов
+        let totals = v.value(&drops);
+        assert_eq!(totals.gross, 100.0 + 160.0 + 80.0);
+        assert!((totals.net - (100.0 + 144.0 + 80.0)).abs() < 1e-9);
+
+        let lines = v.lines(&drops);
+        let excluded_line = lines.iter().find(|d| d.game_id == 3).unwrap();
+        assert!(excluded_line.is_excluded);
+        assert_eq!(excluded_line.total_value, 0.0);
+
+        let manual = [
+            ManualDropEntry { id: "m1".into(), game_id: Some(6), name: "kept".into(), name_ru: None, quantity: 1, price: 10.0 },
+            ManualDropEntry { id: "m2".into(), game_id: None, name: "other".into(), name_ru: None, quantity: 2, price: 10.0 },
+        ];
+        let ledger = v.ledger(&drops, &manual);
+        assert_eq!(ledger.gross_total, totals.gross + 30.0);
+        assert!((ledger.net_total - (totals.net + 10.0 + 18.0)).abs() < 1e-9);
+        let manual_total = ledger.by_source.iter().find(|t| t.source == IncomeSource::ManualDrop).unwrap();
+        assert_eq!(manual_total.quantity, 3);
+    }
+}